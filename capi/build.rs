@@ -0,0 +1,13 @@
+// Generates chip8.h from src/lib.rs into OUT_DIR, see cbindgen.toml and `make header`
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out = std::env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir))
+        .expect("cbindgen.toml is valid");
+    cbindgen::generate_with_config(&dir, config)
+        .expect("src/lib.rs can be turned into a header")
+        .write_to_file(format!("{}/chip8.h", out));
+}
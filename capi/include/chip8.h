@@ -0,0 +1,138 @@
+/*
+ * CHIP-8 emulator
+ *
+ * Ownership: chip8_new returns a machine the caller owns and must pass to chip8_free exactly
+ * once. The pointer chip8_framebuffer returns is borrowed from the machine and is only valid
+ * until the next call given the same machine. chip8_load_rom copies the ROM.
+ *
+ * No panic crosses into C: functions return CHIP8_STATUS_PANICKED instead, after which the
+ * machine should only be freed. A machine must not be used from two threads at once.
+ */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from src/lib.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Display width in pixels
+ */
+#define CHIP8_WIDTH 64
+
+/**
+ * Display height in pixels
+ */
+#define CHIP8_HEIGHT 32
+
+/**
+ * What a call did
+ */
+typedef enum Chip8Status {
+  CHIP8_STATUS_OK = 0,
+  /**
+   * A pointer argument was NULL
+   */
+  CHIP8_STATUS_NULL_POINTER,
+  /**
+   * The ROM doesn't fit in memory, which holds 3584 bytes of program
+   */
+  CHIP8_STATUS_ROM_TOO_LARGE,
+  /**
+   * The key is not 0 to 15
+   */
+  CHIP8_STATUS_BAD_KEY,
+  /**
+   * The machine stopped on a stack fault, load a ROM to start again
+   */
+  CHIP8_STATUS_FAULTED,
+  /**
+   * Something went wrong inside the emulator, the machine should only be freed
+   */
+  CHIP8_STATUS_PANICKED,
+} Chip8Status;
+
+/**
+ * A machine and the scheduler that runs it, opaque to C
+ */
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a machine with no ROM loaded, free it with `chip8_free`
+ *
+ * Returns NULL if the machine couldn't be created.
+ */
+struct Chip8 *chip8_new(void);
+
+/**
+ * Frees a machine from `chip8_new`, doing nothing for NULL
+ *
+ * # Safety
+ *
+ * `machine` must come from `chip8_new` and not have been freed already.
+ */
+void chip8_free(struct Chip8 *machine);
+
+/**
+ * Resets the machine and loads `len` bytes of ROM, with the quirks and speed the ROM database
+ * recommends for it
+ *
+ * # Safety
+ *
+ * `machine` must come from `chip8_new`, and `rom` must point to `len` readable bytes.
+ */
+enum Chip8Status chip8_load_rom(struct Chip8 *machine, const uint8_t *rom, size_t len);
+
+/**
+ * Runs one instruction, ticking the timers if it ends a frame
+ *
+ * # Safety
+ *
+ * `machine` must come from `chip8_new`.
+ */
+enum Chip8Status chip8_step(struct Chip8 *machine);
+
+/**
+ * Runs the rest of the current frame, one 60th of a second
+ *
+ * # Safety
+ *
+ * `machine` must come from `chip8_new`.
+ */
+enum Chip8Status chip8_run_frame(struct Chip8 *machine);
+
+/**
+ * Presses or releases one of the 16 keys, 0 to 15
+ *
+ * # Safety
+ *
+ * `machine` must come from `chip8_new`.
+ */
+enum Chip8Status chip8_set_key(struct Chip8 *machine, uint8_t key, bool pressed);
+
+/**
+ * The display as `CHIP8_WIDTH * CHIP8_HEIGHT` bytes, row by row, 1 for a lit pixel and 0 for an
+ * unlit one, or NULL if `machine` is NULL
+ *
+ * The bytes belong to the machine and are only valid until its next call.
+ *
+ * # Safety
+ *
+ * `machine` must come from `chip8_new`.
+ */
+const uint8_t *chip8_framebuffer(const struct Chip8 *machine);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CHIP8_H */
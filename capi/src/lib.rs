@@ -0,0 +1,181 @@
+use chip8_rust::chip::{self, HEIGHT, WIDTH};
+use chip8_rust::romdb;
+use chip8_rust::scheduler::Scheduler;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+/// Display width in pixels
+pub const CHIP8_WIDTH: usize = 64;
+
+/// Display height in pixels
+pub const CHIP8_HEIGHT: usize = 32;
+
+// The header needs literals, these keep them in step with the emulator
+const _: () = assert!(CHIP8_WIDTH == WIDTH && CHIP8_HEIGHT == HEIGHT);
+
+/// What a call did
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Status {
+    Ok = 0,
+
+    /// A pointer argument was NULL
+    NullPointer,
+
+    /// The ROM doesn't fit in memory, which holds 3584 bytes of program
+    RomTooLarge,
+
+    /// The key is not 0 to 15
+    BadKey,
+
+    /// The machine stopped on a stack fault, load a ROM to start again
+    Faulted,
+
+    /// Something went wrong inside the emulator, the machine should only be freed
+    Panicked,
+}
+
+/// A machine and the scheduler that runs it, opaque to C
+pub struct Machine {
+    chip8: chip::Chip8,
+    scheduler: Scheduler,
+}
+
+impl Machine {
+    fn status(&self) -> Chip8Status {
+        if self.chip8.fault.is_some() {
+            Chip8Status::Faulted
+        } else {
+            Chip8Status::Ok
+        }
+    }
+}
+
+/// Runs `f` on the machine, turning NULL and panics into statuses
+fn with_machine(machine: *mut Machine, f: impl FnOnce(&mut Machine) -> Chip8Status) -> Chip8Status {
+    // SAFETY: callers pass a pointer from chip8_new that hasn't been freed, or NULL
+    let Some(machine) = (unsafe { machine.as_mut() }) else {
+        return Chip8Status::NullPointer;
+    };
+    panic::catch_unwind(AssertUnwindSafe(|| f(machine))).unwrap_or(Chip8Status::Panicked)
+}
+
+/// Creates a machine with no ROM loaded, free it with `chip8_free`
+///
+/// Returns NULL if the machine couldn't be created.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Machine {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(Machine {
+            chip8: chip::Chip8::new(),
+            scheduler: Scheduler::default(),
+        }))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Frees a machine from `chip8_new`, doing nothing for NULL
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(machine: *mut Machine) {
+    if !machine.is_null() {
+        // SAFETY: see above, the box is reclaimed exactly once
+        let machine = unsafe { Box::from_raw(machine) };
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(machine)));
+    }
+}
+
+/// Resets the machine and loads `len` bytes of ROM, with the quirks and speed the ROM database
+/// recommends for it
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`, and `rom` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    machine: *mut Machine,
+    rom: *const u8,
+    len: usize,
+) -> Chip8Status {
+    if rom.is_null() {
+        return Chip8Status::NullPointer;
+    }
+    // SAFETY: see above
+    let rom = unsafe { slice::from_raw_parts(rom, len) };
+
+    with_machine(machine, |machine| {
+        if machine.chip8.load_bytes(rom).is_err() {
+            return Chip8Status::RomTooLarge;
+        }
+        let profile = romdb::bundled().profile(rom);
+        machine.chip8.quirks = profile.quirks;
+        machine.scheduler = Scheduler::new(profile.cycles_per_frame);
+        Chip8Status::Ok
+    })
+}
+
+/// Runs one instruction, ticking the timers if it ends a frame
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(machine: *mut Machine) -> Chip8Status {
+    with_machine(machine, |machine| {
+        machine.scheduler.step_instruction(&mut machine.chip8);
+        machine.status()
+    })
+}
+
+/// Runs the rest of the current frame, one 60th of a second
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(machine: *mut Machine) -> Chip8Status {
+    with_machine(machine, |machine| {
+        machine.scheduler.run_frame(&mut machine.chip8);
+        machine.status()
+    })
+}
+
+/// Presses or releases one of the 16 keys, 0 to 15
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(
+    machine: *mut Machine,
+    key: u8,
+    pressed: bool,
+) -> Chip8Status {
+    if key >= 16 {
+        return Chip8Status::BadKey;
+    }
+    with_machine(machine, |machine| {
+        machine.chip8.set_key(key as usize, pressed);
+        Chip8Status::Ok
+    })
+}
+
+/// The display as `CHIP8_WIDTH * CHIP8_HEIGHT` bytes, row by row, 1 for a lit pixel and 0 for an
+/// unlit one, or NULL if `machine` is NULL
+///
+/// The bytes belong to the machine and are only valid until its next call.
+///
+/// # Safety
+///
+/// `machine` must come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(machine: *const Machine) -> *const u8 {
+    // SAFETY: see above
+    match unsafe { machine.as_ref() } {
+        Some(machine) => machine.chip8.display.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
@@ -0,0 +1,157 @@
+/* Exercises the C API against pong.rom, run with `make test` */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "chip8.h"
+
+/* Program space, memory after 0x200 */
+#define ROM_ROOM (4096 - 0x200)
+
+static int failures = 0;
+
+#define CHECK(condition)                                                   \
+    do {                                                                   \
+        if (!(condition)) {                                                \
+            fprintf(stderr, "%s:%d: failed: %s\n", __FILE__, __LINE__,    \
+                    #condition);                                           \
+            failures++;                                                    \
+        }                                                                  \
+    } while (0)
+
+static uint8_t *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        return NULL;
+    }
+
+    uint8_t *bytes = malloc(ROM_ROOM + 1);
+    *len = fread(bytes, 1, ROM_ROOM + 1, file);
+    fclose(file);
+    return bytes;
+}
+
+static size_t lit_pixels(const Chip8 *machine) {
+    const uint8_t *display = chip8_framebuffer(machine);
+    size_t lit = 0;
+    for (size_t i = 0; i < CHIP8_WIDTH * CHIP8_HEIGHT; i++) {
+        CHECK(display[i] <= 1);
+        lit += display[i];
+    }
+    return lit;
+}
+
+static int paddle_top(const Chip8 *machine) {
+    const uint8_t *display = chip8_framebuffer(machine);
+    for (int y = 0; y < CHIP8_HEIGHT; y++) {
+        if (display[y * CHIP8_WIDTH + 2]) {
+            return y;
+        }
+    }
+    return -1;
+}
+
+static void test_pong(const uint8_t *rom, size_t len) {
+    Chip8 *machine = chip8_new();
+    CHECK(machine != NULL);
+    CHECK(chip8_load_rom(machine, rom, len) == CHIP8_STATUS_OK);
+    CHECK(lit_pixels(machine) == 0);
+
+    CHECK(chip8_step(machine) == CHIP8_STATUS_OK);
+    for (int frame = 0; frame < 100; frame++) {
+        CHECK(chip8_run_frame(machine) == CHIP8_STATUS_OK);
+    }
+    CHECK(lit_pixels(machine) > 0);
+
+    /* Key 1 moves the left paddle up once the game has started */
+    int top = paddle_top(machine);
+    CHECK(chip8_set_key(machine, 1, true) == CHIP8_STATUS_OK);
+    for (int frame = 0; frame < 10; frame++) {
+        chip8_run_frame(machine);
+    }
+    CHECK(chip8_set_key(machine, 1, false) == CHIP8_STATUS_OK);
+    CHECK(paddle_top(machine) >= 0 && paddle_top(machine) < top);
+
+    /* Loading again starts from scratch */
+    CHECK(chip8_load_rom(machine, rom, len) == CHIP8_STATUS_OK);
+    CHECK(lit_pixels(machine) == 0);
+
+    chip8_free(machine);
+}
+
+static void test_bad_arguments(void) {
+    static uint8_t big[ROM_ROOM + 1];
+    Chip8 *machine = chip8_new();
+
+    CHECK(chip8_load_rom(machine, big, sizeof big) == CHIP8_STATUS_ROM_TOO_LARGE);
+    CHECK(chip8_load_rom(machine, NULL, 0) == CHIP8_STATUS_NULL_POINTER);
+    CHECK(chip8_set_key(machine, 16, true) == CHIP8_STATUS_BAD_KEY);
+
+    CHECK(chip8_load_rom(NULL, big, 2) == CHIP8_STATUS_NULL_POINTER);
+    CHECK(chip8_step(NULL) == CHIP8_STATUS_NULL_POINTER);
+    CHECK(chip8_run_frame(NULL) == CHIP8_STATUS_NULL_POINTER);
+    CHECK(chip8_set_key(NULL, 0, true) == CHIP8_STATUS_NULL_POINTER);
+    CHECK(chip8_framebuffer(NULL) == NULL);
+
+    chip8_free(machine);
+    chip8_free(NULL);
+}
+
+/* Programs that used to panic inside the emulator */
+static void test_no_panics(void) {
+    static const uint8_t underflow[] = {0x00, 0xEE};
+    static const uint8_t overflow[] = {0x22, 0x00};
+    static const uint8_t edges[] = {
+        0x60, 0x05, 0x61, 0x03, 0x80, 0x17, /* 8XY7 with VX > VY */
+        0x60, 0xFF, 0xF0, 0x29,             /* FX29 past the font */
+        0xE0, 0x9E, 0xE0, 0xA1, 0x00, 0xE0, /* EX9E/EXA1 with VX > 15 */
+        0xAF, 0xFF, 0xF0, 0x33,             /* FX33 at the end of memory */
+        0xAF, 0xFF, 0xFF, 0x65,             /* FX65 past the end */
+        0xAF, 0xFF, 0xFF, 0x1E,             /* FX1E */
+        0x60, 0x1F, 0x61, 0xFF,             /* FX55 past the end, leaving */
+        0xAF, 0xFF, 0xF1, 0x55,             /* 1FFF split across FFF and 000 */
+        0x1F, 0xFF,                         /* which jumps to itself */
+    };
+
+    Chip8 *machine = chip8_new();
+
+    CHECK(chip8_load_rom(machine, underflow, sizeof underflow) == CHIP8_STATUS_OK);
+    CHECK(chip8_step(machine) == CHIP8_STATUS_FAULTED);
+    CHECK(chip8_run_frame(machine) == CHIP8_STATUS_FAULTED);
+
+    CHECK(chip8_load_rom(machine, overflow, sizeof overflow) == CHIP8_STATUS_OK);
+    for (int frame = 0; frame < 2; frame++) {
+        chip8_run_frame(machine);
+    }
+    CHECK(chip8_run_frame(machine) == CHIP8_STATUS_FAULTED);
+
+    CHECK(chip8_load_rom(machine, edges, sizeof edges) == CHIP8_STATUS_OK);
+    for (int frame = 0; frame < 60; frame++) {
+        Chip8Status status = chip8_run_frame(machine);
+        CHECK(status == CHIP8_STATUS_OK);
+    }
+
+    chip8_free(machine);
+}
+
+int main(int argc, char **argv) {
+    const char *path = argc > 1 ? argv[1] : "../roms/pong.rom";
+    size_t len = 0;
+    uint8_t *rom = read_file(path, &len);
+    if (!rom) {
+        fprintf(stderr, "failed to read %s\n", path);
+        return 1;
+    }
+
+    test_pong(rom, len);
+    test_bad_arguments();
+    test_no_panics();
+    free(rom);
+
+    if (failures) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,545 @@
+mod libretro;
+
+use chip8_rust::cheats::{Location, Patch};
+use chip8_rust::chip::{Chip8, HEIGHT, STATE_SIZE, WIDTH};
+use chip8_rust::keymap::Keymap;
+use chip8_rust::palette::Palette;
+use chip8_rust::romdb;
+use chip8_rust::scheduler::Scheduler;
+use libretro::*;
+use std::collections::BTreeMap;
+use std::ffi::{c_char, c_uint, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{fs, ptr, slice};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+///Stereo samples in one 60Hz frame
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+
+///Frequency and loudness of the square wave played while the sound timer runs
+const BEEP_FREQUENCY: u32 = 440;
+const BEEP_VOLUME: i16 = 6000;
+
+///CHIP-8 key each RetroPad button presses unless the ROM database says otherwise, by button id
+///
+///Every key is on some button, the d-pad is on 2 4 6 8 and A on 5 as most games expect
+const BUTTONS: [u8; 16] = [
+    0x0, // B
+    0x3, // Y
+    0xE, // Select
+    0xF, // Start
+    0x2, // Up
+    0x8, // Down
+    0x4, // Left
+    0x6, // Right
+    0x5, // A
+    0x1, // X
+    0x7, // L
+    0x9, // R
+    0xA, // L2
+    0xB, // R2
+    0xC, // L3
+    0xD, // R3
+];
+
+///Frontend callbacks and the running game
+struct State {
+    environment: Option<retro_environment_t>,
+    video_refresh: Option<retro_video_refresh_t>,
+    audio_sample_batch: Option<retro_audio_sample_batch_t>,
+    input_poll: Option<retro_input_poll_t>,
+    input_state: Option<retro_input_state_t>,
+    core: Option<Core>,
+}
+
+///A loaded game
+struct Core {
+    chip8: Chip8,
+    scheduler: Scheduler,
+    rom: Vec<u8>,
+    palette: Palette,
+
+    ///CHIP-8 key each RetroPad button presses, see `BUTTONS`
+    buttons: [u8; 16],
+
+    ///Cheat codes the frontend set, by index
+    cheats: BTreeMap<c_uint, Vec<Patch>>,
+
+    ///Pixels handed to the frontend as XRGB8888
+    frame: Vec<u32>,
+
+    ///Interleaved stereo samples for one frame
+    audio: Vec<i16>,
+
+    ///Samples into the beep, so it carries on smoothly from frame to frame
+    phase: u32,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+    core: None,
+});
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs an entry point, returning `fallback` instead of unwinding into the frontend if it panics
+///
+/// The core is dropped after a panic, as if the game had been unloaded
+fn guard<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        state().core = None;
+        fallback
+    })
+}
+
+impl Core {
+    /// A core running `rom`, None if it doesn't fit in memory
+    fn new(rom: Vec<u8>) -> Option<Self> {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(&rom).ok()?;
+
+        let mut core = Core {
+            chip8,
+            scheduler: Scheduler::default(),
+            rom,
+            palette: Palette::default(),
+            buttons: BUTTONS,
+            cheats: BTreeMap::new(),
+            frame: vec![0; WIDTH * HEIGHT],
+            audio: vec![0; SAMPLES_PER_FRAME * 2],
+            phase: 0,
+        };
+        core.reset();
+        Some(core)
+    }
+
+    /// Starts the ROM from scratch with the settings the ROM database recommends
+    fn reset(&mut self) {
+        let profile = romdb::bundled().profile(&self.rom);
+
+        self.chip8
+            .load_bytes(&self.rom)
+            .expect("the ROM fit when the core was made");
+        self.chip8.quirks = profile.quirks;
+        self.scheduler = Scheduler::new(profile.cycles_per_frame);
+        self.scheduler.patches = self.cheats.values().flatten().copied().collect();
+        self.palette = profile.palette.unwrap_or_default();
+
+        // Put the database's controls on the matching buttons, swapping so every key stays on one
+        self.buttons = BUTTONS;
+        for (control, key) in &profile.keys {
+            let button = match control.as_str() {
+                "up" => RETRO_DEVICE_ID_JOYPAD_UP,
+                "down" => RETRO_DEVICE_ID_JOYPAD_DOWN,
+                "left" => RETRO_DEVICE_ID_JOYPAD_LEFT,
+                "right" => RETRO_DEVICE_ID_JOYPAD_RIGHT,
+                "a" => RETRO_DEVICE_ID_JOYPAD_A,
+                "b" => RETRO_DEVICE_ID_JOYPAD_B,
+                _ => continue,
+            } as usize;
+            if let Some(other) = self.buttons.iter().position(|k| k == key) {
+                self.buttons.swap(button, other);
+            }
+        }
+        self.phase = 0;
+    }
+
+    fn run(&mut self, input_state: Option<retro_input_state_t>) {
+        let keymap = Keymap::default();
+        for key in 0..16 {
+            let pressed = input_state.is_some_and(|input_state| {
+                // SAFETY: the frontend's callback, called as libretro.h says
+                let button = |id: usize| unsafe {
+                    input_state(0, RETRO_DEVICE_JOYPAD, 0, id as c_uint) != 0
+                };
+                let keyboard = unsafe {
+                    input_state(0, RETRO_DEVICE_KEYBOARD, 0, keymap.host_key(key) as c_uint) != 0
+                };
+                keyboard || (0..16).any(|id| self.buttons[id] as usize == key && button(id))
+            });
+            self.chip8.set_key(key, pressed);
+        }
+
+        self.scheduler.run_frame(&mut self.chip8);
+
+        for (pixel, lit) in self.frame.iter_mut().zip(&self.chip8.display) {
+            let [r, g, b] = if *lit != 0 {
+                self.palette.on
+            } else {
+                self.palette.off
+            };
+            *pixel = u32::from_be_bytes([0, r, g, b]);
+        }
+
+        let beeping = self.chip8.sound_timer > 0;
+        let half_period = SAMPLE_RATE / BEEP_FREQUENCY / 2;
+        for sample in self.audio.chunks_mut(2) {
+            let value = match beeping {
+                true if (self.phase / half_period) & 1 == 0 => BEEP_VOLUME,
+                true => -BEEP_VOLUME,
+                false => 0,
+            };
+            sample.fill(value);
+            self.phase = if beeping { self.phase + 1 } else { 0 };
+        }
+    }
+
+    /// The pressable keys for RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS
+    fn input_descriptors(&self) -> Vec<(c_uint, CString)> {
+        (0..16)
+            .map(|id| {
+                let description = format!("Key {:X}", self.buttons[id]);
+                (id as c_uint, CString::new(description).unwrap())
+            })
+            .collect()
+    }
+}
+
+/// Parses a cheat code, `LOCATION VALUE` or `LOCATION:VALUE` as in the cheat file, with several
+/// joined by `+`
+fn parse_cheat(code: &str) -> Option<Vec<Patch>> {
+    code.split('+')
+        .map(|patch| {
+            let (location, value) = patch.trim().split_once([' ', ':'])?;
+            Some(Patch {
+                location: Location::parse(location.trim())?,
+                value: u8::from_str_radix(value.trim().trim_start_matches("0x"), 16).ok()?,
+            })
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info) {
+    // SAFETY: see above, the strings are static
+    unsafe {
+        *info = retro_system_info {
+            library_name: c"CHIP-8".as_ptr(),
+            library_version: c"0.1.0".as_ptr(),
+            valid_extensions: c"ch8|c8|rom|sc8|xo8".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        };
+    }
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info) {
+    // SAFETY: see above
+    unsafe {
+        *info = retro_system_av_info {
+            geometry: retro_game_geometry {
+                base_width: WIDTH as c_uint,
+                base_height: HEIGHT as c_uint,
+                max_width: WIDTH as c_uint,
+                max_height: HEIGHT as c_uint,
+                aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+            },
+            timing: retro_system_timing {
+                fps: 60.0,
+                sample_rate: SAMPLE_RATE as f64,
+            },
+        };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: retro_environment_t) {
+    state().environment = Some(environment);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: retro_video_refresh_t) {
+    state().video_refresh = Some(video_refresh);
+}
+
+/// The beep goes through `retro_set_audio_sample_batch` instead
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: retro_audio_sample_t) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: retro_audio_sample_batch_t) {
+    state().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: retro_input_poll_t) {
+    state().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: retro_input_state_t) {
+    state().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    guard((), || {
+        state().core = None;
+    })
+}
+
+/// # Safety
+///
+/// `game` must be NULL or point to a `retro_game_info` whose `data` holds `size` bytes or whose
+/// `path` is a NUL terminated path.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const retro_game_info) -> bool {
+    guard(false, || {
+        // SAFETY: see above
+        let Some(game) = (unsafe { game.as_ref() }) else {
+            return false;
+        };
+        let rom = if !game.data.is_null() {
+            // SAFETY: see above
+            unsafe { slice::from_raw_parts(game.data as *const u8, game.size) }.to_vec()
+        } else if !game.path.is_null() {
+            // SAFETY: see above
+            let path = unsafe { CStr::from_ptr(game.path) };
+            match fs::read(&*path.to_string_lossy()) {
+                Ok(rom) => rom,
+                Err(_) => return false,
+            }
+        } else {
+            return false;
+        };
+
+        let mut state = state();
+        let Some(environment) = state.environment else {
+            return false;
+        };
+
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        // SAFETY: the frontend's callback with the data libretro.h documents for the command
+        if !unsafe {
+            environment(
+                RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+                &mut format as *mut c_uint as *mut c_void,
+            )
+        } {
+            return false;
+        }
+
+        let Some(core) = Core::new(rom) else {
+            return false;
+        };
+        let descriptions = core.input_descriptors();
+        let mut descriptors: Vec<retro_input_descriptor> = descriptions
+            .iter()
+            .map(|(id, description)| retro_input_descriptor {
+                port: 0,
+                device: RETRO_DEVICE_JOYPAD,
+                index: 0,
+                id: *id,
+                description: description.as_ptr(),
+            })
+            .collect();
+        descriptors.push(retro_input_descriptor {
+            port: 0,
+            device: 0,
+            index: 0,
+            id: 0,
+            description: ptr::null(),
+        });
+        // SAFETY: as above, the frontend copies the descriptors before returning
+        unsafe {
+            environment(
+                RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+                descriptors.as_mut_ptr() as *mut c_void,
+            );
+        }
+
+        state.core = Some(core);
+        true
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const retro_game_info,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    guard((), || {
+        state().core = None;
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    guard((), || {
+        if let Some(core) = &mut state().core {
+            core.reset();
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    guard((), || {
+        let mut state = state();
+        let State {
+            video_refresh,
+            audio_sample_batch,
+            input_poll,
+            input_state,
+            core: Some(core),
+            ..
+        } = &mut *state
+        else {
+            return;
+        };
+
+        if let Some(input_poll) = input_poll {
+            // SAFETY: the frontend's callback
+            unsafe { input_poll() };
+        }
+        core.run(*input_state);
+
+        if let Some(video_refresh) = video_refresh {
+            // SAFETY: the frontend's callback, the frame outlives the call
+            unsafe {
+                video_refresh(
+                    core.frame.as_ptr() as *const c_void,
+                    WIDTH as c_uint,
+                    HEIGHT as c_uint,
+                    WIDTH * 4,
+                )
+            };
+        }
+        if let Some(audio_sample_batch) = audio_sample_batch {
+            // SAFETY: as above
+            unsafe { audio_sample_batch(core.audio.as_ptr(), SAMPLES_PER_FRAME) };
+        }
+    })
+}
+
+/// Save states are the same size for every game, which netplay and rewinding rely on
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    guard(false, || {
+        let Some(core) = &state().core else {
+            return false;
+        };
+        let saved = core.chip8.save_state();
+        if data.is_null() || size < saved.len() {
+            return false;
+        }
+        // SAFETY: see above
+        unsafe { ptr::copy_nonoverlapping(saved.as_ptr(), data as *mut u8, saved.len()) };
+        true
+    })
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    guard(false, || {
+        let Some(core) = &mut state().core else {
+            return false;
+        };
+        if data.is_null() {
+            return false;
+        }
+        // SAFETY: see above
+        let saved = unsafe { slice::from_raw_parts(data as *const u8, size) };
+        core.chip8
+            .load_state(&saved[..size.min(STATE_SIZE)])
+            .is_ok()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    guard((), || {
+        if let Some(core) = &mut state().core {
+            core.cheats.clear();
+            core.scheduler.patches.clear();
+        }
+    })
+}
+
+/// # Safety
+///
+/// `code` must be NULL or a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    guard((), || {
+        let Some(core) = &mut state().core else {
+            return;
+        };
+        core.cheats.remove(&index);
+        if enabled && !code.is_null() {
+            // SAFETY: see above
+            let code = unsafe { CStr::from_ptr(code) }.to_string_lossy();
+            if let Some(patches) = parse_cheat(&code) {
+                core.cheats.insert(index, patches);
+            }
+        }
+        core.scheduler.patches = core.cheats.values().flatten().copied().collect();
+    })
+}
+
+/// All 4096 bytes of memory as system RAM, for achievements and memory watches
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    guard(ptr::null_mut(), || match &mut state().core {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => {
+            core.chip8.memory.as_mut_ptr() as *mut c_void
+        }
+        _ => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    guard(0, || match &state().core {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.chip8.memory.len(),
+        _ => 0,
+    })
+}
@@ -0,0 +1,309 @@
+/*
+ * Minimal libretro frontend that loads the core and checks what it does, run with `make test`
+ *
+ *     frontend path/to/chip8_libretro.so path/to/pong.rom
+ */
+
+#define _POSIX_C_SOURCE 200809L
+
+#include <dlfcn.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* The parts of libretro.h this needs */
+#define RETRO_DEVICE_JOYPAD 1
+#define RETRO_DEVICE_ID_JOYPAD_UP 4
+#define RETRO_ENVIRONMENT_SET_PIXEL_FORMAT 10
+#define RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS 11
+#define RETRO_PIXEL_FORMAT_XRGB8888 1
+#define RETRO_MEMORY_SYSTEM_RAM 2
+
+struct retro_system_info {
+    const char *library_name;
+    const char *library_version;
+    const char *valid_extensions;
+    bool need_fullpath;
+    bool block_extract;
+};
+
+struct retro_system_av_info {
+    struct {
+        unsigned base_width, base_height, max_width, max_height;
+        float aspect_ratio;
+    } geometry;
+    struct {
+        double fps, sample_rate;
+    } timing;
+};
+
+struct retro_game_info {
+    const char *path;
+    const void *data;
+    size_t size;
+    const char *meta;
+};
+
+struct retro_input_descriptor {
+    unsigned port, device, index, id;
+    const char *description;
+};
+
+typedef bool (*environment_t)(unsigned, void *);
+typedef void (*video_refresh_t)(const void *, unsigned, unsigned, size_t);
+typedef void (*audio_sample_t)(int16_t, int16_t);
+typedef size_t (*audio_sample_batch_t)(const int16_t *, size_t);
+typedef void (*input_poll_t)(void);
+typedef int16_t (*input_state_t)(unsigned, unsigned, unsigned, unsigned);
+
+static struct {
+    unsigned (*api_version)(void);
+    void (*get_system_info)(struct retro_system_info *);
+    void (*get_system_av_info)(struct retro_system_av_info *);
+    void (*set_environment)(environment_t);
+    void (*set_video_refresh)(video_refresh_t);
+    void (*set_audio_sample)(audio_sample_t);
+    void (*set_audio_sample_batch)(audio_sample_batch_t);
+    void (*set_input_poll)(input_poll_t);
+    void (*set_input_state)(input_state_t);
+    void (*init)(void);
+    void (*deinit)(void);
+    bool (*load_game)(const struct retro_game_info *);
+    void (*unload_game)(void);
+    void (*reset)(void);
+    void (*run)(void);
+    size_t (*serialize_size)(void);
+    bool (*serialize)(void *, size_t);
+    bool (*unserialize)(const void *, size_t);
+    void (*cheat_reset)(void);
+    void (*cheat_set)(unsigned, bool, const char *);
+    void *(*get_memory_data)(unsigned);
+    size_t (*get_memory_size)(unsigned);
+} core;
+
+static int failures = 0;
+
+#define CHECK(condition)                                                   \
+    do {                                                                   \
+        if (!(condition)) {                                                \
+            fprintf(stderr, "%s:%d: failed: %s\n", __FILE__, __LINE__,    \
+                    #condition);                                           \
+            failures++;                                                    \
+        }                                                                  \
+    } while (0)
+
+/* What the core last handed over */
+static unsigned pixel_format = 0;
+static int descriptors = 0;
+static uint32_t frame[64 * 32];
+static unsigned frame_width, frame_height, video_calls;
+static size_t audio_frames;
+static bool audio_heard;
+static bool up_held;
+
+static bool environment(unsigned cmd, void *data) {
+    switch (cmd) {
+    case RETRO_ENVIRONMENT_SET_PIXEL_FORMAT:
+        pixel_format = *(unsigned *)data;
+        return true;
+    case RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS:
+        descriptors = 0;
+        for (const struct retro_input_descriptor *d = data; d->description; d++) {
+            descriptors++;
+        }
+        return true;
+    default:
+        return false;
+    }
+}
+
+static void video_refresh(const void *data, unsigned width, unsigned height, size_t pitch) {
+    frame_width = width;
+    frame_height = height;
+    video_calls++;
+    for (unsigned y = 0; y < height && y < 32; y++) {
+        memcpy(&frame[y * 64], (const uint8_t *)data + y * pitch, 64 * sizeof(uint32_t));
+    }
+}
+
+static void audio_sample(int16_t left, int16_t right) {
+    (void)left;
+    (void)right;
+    audio_frames++;
+}
+
+static size_t audio_sample_batch(const int16_t *data, size_t frames) {
+    audio_frames += frames;
+    for (size_t i = 0; i < frames * 2; i++) {
+        audio_heard |= data[i] != 0;
+    }
+    return frames;
+}
+
+static void input_poll(void) {}
+
+static int16_t input_state(unsigned port, unsigned device, unsigned index, unsigned id) {
+    (void)index;
+    return port == 0 && device == RETRO_DEVICE_JOYPAD && id == RETRO_DEVICE_ID_JOYPAD_UP &&
+           up_held;
+}
+
+static void *load(void *library, const char *name) {
+    void *symbol = dlsym(library, name);
+    if (!symbol) {
+        fprintf(stderr, "missing %s\n", name);
+        exit(1);
+    }
+    return symbol;
+}
+
+/* Top row of the left paddle, which pong.rom draws at x = 2 */
+static int paddle_top(void) {
+    for (int y = 0; y < 32; y++) {
+        if (frame[y * 64 + 2] != frame[0]) {
+            return y;
+        }
+    }
+    return -1;
+}
+
+static void run_frames(int frames) {
+    for (int i = 0; i < frames; i++) {
+        core.run();
+    }
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s CORE ROM\n", argv[0]);
+        return 2;
+    }
+
+    void *library = dlopen(argv[1], RTLD_NOW);
+    if (!library) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+
+#define LOAD(field, name) *(void **)&core.field = load(library, name)
+    LOAD(api_version, "retro_api_version");
+    LOAD(get_system_info, "retro_get_system_info");
+    LOAD(get_system_av_info, "retro_get_system_av_info");
+    LOAD(set_environment, "retro_set_environment");
+    LOAD(set_video_refresh, "retro_set_video_refresh");
+    LOAD(set_audio_sample, "retro_set_audio_sample");
+    LOAD(set_audio_sample_batch, "retro_set_audio_sample_batch");
+    LOAD(set_input_poll, "retro_set_input_poll");
+    LOAD(set_input_state, "retro_set_input_state");
+    LOAD(init, "retro_init");
+    LOAD(deinit, "retro_deinit");
+    LOAD(load_game, "retro_load_game");
+    LOAD(unload_game, "retro_unload_game");
+    LOAD(reset, "retro_reset");
+    LOAD(run, "retro_run");
+    LOAD(serialize_size, "retro_serialize_size");
+    LOAD(serialize, "retro_serialize");
+    LOAD(unserialize, "retro_unserialize");
+    LOAD(cheat_reset, "retro_cheat_reset");
+    LOAD(cheat_set, "retro_cheat_set");
+    LOAD(get_memory_data, "retro_get_memory_data");
+    LOAD(get_memory_size, "retro_get_memory_size");
+    /* Required by the API even though nothing here calls them */
+    load(library, "retro_set_controller_port_device");
+    load(library, "retro_load_game_special");
+    load(library, "retro_get_region");
+
+    CHECK(core.api_version() == 1);
+
+    struct retro_system_info info;
+    core.get_system_info(&info);
+    CHECK(strstr(info.valid_extensions, "ch8") != NULL);
+    CHECK(!info.need_fullpath);
+
+    core.set_environment(environment);
+    core.set_video_refresh(video_refresh);
+    core.set_audio_sample(audio_sample);
+    core.set_audio_sample_batch(audio_sample_batch);
+    core.set_input_poll(input_poll);
+    core.set_input_state(input_state);
+    core.init();
+
+    FILE *file = fopen(argv[2], "rb");
+    if (!file) {
+        fprintf(stderr, "failed to read %s\n", argv[2]);
+        return 1;
+    }
+    static uint8_t rom[4096];
+    size_t size = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+
+    struct retro_game_info game = {argv[2], rom, size, NULL};
+    CHECK(core.load_game(&game));
+    CHECK(pixel_format == RETRO_PIXEL_FORMAT_XRGB8888);
+    CHECK(descriptors == 16);
+
+    struct retro_system_av_info av;
+    core.get_system_av_info(&av);
+    CHECK(av.geometry.base_width == 64 && av.geometry.base_height == 32);
+    CHECK(av.timing.fps == 60.0);
+
+    /* One video frame and a 60th of a second of audio per run */
+    run_frames(100);
+    CHECK(video_calls == 100);
+    CHECK(frame_width == 64 && frame_height == 32);
+    CHECK(audio_frames == 100 * (size_t)(av.timing.sample_rate / 60));
+    CHECK(paddle_top() == 12);
+
+    /* The ROM database puts pong's key 1 on up */
+    up_held = true;
+    run_frames(10);
+    up_held = false;
+    CHECK(paddle_top() >= 0 && paddle_top() < 12);
+
+    /* The beep plays when the ball bounces or someone scores */
+    audio_heard = false;
+    run_frames(600);
+    CHECK(audio_heard);
+
+    /* Memory is exposed with the ROM at 0x200, code first and the score after it */
+    CHECK(core.get_memory_size(RETRO_MEMORY_SYSTEM_RAM) == 4096);
+    uint8_t *memory = core.get_memory_data(RETRO_MEMORY_SYSTEM_RAM);
+    CHECK(memory && memcmp(memory + 0x200, rom, 0x40) == 0);
+
+    /* A state loaded back plays out the same */
+    size_t state_size = core.serialize_size();
+    uint8_t *state = malloc(state_size);
+    CHECK(core.serialize(state, state_size));
+    run_frames(120);
+    uint32_t expected[64 * 32];
+    memcpy(expected, frame, sizeof frame);
+    CHECK(core.unserialize(state, state_size));
+    run_frames(120);
+    CHECK(memcmp(expected, frame, sizeof frame) == 0);
+    CHECK(!core.unserialize(state, state_size - 1));
+    free(state);
+
+    /* Cheats freeze values, here the score digits FX33 stores */
+    core.cheat_set(0, true, "2F3:07+2F4 03");
+    run_frames(5);
+    CHECK(memory[0x2F3] == 7 && memory[0x2F4] == 3);
+    core.cheat_reset();
+
+    core.reset();
+    run_frames(1);
+    CHECK(paddle_top() == -1);
+
+    core.unload_game();
+    core.deinit();
+    dlclose(library);
+
+    if (failures) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,61 @@
+"""Tabular Q-learning on Pong (1 player) or Breakout, from the paddle and ball registers.
+
+    python examples/train.py "../roms/Pong-(1player).ch8" --episodes 200
+"""
+
+import argparse
+import random
+from collections import defaultdict
+
+import chip8
+
+# Registers holding the ball's x and y and the paddle's position, by game title
+FEATURES = {
+    "Pong": (0x6, 0x7, 0xB),
+    "Breakout": (0x6, 0x7, 0xC),
+}
+
+
+def state(env):
+    ball_x, ball_y, paddle = FEATURES[env.title]
+    v = env.v
+    if env.title == "Pong":
+        offset = v[ball_y] - (v[paddle] + 3)
+        return max(-4, min(4, offset // 2)), v[ball_x] // 8
+    offset = v[ball_x] - (v[paddle] + 3)
+    return max(-4, min(4, offset // 2)), v[ball_y] // 4
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("rom")
+    parser.add_argument("--episodes", type=int, default=200)
+    parser.add_argument("--seed", type=int, default=0)
+    args = parser.parse_args()
+
+    env = chip8.Env(args.rom, sticky_actions=0.25, max_frames=36000, seed=args.seed)
+    rng = random.Random(args.seed)
+    q = defaultdict(lambda: [0.0] * env.action_count)
+    alpha, gamma, epsilon = 0.2, 0.99, 0.1
+
+    for episode in range(args.episodes):
+        env.reset()
+        s, total, done = state(env), 0.0, False
+        while not done:
+            if rng.random() < epsilon:
+                action = rng.randrange(env.action_count)
+            else:
+                action = max(range(env.action_count), key=lambda a: q[s][a])
+            _, reward, done, _ = env.step(action)
+            total += reward
+
+            next_s = state(env)
+            target = reward if done else reward + gamma * max(q[next_s])
+            q[s][action] += alpha * (target - q[s][action])
+            s = next_s
+
+        print(f"episode {episode}: {total:+.0f}")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,269 @@
+use chip8_rust::chip::{Chip8, HEIGHT, WIDTH};
+use chip8_rust::env::{Env, Game, Options};
+use chip8_rust::romdb;
+use chip8_rust::scheduler::Scheduler;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::fs;
+use std::path::PathBuf;
+
+///A CHIP-8 machine driven one instruction or frame at a time
+#[pyclass(name = "Chip8", unsendable)]
+struct PyChip8 {
+    chip8: Chip8,
+    scheduler: Scheduler,
+
+    ///Used instead of what the database recommends for the ROM
+    cycles_per_frame: Option<u32>,
+
+    ///Seed for CXNN, random if unset
+    seed: Option<u64>,
+
+    ///ROM `reset` starts again
+    rom: Vec<u8>,
+}
+
+#[pymethods]
+impl PyChip8 {
+    /// Chip8(cycles_per_frame=None, seed=None)
+    ///
+    /// Instructions per frame default to what the ROM database recommends for each ROM. Give a
+    /// seed for CXNN to return the same numbers every run.
+    #[new]
+    #[pyo3(signature = (cycles_per_frame = None, seed = None))]
+    fn new(cycles_per_frame: Option<u32>, seed: Option<u64>) -> PyResult<Self> {
+        if cycles_per_frame == Some(0) {
+            return Err(PyValueError::new_err("cycles_per_frame must be at least 1"));
+        }
+
+        let mut chip8 = Chip8::new();
+        if let Some(seed) = seed {
+            chip8.seed(seed);
+        }
+
+        Ok(PyChip8 {
+            chip8,
+            scheduler: Scheduler::default(),
+            cycles_per_frame,
+            seed,
+            rom: Vec::new(),
+        })
+    }
+
+    /// Loads a ROM from a path or from bytes and starts it, with the quirks the database recommends
+    fn load_rom(&mut self, rom: &PyAny) -> PyResult<()> {
+        let rom = read_rom(rom)?;
+        self.chip8
+            .load_bytes(&rom)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        self.rom = rom;
+        self.reset();
+        Ok(())
+    }
+
+    /// Starts the ROM again from a fresh machine
+    fn reset(&mut self) {
+        let profile = romdb::bundled().profile(&self.rom);
+
+        self.chip8
+            .load_bytes(&self.rom)
+            .expect("the ROM fit when it was loaded");
+        self.chip8.quirks = profile.quirks;
+        if let Some(seed) = self.seed {
+            self.chip8.seed(seed);
+        }
+        self.scheduler = Scheduler::new(self.cycles_per_frame.unwrap_or(profile.cycles_per_frame));
+    }
+
+    /// Runs one instruction, ticking the timers if it ends a frame
+    fn step(&mut self) {
+        self.scheduler.step_instruction(&mut self.chip8);
+    }
+
+    /// Runs the rest of the current frame, then `frames - 1` more
+    #[pyo3(signature = (frames = 1))]
+    fn run_frame(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.scheduler.run_frame(&mut self.chip8);
+        }
+    }
+
+    /// Sets which of the 16 keys are down, from a bit mask with key 0 as bit 0 or 16 truth values
+    fn set_keys(&mut self, keys: &PyAny) -> PyResult<()> {
+        let pressed: Vec<bool> = match keys.extract::<u16>() {
+            Ok(mask) => (0..16).map(|key| mask & (1 << key) != 0).collect(),
+            Err(_) => keys.extract()?,
+        };
+        if pressed.len() != 16 {
+            return Err(PyValueError::new_err(format!(
+                "expected 16 keys, got {}",
+                pressed.len()
+            )));
+        }
+
+        for (key, pressed) in pressed.into_iter().enumerate() {
+            self.chip8.set_key(key, pressed);
+        }
+        Ok(())
+    }
+
+    /// The display as WIDTH * HEIGHT bytes, 1 for a lit pixel, row by row
+    ///
+    /// `numpy.frombuffer(screen, dtype=numpy.uint8).reshape(HEIGHT, WIDTH)` makes it an array
+    fn get_screen<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.chip8.display)
+    }
+
+    /// All 4096 bytes of memory
+    fn get_ram<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.chip8.memory)
+    }
+
+    /// The machine as bytes for `load_state`, save between frames to resume exactly
+    fn save_state<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.chip8.save_state())
+    }
+
+    /// Restores a state from `save_state`, raising ValueError if it isn't one
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        self.chip8
+            .load_state(state)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.chip8.pc
+    }
+
+    #[getter]
+    fn i(&self) -> u16 {
+        self.chip8.i
+    }
+
+    /// V0 to VF
+    #[getter]
+    fn v(&self) -> Vec<u8> {
+        self.chip8.v.to_vec()
+    }
+}
+
+///A Gym style environment, observations are the display and rewards come from game memory
+#[pyclass(name = "Env", unsendable)]
+struct PyEnv {
+    env: Env,
+}
+
+#[pymethods]
+impl PyEnv {
+    /// Env(rom, game=None, frame_skip=4, sticky_actions=0.0, max_frames=None, seed=None)
+    ///
+    /// `rom` is a path or bytes. The actions, rewards and game over conditions come from the
+    /// definitions bundled for the ROM, or from `game`, a `[[game]]` table in the format of
+    /// `data/games.toml`.
+    #[new]
+    #[pyo3(signature = (rom, game = None, frame_skip = 4, sticky_actions = 0.0, max_frames = None, seed = None))]
+    fn new(
+        rom: &PyAny,
+        game: Option<&str>,
+        frame_skip: u32,
+        sticky_actions: f32,
+        max_frames: Option<u32>,
+        seed: Option<u64>,
+    ) -> PyResult<Self> {
+        let rom = read_rom(rom)?;
+        let options = Options {
+            frame_skip,
+            sticky_actions,
+            max_frames,
+            seed,
+        };
+
+        let env = match game {
+            Some(game) => {
+                let game = Game::parse(game)
+                    .map_err(|e| PyValueError::new_err(format!("{:#}", e)))?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| PyValueError::new_err("no [[game]] table in the definition"))?;
+                Env::new(rom, game, options)
+            }
+            None => Env::for_rom(rom, options),
+        };
+        env.map(|env| PyEnv { env })
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+
+    /// Starts a new episode and returns the first observation
+    fn reset<'py>(&mut self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.env.reset())
+    }
+
+    /// Plays an action, returning `(observation, reward, done, info)`
+    ///
+    /// `done` is true when the game is over or the episode was cut short, which `info["truncated"]`
+    /// tells apart. `info["frames"]` counts the frames since the reset.
+    fn step<'py>(&mut self, py: Python<'py>, action: usize) -> PyResult<PyObject> {
+        let step = self
+            .env
+            .step(action)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let info = PyDict::new(py);
+        info.set_item("frames", step.frames)?;
+        info.set_item("truncated", step.truncated)?;
+        let observation = PyBytes::new(py, self.env.observation());
+        Ok((observation, step.reward, step.done || step.truncated, info).into_py(py))
+    }
+
+    /// The display as WIDTH * HEIGHT bytes, 1 for a lit pixel, row by row
+    fn observation<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.env.observation())
+    }
+
+    /// All 4096 bytes of memory
+    fn get_ram<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.env.chip8.memory)
+    }
+
+    /// V0 to VF
+    #[getter]
+    fn v(&self) -> Vec<u8> {
+        self.env.chip8.v.to_vec()
+    }
+
+    #[getter]
+    fn title(&self) -> String {
+        self.env.game.title.clone()
+    }
+
+    /// Actions `step` takes, numbered from 0
+    #[getter]
+    fn action_count(&self) -> usize {
+        self.env.action_count()
+    }
+}
+
+/// Contents of a ROM given as bytes or a path
+fn read_rom(rom: &PyAny) -> PyResult<Vec<u8>> {
+    match rom.downcast::<PyBytes>() {
+        Ok(bytes) => Ok(bytes.as_bytes().to_vec()),
+        Err(_) => {
+            let path: PathBuf = rom.extract()?;
+            fs::read(&path).map_err(|e| {
+                PyOSError::new_err(format!("failed to read {}: {}", path.display(), e))
+            })
+        }
+    }
+}
+
+#[pymodule]
+fn chip8(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyChip8>()?;
+    m.add_class::<PyEnv>()?;
+    m.add("WIDTH", WIDTH)?;
+    m.add("HEIGHT", HEIGHT)?;
+    Ok(())
+}
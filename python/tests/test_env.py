@@ -0,0 +1,108 @@
+import random
+from pathlib import Path
+
+import pytest
+
+import chip8
+
+ROMS = Path(__file__).resolve().parents[2] / "roms"
+PONG = ROMS / "pong.rom"
+BREAKOUT = ROMS / "Breakout.ch8"
+
+
+def play(env, policy):
+    env.reset()
+    total, done, info = 0.0, False, {}
+    while not done:
+        _, reward, done, info = env.step(policy())
+        total += reward
+    return total, info
+
+
+def test_observation_is_the_display():
+    env = chip8.Env(PONG, seed=1)
+    observation = env.reset()
+    assert len(observation) == chip8.WIDTH * chip8.HEIGHT
+    assert set(observation) <= {0, 1}
+    assert sum(observation) > 0
+    assert env.title == "Pong"
+    assert env.action_count == 3
+
+
+def test_step_returns_gym_tuple():
+    env = chip8.Env(BREAKOUT, frame_skip=4, seed=1)
+    env.reset()
+    observation, reward, done, info = env.step(1)
+    assert len(observation) == chip8.WIDTH * chip8.HEIGHT
+    assert reward == 0.0
+    assert not done
+    assert info == {"frames": 4, "truncated": False}
+
+
+def test_breakout_rewards_bricks_until_the_balls_run_out():
+    env = chip8.Env(BREAKOUT, seed=2)
+    rng = random.Random(2)
+    total, info = play(env, lambda: rng.randrange(env.action_count))
+    assert total > 0
+    assert env.v[0xE] == 0 or env.v[0x5] == 96
+    assert not info["truncated"]
+
+
+def test_pong_ends_at_nine_points():
+    env = chip8.Env(PONG, seed=3)
+    total, info = play(env, lambda: 0)
+    ram = env.get_ram()
+    assert 9 in (ram[0x2F3], ram[0x2F4])
+    assert total == ram[0x2F3] - ram[0x2F4]
+
+
+def test_max_frames_truncates():
+    env = chip8.Env(PONG, max_frames=100, seed=1)
+    _, info = play(env, lambda: 1)
+    assert info == {"frames": 100, "truncated": True}
+
+
+def test_same_seed_same_episode():
+    def episode(seed):
+        env = chip8.Env(BREAKOUT, sticky_actions=0.25, max_frames=2000, seed=seed)
+        rng = random.Random(0)
+        return play(env, lambda: rng.randrange(3)), env.observation()
+
+    assert episode(7) == episode(7)
+
+
+def test_step_after_done_raises():
+    env = chip8.Env(PONG, max_frames=4, seed=1)
+    env.reset()
+    assert env.step(0)[2]
+    with pytest.raises(ValueError):
+        env.step(0)
+    env.reset()
+    env.step(0)
+
+
+def test_bad_action_raises():
+    env = chip8.Env(PONG)
+    with pytest.raises(ValueError):
+        env.step(3)
+
+
+def test_custom_game():
+    game = """
+        [[game]]
+        title = "Pong, right paddle"
+        roms = []
+        actions = ["", "C", "D"]
+        start_frames = 100
+        rewards = [{ location = "2F4" }, { location = "2F3", scale = -1.0 }]
+        done = [{ location = "2F3", equals = 1 }]
+    """
+    env = chip8.Env(PONG, game=game, seed=1)
+    total, _ = play(env, lambda: 0)
+    assert env.title == "Pong, right paddle"
+    assert total == env.get_ram()[0x2F4] - 1
+
+
+def test_unknown_rom_needs_a_game():
+    with pytest.raises(ValueError):
+        chip8.Env(bytes([0x12, 0x00]))
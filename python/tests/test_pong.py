@@ -0,0 +1,116 @@
+from pathlib import Path
+
+import pytest
+
+import chip8
+
+PONG = Path(__file__).resolve().parents[2] / "roms" / "pong.rom"
+
+
+@pytest.fixture
+def machine():
+    machine = chip8.Chip8(seed=1)
+    machine.load_rom(str(PONG))
+    return machine
+
+
+def lit(screen):
+    return sum(screen)
+
+
+def paddle_rows(screen, column):
+    return [y for y in range(chip8.HEIGHT) if screen[y * chip8.WIDTH + column]]
+
+
+def test_rom_is_loaded_at_0x200(machine):
+    rom = PONG.read_bytes()
+    assert machine.get_ram()[0x200 : 0x200 + len(rom)] == rom
+    assert machine.pc == 0x200
+
+
+def test_load_rom_from_bytes():
+    machine = chip8.Chip8()
+    machine.load_rom(PONG.read_bytes())
+    assert machine.get_ram()[0x200:0x202] == PONG.read_bytes()[:2]
+
+
+def test_rom_too_big():
+    with pytest.raises(ValueError):
+        chip8.Chip8().load_rom(bytes(4096))
+
+
+def test_screen_after_a_second(machine):
+    machine.run_frame(60)
+    screen = machine.get_screen()
+    assert len(screen) == chip8.WIDTH * chip8.HEIGHT
+    assert set(screen) <= {0, 1}
+    assert lit(screen) > 0
+
+
+def test_step_runs_one_instruction(machine):
+    machine.step()
+    assert machine.pc == 0x202
+
+
+def test_keys_move_the_left_paddle(machine):
+    # The game waits a moment before the paddles can move
+    machine.run_frame(100)
+    state = machine.save_state()
+
+    machine.run_frame(10)
+    still = paddle_rows(machine.get_screen(), 2)
+
+    machine.load_state(state)
+    machine.set_keys(1 << 1)
+    machine.run_frame(10)
+    up = paddle_rows(machine.get_screen(), 2)
+
+    machine.load_state(state)
+    machine.set_keys([False] * 4 + [True] + [False] * 11)
+    machine.run_frame(10)
+    down = paddle_rows(machine.get_screen(), 2)
+
+    assert still and up and down
+    assert min(up) < min(still) < min(down)
+
+
+def test_set_keys_needs_16(machine):
+    with pytest.raises(ValueError):
+        machine.set_keys([True] * 3)
+
+
+def test_save_and_load_state_resume_exactly(machine):
+    machine.run_frame(30)
+    state = machine.save_state()
+
+    machine.run_frame(60)
+    screen, ram, v = machine.get_screen(), machine.get_ram(), machine.v
+
+    machine.load_state(state)
+    machine.run_frame(60)
+    assert machine.get_screen() == screen
+    assert machine.get_ram() == ram
+    assert machine.v == v
+
+
+def test_reset_starts_again(machine):
+    machine.run_frame(30)
+    machine.reset()
+    assert machine.pc == 0x200
+    assert lit(machine.get_screen()) == 0
+
+
+def test_bad_state_raises(machine):
+    with pytest.raises(ValueError):
+        machine.load_state(b"not a state")
+    with pytest.raises(ValueError):
+        machine.load_state(machine.save_state()[:-1])
+
+
+def test_screen_as_numpy_array(machine):
+    numpy = pytest.importorskip("numpy")
+    machine.run_frame(60)
+    screen = numpy.frombuffer(machine.get_screen(), dtype=numpy.uint8)
+    screen = screen.reshape(chip8.HEIGHT, chip8.WIDTH)
+    assert screen.shape == (32, 64)
+    assert screen.sum() == lit(machine.get_screen())
@@ -0,0 +1,289 @@
+use crate::chip::{Quirks, START};
+use crate::platform::Platform;
+
+///How far past an FX55/FX65 to look for the next use of I
+const LOOKAHEAD: usize = 16;
+
+///What a static pass over a ROM's reachable code found
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Analysis {
+    ///Instructions reachable from the entry point
+    pub instructions: usize,
+
+    ///SUPER-CHIP only instructions, with their address
+    pub super_chip: Vec<(u16, u16)>,
+
+    ///XO-CHIP only instructions, with their address
+    pub xo_chip: Vec<(u16, u16)>,
+
+    ///I is used again after FX55/FX65 without being reloaded, so the program expects it incremented
+    pub relies_on_i_increment: bool,
+
+    ///Every FX55/FX65 is followed by a reload of I, so the program doesn't care what they leave in it
+    pub reloads_i_after_memory: bool,
+
+    ///Shifts are written 8X06/8X0E, so the program expects VX shifted in place
+    pub shifts_in_place: bool,
+
+    ///BNNN jumps whose targets can't be followed, some code may have been missed
+    pub indirect_jumps: bool,
+
+    ///Which ROM offsets start a reachable instruction
+    pub code: Vec<bool>,
+}
+
+impl Analysis {
+    /// Platform the program was most likely written for
+    pub fn platform(&self) -> Platform {
+        if !self.xo_chip.is_empty() {
+            Platform::XoChip
+        } else if !self.super_chip.is_empty() {
+            Platform::SuperChip
+        } else {
+            Platform::ModernChip8
+        }
+    }
+
+    /// Quirks of `platform()`, adjusted for the patterns found in the code
+    pub fn quirks(&self) -> Quirks {
+        let mut quirks = self.platform().quirks();
+
+        // Code that never looks at I afterwards runs the same either way, so leave it alone like
+        // most modern interpreters do
+        if self.relies_on_i_increment {
+            quirks.memory_leave_i_unchanged = false;
+        } else if self.reloads_i_after_memory {
+            quirks.memory_leave_i_unchanged = true;
+        }
+
+        if self.shifts_in_place {
+            quirks.shift = true;
+        }
+
+        quirks
+    }
+
+    /// Human readable reasons for the guess
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+
+        if let Some((addr, opcode)) = self.xo_chip.first() {
+            notes.push(format!(
+                "XO-CHIP instruction {:04X} at {:#05X}",
+                opcode, addr
+            ));
+        }
+        if let Some((addr, opcode)) = self.super_chip.first() {
+            notes.push(format!(
+                "SUPER-CHIP instruction {:04X} at {:#05X}",
+                opcode, addr
+            ));
+        }
+        if self.relies_on_i_increment {
+            notes.push("Uses I after FX55/FX65 without reloading it".to_string());
+        } else if self.reloads_i_after_memory {
+            notes.push("Reloads I after every FX55/FX65".to_string());
+        }
+        if self.shifts_in_place {
+            notes.push("Shifts written as 8X06/8X0E".to_string());
+        }
+        if self.indirect_jumps {
+            notes.push("Has BNNN jumps that couldn't be followed".to_string());
+        }
+
+        notes
+    }
+}
+
+/// Size of the instruction at `addr`, XO-CHIP's F000 NNNN is the only 4 byte one
+fn size(rom: &[u8], addr: usize) -> usize {
+    if opcode_at(rom, addr) == Some(0xF000) {
+        4
+    } else {
+        2
+    }
+}
+
+fn opcode_at(rom: &[u8], addr: usize) -> Option<u16> {
+    let offset = addr.checked_sub(START)?;
+    let hi = *rom.get(offset)?;
+    let lo = *rom.get(offset + 1)?;
+    Some(((hi as u16) << 8) | lo as u16)
+}
+
+fn is_skip(opcode: u16) -> bool {
+    match opcode & 0xF000 {
+        0x3000 | 0x4000 => true,
+        0x5000 | 0x9000 => opcode & 0x000F == 0,
+        0xE000 => matches!(opcode & 0x00FF, 0x9E | 0xA1),
+        _ => false,
+    }
+}
+
+fn is_super_chip(opcode: u16) -> bool {
+    match opcode {
+        0x00FB..=0x00FF => true,
+        _ if opcode & 0xFFF0 == 0x00C0 => true,
+        _ if opcode & 0xF00F == 0xD000 => true,
+        _ => matches!(opcode & 0xF0FF, 0xF030 | 0xF075 | 0xF085),
+    }
+}
+
+fn is_xo_chip(opcode: u16) -> bool {
+    match opcode {
+        0xF000 | 0xF002 => true,
+        _ if opcode & 0xFFF0 == 0x00D0 => true,
+        _ if matches!(opcode & 0xF00F, 0x5002 | 0x5003) => true,
+        _ if opcode & 0xF0FF == 0xF03A => true,
+        // FN01 selects drawing planes
+        _ => opcode & 0xF0FF == 0xF001,
+    }
+}
+
+fn sets_i(opcode: u16) -> bool {
+    opcode & 0xF000 == 0xA000 || matches!(opcode & 0xF0FF, 0xF029 | 0xF030) || opcode == 0xF000
+}
+
+fn uses_i(opcode: u16) -> bool {
+    opcode & 0xF000 == 0xD000 || matches!(opcode & 0xF0FF, 0xF01E | 0xF033 | 0xF055 | 0xF065)
+}
+
+/// Whether I is used again after the FX55/FX65 at `addr` before anything reloads it, `None` if
+/// the straight line ends before either happens
+fn uses_i_after(rom: &[u8], addr: usize) -> Option<bool> {
+    let mut pc = addr + size(rom, addr);
+
+    for _ in 0..LOOKAHEAD {
+        let opcode = opcode_at(rom, pc)?;
+
+        if sets_i(opcode) {
+            return Some(false);
+        }
+        if uses_i(opcode) {
+            return Some(true);
+        }
+
+        pc = match opcode & 0xF000 {
+            0x1000 => (opcode & 0x0FFF) as usize,
+            // Calls, returns and computed jumps end the straight line
+            0x2000 | 0xB000 => return None,
+            _ if opcode == 0x00EE => return None,
+            _ => pc + size(rom, pc),
+        };
+    }
+
+    None
+}
+
+/// Follows every path from the entry point through a ROM, noting what the code needs
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let mut analysis = Analysis {
+        code: vec![false; rom.len()],
+        ..Analysis::default()
+    };
+    let mut visited = vec![false; START + rom.len() + 4];
+    let mut pending = vec![START];
+    // FX55/FX65 seen, and how many of them were followed by a reload of I
+    let mut memory_ops = 0;
+    let mut reloads = 0;
+
+    while let Some(addr) = pending.pop() {
+        let Some(opcode) = opcode_at(rom, addr) else {
+            continue;
+        };
+        if visited[addr] {
+            continue;
+        }
+        visited[addr] = true;
+        analysis.instructions += 1;
+        analysis.code[addr - START] = true;
+
+        if is_xo_chip(opcode) {
+            analysis.xo_chip.push((addr as u16, opcode));
+        } else if is_super_chip(opcode) {
+            analysis.super_chip.push((addr as u16, opcode));
+        }
+
+        if matches!(opcode & 0xF0FF, 0xF055 | 0xF065) {
+            memory_ops += 1;
+            match uses_i_after(rom, addr) {
+                Some(true) => analysis.relies_on_i_increment = true,
+                Some(false) => reloads += 1,
+                None => {}
+            }
+        }
+
+        let x = (opcode & 0x0F00) >> 8;
+        let y = (opcode & 0x00F0) >> 4;
+        if matches!(opcode & 0xF00F, 0x8006 | 0x800E) && y == 0 && x != 0 {
+            analysis.shifts_in_place = true;
+        }
+
+        let next = addr + size(rom, addr);
+        match opcode & 0xF000 {
+            // 00EE returns and 00FD exits, neither falls through
+            0x0000 if opcode == 0x00EE || opcode == 0x00FD => {}
+            0x1000 => pending.push((opcode & 0x0FFF) as usize),
+            0x2000 => {
+                pending.push((opcode & 0x0FFF) as usize);
+                pending.push(next);
+            }
+            0xB000 => analysis.indirect_jumps = true,
+            _ if is_skip(opcode) => {
+                pending.push(next);
+                pending.push(next + size(rom, next));
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    analysis.reloads_i_after_memory = memory_ops > 0 && reloads == memory_ops;
+    analysis.super_chip.sort();
+    analysis.xo_chip.sort();
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled(name: &str) -> Vec<u8> {
+        std::fs::read(format!("{}/roms/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    #[test]
+    fn reloading_i_after_every_store_leaves_it_unchanged() {
+        for name in ["pong.rom", "Breakout.ch8", "test_opcode.ch8"] {
+            let analysis = analyze(&bundled(name));
+            assert_eq!(analysis.platform(), Platform::ModernChip8, "{}", name);
+            assert!(analysis.reloads_i_after_memory, "{}", name);
+            assert!(!analysis.relies_on_i_increment, "{}", name);
+            assert!(analysis.quirks().memory_leave_i_unchanged, "{}", name);
+            assert_ne!(
+                analysis.quirks(),
+                Platform::ModernChip8.quirks(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn platform_quirks_without_a_pattern() {
+        let analysis = analyze(&bundled("Fishie.ch8"));
+        assert!(!analysis.reloads_i_after_memory);
+        assert!(!analysis.relies_on_i_increment);
+        assert_eq!(analysis.quirks(), Platform::ModernChip8.quirks());
+    }
+
+    #[test]
+    fn using_i_after_a_store_increments_it_even_on_super_chip() {
+        // HIGH, then FX55 straight into FX65 without reloading I
+        let rom = [0x00, 0xFF, 0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65, 0x12, 0x08];
+        let analysis = analyze(&rom);
+        assert_eq!(analysis.platform(), Platform::SuperChip);
+        assert!(analysis.relies_on_i_increment);
+        assert!(!analysis.quirks().memory_leave_i_unchanged);
+        assert!(Platform::SuperChip.quirks().memory_leave_i_unchanged);
+    }
+}
@@ -0,0 +1,1117 @@
+use crate::browser::RomBrowser;
+use crate::cheat_panel::CheatPanel;
+use crate::chip::*;
+use crate::code_viewer::CodeViewer;
+use crate::console::LogConsole;
+use crate::filter::*;
+use crate::framebuffer::Framebuffer;
+use crate::keymap::Keymap;
+use crate::logging;
+use crate::memory_viewer::MemoryViewer;
+use crate::opcodes;
+use crate::palette::Palette;
+use crate::platform::PLATFORMS;
+use crate::profiler::Profiler;
+use crate::romdb::{self, Overrides, RomProfile};
+use crate::scheduler::{Scheduler, SPEEDS};
+#[cfg(feature = "scripting")]
+use crate::script::ScriptPanel;
+use crate::settings::Settings;
+use crate::sound::Beeper;
+use crate::sprite_viewer::SpriteViewer;
+use crate::support::Frame;
+use crate::symbols::Symbols;
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+///Call depth the debug window starts warning at
+const STACK_WARNING: usize = STACK_SIZE - 2;
+
+///Screen pixels per CHIP-8 pixel in screenshots when the display scales with the window
+const SCREENSHOT_SCALE: u32 = 8;
+
+///Saved in the same directory as the settings
+const CHEATS_FILE: &str = "cheats.txt";
+
+///CHIP-8 keys in the layout of the COSMAC VIP keypad
+const KEYPAD: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+///Debug windows that see every instruction as it runs
+struct Watchers {
+    memory_viewer: MemoryViewer,
+    profiler: Profiler,
+}
+
+impl Watchers {
+    fn record(&mut self, pc: u16, chip8: &Chip8) {
+        self.memory_viewer.record(chip8);
+        self.profiler.record(pc, chip8);
+    }
+}
+
+///Frontend settings given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    ///Settings for every ROM, over what the database recommends
+    pub overrides: Overrides,
+
+    ///Used instead of the keymap in the settings
+    pub keymap: Option<Keymap>,
+
+    ///Directory the ROM browser opens in, instead of the last one used
+    pub rom_dir: Option<PathBuf>,
+
+    pub fullscreen: bool,
+
+    ///Screen pixels per CHIP-8 pixel, scales freely with the window if unset
+    pub scale: Option<u32>,
+
+    ///Seed for CXNN, random if unset
+    pub seed: Option<u64>,
+
+    pub mute: bool,
+}
+
+///State of the imgui frontend
+pub struct App {
+    chip8: Chip8,
+    scheduler: Scheduler,
+    filter: DisplayFilter,
+    decay: f32,
+    framebuffer: Framebuffer,
+
+    ///Fast-forward switched on from the menu rather than held down
+    fast_forward_locked: bool,
+
+    browser: RomBrowser,
+
+    ///ROM currently running, nothing runs until one is loaded
+    rom: Option<PathBuf>,
+    rom_data: Vec<u8>,
+    load_error: Option<String>,
+
+    ///How the current ROM is being run
+    profile: Option<RomProfile>,
+
+    ///Settings given on the command line, for every ROM
+    overrides: Overrides,
+
+    ///Remembered between runs, saved when the window closes
+    settings: Settings,
+
+    ///Where the settings are saved, not at all if unset
+    settings_path: Option<PathBuf>,
+
+    preferences_open: bool,
+
+    ///Contents of the keymap text box in the preferences
+    keymap_input: String,
+    keymap_error: Option<String>,
+
+    fullscreen: bool,
+
+    keymap: Keymap,
+    seed: Option<u64>,
+
+    ///Screen pixels per CHIP-8 pixel the emulator window starts at
+    scale: Option<u32>,
+
+    ///None if there's no audio device
+    beeper: Option<Beeper>,
+    muted: bool,
+
+    watchers: Watchers,
+    sprite_viewer: SpriteViewer,
+    code_viewer: CodeViewer,
+    cheats: CheatPanel,
+    console: LogConsole,
+    #[cfg(feature = "scripting")]
+    scripts: ScriptPanel,
+
+    ///Keys held down from the debug window's keypad until clicked again
+    latched_keys: [bool; 16],
+
+    ///Why the last register edit was refused
+    register_error: Option<String>,
+}
+
+impl App {
+    /// Starts without a ROM, with the browser open
+    pub fn new(
+        mut scheduler: Scheduler,
+        options: Options,
+        settings: Settings,
+        settings_path: Option<PathBuf>,
+    ) -> Self {
+        let rom_dir = options.rom_dir.as_ref().unwrap_or(&settings.rom_dir);
+        let mut browser = RomBrowser::new(rom_dir);
+        browser.recent = settings.recent.clone();
+        browser.open = true;
+
+        let mut framebuffer = Framebuffer::new(settings.palette);
+        framebuffer.integer_scale = options.scale.is_some();
+
+        let beeper = match Beeper::new() {
+            Ok(beeper) => {
+                beeper.set_volume(settings.volume);
+                Some(beeper)
+            }
+            Err(e) => {
+                warn!(target: logging::AUDIO, "no audio: {}", e);
+                None
+            }
+        };
+
+        let keymap = options.keymap.unwrap_or_else(|| {
+            Keymap::parse(&settings.keymap).unwrap_or_else(|e| {
+                warn!(target: logging::INPUT, "ignoring keymap in settings: {}", e);
+                Keymap::default()
+            })
+        });
+
+        scheduler.speed = settings.speed;
+        let fullscreen = options.fullscreen || settings.window.fullscreen;
+
+        // Cheats live next to the settings
+        let cheats_path = settings_path
+            .as_ref()
+            .and_then(|path| Some(path.parent()?.join(CHEATS_FILE)));
+
+        let chip8 = Chip8::new();
+        let watchers = Watchers {
+            memory_viewer: MemoryViewer::new(chip8.memory.len()),
+            profiler: Profiler::new(chip8.memory.len()),
+        };
+
+        App {
+            chip8,
+            scheduler,
+            filter: DisplayFilter::default(),
+            decay: 0.5,
+            framebuffer,
+            fast_forward_locked: false,
+            browser,
+            rom: None,
+            rom_data: Vec::new(),
+            load_error: None,
+            profile: None,
+            overrides: options.overrides,
+            keymap_input: settings.keymap.clone(),
+            keymap_error: None,
+            settings,
+            settings_path,
+            preferences_open: false,
+            fullscreen,
+            keymap,
+            seed: options.seed,
+            scale: options.scale,
+            beeper,
+            muted: options.mute,
+            watchers,
+            sprite_viewer: SpriteViewer::new(),
+            code_viewer: CodeViewer::new(),
+            cheats: CheatPanel::new(cheats_path),
+            console: LogConsole::default(),
+            #[cfg(feature = "scripting")]
+            scripts: ScriptPanel::new(),
+            latched_keys: [false; 16],
+            register_error: None,
+        }
+    }
+
+    /// Resets the machine and runs `path` in it, with the settings recommended for it
+    pub fn load_rom(&mut self, path: &Path) {
+        let rom = match fs::read(path) {
+            Ok(rom) => rom,
+            Err(e) => {
+                self.load_error = Some(format!("Failed to load {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        if let Err(e) = self.chip8.load_bytes(&rom) {
+            self.load_error = Some(format!("Failed to load {}: {:#}", path.display(), e));
+            return;
+        }
+        let profile = self.profile_for(&rom);
+
+        if let Some(seed) = self.seed {
+            self.chip8.seed(seed);
+        }
+        self.apply_profile(&profile);
+
+        self.chip8.symbols = Symbols::for_rom(path).unwrap_or_else(|e| {
+            warn!(target: logging::FRONTEND, "ignoring symbols: {:#}", e);
+            Symbols::default()
+        });
+        // Breakpoints survive a reset but not a different ROM
+        if self.rom.as_deref() != Some(path) {
+            self.scheduler.breakpoints = self.chip8.symbols.breakpoints.keys().copied().collect();
+        }
+        self.code_viewer.load(&rom, &self.chip8.symbols);
+        self.cheats.load_rom(&profile.sha1);
+
+        self.filter.reset();
+        self.watchers.memory_viewer.reset();
+        self.watchers.profiler.clear();
+        self.browser.add_recent(path);
+        self.browser.open = false;
+        self.rom = Some(path.to_path_buf());
+        self.load_error = None;
+        self.profile = Some(profile);
+        self.rom_data = rom;
+    }
+
+    /// Runs a script alongside the ROM, see `Script`
+    #[cfg(feature = "scripting")]
+    pub fn load_script(&mut self, path: &Path) {
+        self.scripts
+            .load(path, &mut self.chip8, &mut self.scheduler);
+        self.scripts.open = true;
+    }
+
+    /// Recommended settings for a ROM with the user's overrides on top
+    fn profile_for(&self, rom: &[u8]) -> RomProfile {
+        let mut profile = romdb::bundled().profile(rom);
+        profile.apply(&self.overrides);
+        if let Some(overrides) = self.settings.roms.get(&profile.sha1) {
+            profile.apply(overrides);
+        }
+        profile
+    }
+
+    fn apply_profile(&mut self, profile: &RomProfile) {
+        self.chip8.quirks = profile.quirks;
+        self.scheduler.cycles_per_frame = profile.cycles_per_frame;
+        self.framebuffer.palette = profile.palette.unwrap_or(self.settings.palette);
+    }
+
+    /// Settings changed by hand for the current ROM
+    fn current_overrides(&mut self) -> Option<&mut Overrides> {
+        let sha1 = self.profile.as_ref()?.sha1.clone();
+        Some(self.settings.roms.entry(sha1).or_default())
+    }
+
+    /// Presses the CHIP-8 keys whose host keys are held down
+    fn update_keypad(&mut self, ui: &Ui) {
+        // Don't play the game while typing into a text box
+        let typing = ui.io().want_text_input;
+
+        #[cfg(feature = "scripting")]
+        let script_keys = self.scripts.keys();
+        #[cfg(not(feature = "scripting"))]
+        let script_keys = [false; 16];
+
+        for (key, held_by_script) in script_keys.into_iter().enumerate() {
+            let mut pressed =
+                imgui_key(self.keymap.host_key(key)).is_some_and(|k| ui.is_key_down(k));
+
+            if let Some(profile) = &self.profile {
+                pressed |= profile
+                    .keys
+                    .iter()
+                    .filter(|(_, k)| *k as usize == key)
+                    .filter_map(|(control, _)| control_key(control))
+                    .any(|k| ui.is_key_down(k));
+            }
+
+            self.chip8.set_key(
+                key,
+                (pressed && !typing) || self.latched_keys[key] || held_by_script,
+            );
+        }
+    }
+
+    /// Runs the emulator for one host frame and draws every window
+    pub fn frame(&mut self, ui: &Ui, frame: &mut Frame) {
+        if let Some(layout) = frame.layout.take() {
+            self.settings.layout = layout;
+        }
+        if frame.closing {
+            self.remember_window(frame);
+            self.save_settings();
+            return;
+        }
+
+        if let Some(path) = frame.dropped_files.pop() {
+            self.load_rom(&path);
+        }
+
+        logging::report_dropped();
+        self.hotkeys(ui);
+        self.menu_bar(ui);
+
+        if let Some(path) = self.browser.draw(ui) {
+            self.load_rom(&path);
+        }
+
+        if self.rom.is_some() {
+            self.update_keypad(ui);
+            self.scheduler.patches = self.cheats.patches();
+            self.run_frames(false);
+        }
+
+        if let Some(beeper) = &self.beeper {
+            let running = self.rom.is_some() && !self.scheduler.paused;
+            beeper.update(running && !self.muted && self.chip8.sound_timer > 0);
+        }
+
+        self.debug_window(ui);
+        self.watchers.memory_viewer.draw(ui, &mut self.chip8);
+        self.watchers
+            .profiler
+            .draw(ui, &self.chip8, self.scheduler.cycles_per_frame);
+        self.sprite_viewer
+            .draw(ui, &self.chip8, self.framebuffer.palette);
+        self.code_viewer.draw(ui, &self.chip8, &mut self.scheduler);
+        self.cheats.draw(ui, &self.chip8);
+        self.console.draw(ui);
+        #[cfg(feature = "scripting")]
+        self.scripts.draw(ui, &mut self.chip8, &mut self.scheduler);
+        self.emulator_window(ui, frame);
+        self.preferences_window(ui);
+    }
+
+    /// Keeps the window's size and position for next time, unless it's fullscreen
+    fn remember_window(&mut self, frame: &Frame) {
+        if self.fullscreen {
+            return;
+        }
+
+        let window = &mut self.settings.window;
+        [window.width, window.height] = frame.window_size;
+        window.x = frame.window_position.map(|p| p[0]);
+        window.y = frame.window_position.map(|p| p[1]);
+    }
+
+    /// Writes the settings file with everything that changed while running
+    fn save_settings(&mut self) {
+        let Some(path) = &self.settings_path else {
+            return;
+        };
+
+        self.settings.speed = self.scheduler.speed;
+        self.settings.rom_dir = self.browser.dir.clone();
+        self.settings.recent = self.browser.recent.clone();
+        self.settings.roms.retain(|_, o| *o != Overrides::default());
+
+        if let Err(e) = self.settings.save(path) {
+            error!(target: logging::FRONTEND, "failed to save settings: {:#}", e);
+        }
+    }
+
+    fn preferences_window(&mut self, ui: &Ui) {
+        if !self.preferences_open {
+            return;
+        }
+
+        let mut open = true;
+        ui.window("Preferences")
+            .opened(&mut open)
+            .size([380.0, 420.0], Condition::FirstUseEver)
+            .position([620.0, 20.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.text("Display");
+                let palette = self.settings.palette;
+                let mut on = palette.on.map(|c| c as f32 / 255.0);
+                let mut off = palette.off.map(|c| c as f32 / 255.0);
+                let on_changed = ui.color_edit3("Lit", &mut on);
+                let off_changed = ui.color_edit3("Unlit", &mut off);
+                if on_changed || off_changed {
+                    self.settings.palette = Palette {
+                        on: on.map(|c| (c * 255.0).round() as u8),
+                        off: off.map(|c| (c * 255.0).round() as u8),
+                    };
+                    // ROMs with their own colours keep them
+                    if self.profile.as_ref().is_none_or(|p| p.palette.is_none()) {
+                        self.framebuffer.palette = self.settings.palette;
+                    }
+                }
+
+                ui.separator();
+                ui.text("Input");
+                if ui.input_text("Keymap", &mut self.keymap_input).build() {
+                    match Keymap::parse(&self.keymap_input) {
+                        Ok(keymap) => {
+                            self.keymap = keymap;
+                            self.settings.keymap = self.keymap_input.clone();
+                            self.keymap_error = None;
+                        }
+                        Err(e) => self.keymap_error = Some(e.to_string()),
+                    }
+                }
+                ui.text_disabled("Host key for each CHIP-8 key, 0 through F");
+                if let Some(error) = &self.keymap_error {
+                    ui.text_colored(ERROR_COLOR, error);
+                }
+
+                ui.separator();
+                ui.text("Audio");
+                ui.disabled(self.beeper.is_none(), || {
+                    if ui.slider("Volume", 0.0, 1.0, &mut self.settings.volume) {
+                        if let Some(beeper) = &self.beeper {
+                            beeper.set_volume(self.settings.volume);
+                        }
+                    }
+                    ui.checkbox("Mute", &mut self.muted);
+                });
+                if self.beeper.is_none() {
+                    ui.text_disabled("No audio device");
+                }
+
+                ui.separator();
+                ui.text("Window");
+                ui.checkbox("Vsync", &mut self.settings.window.vsync);
+                ui.checkbox("Start fullscreen", &mut self.settings.window.fullscreen);
+                ui.slider("Font size", 8.0, 32.0, &mut self.settings.window.font_size);
+                ui.text_disabled("Window settings take effect after a restart");
+
+                ui.separator();
+                ui.text(format!(
+                    "Settings for {} ROMs changed in the ROM menu",
+                    self.settings.roms.len()
+                ));
+                if ui.button("Forget them") {
+                    self.settings.roms.clear();
+                    if self.rom.is_some() {
+                        self.reload_profile();
+                    }
+                }
+
+                ui.separator();
+                match &self.settings_path {
+                    Some(path) => {
+                        ui.text_disabled(format!("Saved to {} on exit", path.display()));
+                        if ui.button("Save now") {
+                            self.save_settings();
+                        }
+                    }
+                    None => ui.text_disabled("Settings aren't saved"),
+                }
+            });
+
+        self.preferences_open = open;
+    }
+
+    /// Ctrl+O load ROM, F5 pause, F6 frame advance, F7 step, F8 slow motion, F12 screenshot,
+    /// hold Tab to fast-forward
+    fn hotkeys(&mut self, ui: &Ui) {
+        if ui.io().key_ctrl && ui.is_key_pressed_no_repeat(Key::O) {
+            self.browser.open = true;
+        }
+
+        if ui.is_key_pressed_no_repeat(Key::F5) {
+            self.scheduler.paused = !self.scheduler.paused;
+        }
+
+        if ui.is_key_pressed(Key::F6) {
+            self.advance_frame();
+        }
+
+        if ui.is_key_pressed(Key::F7) {
+            self.step_instruction();
+        }
+
+        if ui.is_key_pressed_no_repeat(Key::F8) {
+            let current = SPEEDS
+                .iter()
+                .position(|s| *s == self.scheduler.speed)
+                .unwrap_or(0);
+            self.scheduler.speed = SPEEDS[(current + 1) % SPEEDS.len()];
+        }
+
+        if ui.is_key_pressed_no_repeat(Key::F12) {
+            self.screenshot();
+        }
+
+        self.scheduler.fast_forward = self.fast_forward_locked || ui.is_key_down(Key::Tab);
+    }
+
+    fn menu_bar(&mut self, ui: &Ui) {
+        ui.main_menu_bar(|| {
+            ui.menu("File", || {
+                if ui
+                    .menu_item_config("Load ROM...")
+                    .shortcut("Ctrl+O")
+                    .build()
+                {
+                    self.browser.open = true;
+                }
+
+                let mut picked = None;
+                ui.menu_with_enabled("Recent", !self.browser.recent.is_empty(), || {
+                    for path in &self.browser.recent {
+                        if ui.menu_item(path.display().to_string()) {
+                            picked = Some(path.clone());
+                        }
+                    }
+                });
+                if let Some(path) = picked {
+                    self.load_rom(&path);
+                }
+
+                if let Some(rom) = self.rom.clone() {
+                    if ui.menu_item("Reset") {
+                        self.load_rom(&rom);
+                    }
+                    if ui
+                        .menu_item_config("Save screenshot")
+                        .shortcut("F12")
+                        .build()
+                    {
+                        self.screenshot();
+                    }
+                }
+
+                ui.separator();
+                if ui.menu_item("Preferences...") {
+                    self.preferences_open = true;
+                }
+            });
+
+            ui.menu("Emulation", || {
+                let label = if self.scheduler.paused {
+                    "Resume"
+                } else {
+                    "Pause"
+                };
+                if ui.menu_item_config(label).shortcut("F5").build() {
+                    self.scheduler.paused = !self.scheduler.paused;
+                }
+
+                if ui.menu_item_config("Advance frame").shortcut("F6").build() {
+                    self.advance_frame();
+                }
+
+                if ui
+                    .menu_item_config("Step instruction")
+                    .shortcut("F7")
+                    .build()
+                {
+                    self.step_instruction();
+                }
+
+                ui.separator();
+
+                if ui
+                    .menu_item_config("Fast-forward")
+                    .shortcut("Hold Tab")
+                    .selected(self.fast_forward_locked)
+                    .build()
+                {
+                    self.fast_forward_locked = !self.fast_forward_locked;
+                }
+
+                ui.menu("Speed", || {
+                    for speed in SPEEDS {
+                        let label = format!("{}%", (speed * 100.0) as u32);
+                        if ui
+                            .menu_item_config(label)
+                            .selected(self.scheduler.speed == speed)
+                            .build()
+                        {
+                            self.scheduler.speed = speed;
+                        }
+                    }
+                });
+
+                ui.menu_item_config("Mute")
+                    .enabled(self.beeper.is_some())
+                    .build_with_ref(&mut self.muted);
+
+                if ui.slider("Cycles/frame", 1, 100, &mut self.scheduler.cycles_per_frame) {
+                    let cycles_per_frame = self.scheduler.cycles_per_frame;
+                    if let Some(overrides) = self.current_overrides() {
+                        overrides.cycles_per_frame = Some(cycles_per_frame);
+                    }
+                }
+            });
+
+            ui.menu("Debug", || {
+                ui.menu_item_config("Memory")
+                    .build_with_ref(&mut self.watchers.memory_viewer.open);
+                ui.menu_item_config("Sprites")
+                    .build_with_ref(&mut self.sprite_viewer.open);
+                ui.menu_item_config("Disassembly")
+                    .build_with_ref(&mut self.code_viewer.open);
+                ui.menu_item_config("Profiler")
+                    .build_with_ref(&mut self.watchers.profiler.open);
+                ui.menu_item_config("Cheats")
+                    .build_with_ref(&mut self.cheats.open);
+                ui.menu_item_config("Log")
+                    .build_with_ref(&mut self.console.open);
+                #[cfg(feature = "scripting")]
+                ui.menu_item_config("Script")
+                    .build_with_ref(&mut self.scripts.open);
+            });
+
+            self.rom_menu(ui);
+        });
+    }
+
+    /// Details of the running ROM and the settings it runs with
+    fn rom_menu(&mut self, ui: &Ui) {
+        let Some(profile) = self.profile.clone() else {
+            return;
+        };
+
+        ui.menu("ROM", || {
+            ui.text(profile.title.as_deref().unwrap_or("Unknown ROM"));
+            if !profile.authors.is_empty() {
+                ui.text(format!("by {}", profile.authors.join(", ")));
+            }
+            ui.text_disabled(format!("SHA-1 {}", profile.sha1));
+            if !profile.known {
+                ui.text_disabled("Not in the database, settings are a guess");
+            }
+            for note in &profile.notes {
+                ui.text_disabled(note);
+            }
+
+            ui.separator();
+
+            ui.menu("Platform", || {
+                for platform in PLATFORMS {
+                    if ui
+                        .menu_item_config(platform.name())
+                        .selected(profile.platform == platform)
+                        .build()
+                    {
+                        if let Some(overrides) = self.current_overrides() {
+                            *overrides = Overrides {
+                                platform: Some(platform),
+                                ..Default::default()
+                            };
+                        }
+                        self.reload_profile();
+                    }
+                }
+            });
+
+            ui.menu("Quirks", || {
+                let mut quirks = self.chip8.quirks;
+                let changed = [
+                    ui.checkbox("Shift VX in place", &mut quirks.shift),
+                    ui.checkbox(
+                        "FX55/FX65 increment I by X",
+                        &mut quirks.memory_increment_by_x,
+                    ),
+                    ui.checkbox(
+                        "FX55/FX65 leave I unchanged",
+                        &mut quirks.memory_leave_i_unchanged,
+                    ),
+                    ui.checkbox("Wrap sprites", &mut quirks.wrap),
+                    ui.checkbox("BXNN jumps to XNN + VX", &mut quirks.jump),
+                    ui.checkbox("Wait for vblank before drawing", &mut quirks.vblank),
+                    ui.checkbox("Logic ops reset VF", &mut quirks.logic),
+                    ui.checkbox("FX0A ends on key press", &mut quirks.key_press),
+                ];
+
+                if changed.contains(&true) {
+                    self.chip8.quirks = quirks;
+                    if let Some(overrides) = self.current_overrides() {
+                        overrides.quirks = Some(quirks);
+                    }
+                }
+            });
+
+            if !profile.keys.is_empty() {
+                ui.separator();
+                for (control, key) in &profile.keys {
+                    ui.text(format!("{}: key {:X}", control, key));
+                }
+            }
+
+            ui.separator();
+            if ui.menu_item("Use recommended settings") {
+                if let Some(overrides) = self.current_overrides() {
+                    *overrides = Overrides::default();
+                }
+                self.reload_profile();
+            }
+        });
+    }
+
+    /// Works out the profile of the current ROM again after its overrides changed
+    fn reload_profile(&mut self) {
+        let profile = self.profile_for(&self.rom_data);
+        self.apply_profile(&profile);
+        self.profile = Some(profile);
+    }
+
+    /// Runs one full frame, pausing if we weren't already
+    fn advance_frame(&mut self) {
+        if self.rom.is_none() {
+            return;
+        }
+        self.scheduler.paused = true;
+        self.run_frames(true);
+    }
+
+    /// Runs the frames due this host frame, or just one to advance by a frame
+    ///
+    /// The display filter sees every emulated frame as it finishes, so it fades at the same
+    /// rate whatever the refresh rate, speed or fast-forward
+    fn run_frames(&mut self, advance: bool) {
+        let (watchers, filter) = (&mut self.watchers, &mut self.filter);
+        #[cfg(feature = "scripting")]
+        let scripts = &mut self.scripts;
+        let mut run_frame = |scheduler: &mut Scheduler, chip8: &mut Chip8| {
+            let on_step = |pc, chip8: &Chip8| watchers.record(pc, chip8);
+            #[cfg(feature = "scripting")]
+            scripts.run_frame(chip8, scheduler, on_step);
+            #[cfg(not(feature = "scripting"))]
+            scheduler.run_frame_with(chip8, on_step);
+
+            // Unless a breakpoint stopped it partway
+            if scheduler.at_frame_start() {
+                filter.apply(&chip8.display);
+            }
+        };
+
+        if advance {
+            run_frame(&mut self.scheduler, &mut self.chip8);
+        } else {
+            self.scheduler.update_frames(&mut self.chip8, run_frame);
+        }
+    }
+
+    /// Saves the display as shown, filter and palette included, next to the ROM
+    fn screenshot(&mut self) {
+        let Some(rom) = &self.rom else {
+            return;
+        };
+
+        let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+        let path = (1..)
+            .map(|n| rom.with_file_name(format!("{}-{}.png", stem, n)))
+            .find(|path| !path.exists())
+            .unwrap();
+        match self
+            .framebuffer
+            .save_png(&path, self.scale.unwrap_or(SCREENSHOT_SCALE))
+        {
+            Ok(()) => info!(target: logging::FRONTEND, "saved {}", path.display()),
+            Err(e) => error!(target: logging::FRONTEND, "failed to save screenshot: {:#}", e),
+        }
+    }
+
+    /// Runs one instruction, pausing if we weren't already
+    fn step_instruction(&mut self) {
+        if self.rom.is_none() {
+            return;
+        }
+        self.scheduler.paused = true;
+        let watchers = &mut self.watchers;
+        self.scheduler
+            .step_instruction_with(&mut self.chip8, |pc, chip8| watchers.record(pc, chip8));
+        if self.scheduler.at_frame_start() {
+            self.filter.apply(&self.chip8.display);
+        }
+    }
+
+    fn debug_window(&mut self, ui: &Ui) {
+        ui.window("Debug Window")
+            .position([200.0, 500.0], Condition::FirstUseEver)
+            .size([240.0, 400.0], Condition::FirstUseEver)
+            .build(|| {
+                let chip8 = &mut self.chip8;
+
+                if self.scheduler.paused && self.rom.is_some() {
+                    edit_registers(ui, chip8, &mut self.register_error);
+                } else {
+                    let pc = format!("PC: {:#X}", chip8.pc);
+                    let sp = format!("SP: {:#X}", chip8.sp);
+                    let i = format!("I: {:#X}", chip8.i);
+                    let delay_timer = format!("DELAY TIMER: {}", chip8.delay_timer);
+                    let sound_timer = format!("SOUND TIMER: {}", chip8.sound_timer);
+                    let draw_flag = format!("DRAW: {}", chip8.draw_flag);
+                    ui.text(pc);
+                    ui.text(sp);
+                    ui.text(i);
+                    ui.text(draw_flag);
+                    ui.text(delay_timer);
+                    ui.text(sound_timer);
+
+                    for (i, reg) in chip8.v.iter().enumerate() {
+                        let register = format!("V[{:X}]: {:#X}", i, reg);
+                        ui.text(register);
+                    }
+                    ui.text_disabled("Pause to edit");
+                }
+
+                if let Some(place) = chip8.symbols.describe(chip8.pc) {
+                    ui.text(format!("IN: {}", place));
+                }
+                if let Some((number, text)) = chip8.symbols.source_line(chip8.pc) {
+                    ui.text_disabled(format!("{}: {}", number, text.trim()));
+                }
+
+                ui.separator();
+                let stack = chip8.stack();
+                ui.text(format!("STACK: {}/{}", stack.len(), STACK_SIZE));
+                if let Some(fault) = chip8.fault {
+                    ui.text_colored(ERROR_COLOR, fault.to_string());
+                } else if stack.len() >= STACK_WARNING {
+                    ui.text_colored([1.0, 0.8, 0.2, 1.0], "Stack nearly full");
+                }
+                if let Some(idle) = chip8.idle() {
+                    ui.text_disabled(format!("IDLE: {}", idle));
+                }
+                // Innermost call first, with the instruction it returns to
+                for (depth, &call) in stack.iter().enumerate().rev() {
+                    let ret = (call as usize + 2) % chip8.memory.len();
+                    let hi = chip8.memory[ret];
+                    let lo = chip8.memory[(ret + 1) % chip8.memory.len()];
+                    let opcode = u16::from_be_bytes([hi, lo]);
+                    let symbol = |addr| chip8.symbols.label(addr).map(str::to_string);
+                    let text = opcodes::format(opcode, symbol).unwrap_or_default();
+                    ui.text(format!(
+                        "{:X}: {:03X}  {:02X}{:02X}  {}",
+                        depth, ret, hi, lo, text
+                    ));
+                }
+
+                ui.separator();
+                ui.text("KEYPAD");
+                keypad(ui, chip8, &mut self.latched_keys);
+
+                ui.separator();
+                let mut mode = match self.filter.mode {
+                    FilterMode::Off => 0,
+                    FilterMode::Phosphor { .. } => 1,
+                    FilterMode::Or => 2,
+                };
+                ui.combo_simple_string("Filter", &mut mode, &["Off", "Phosphor", "OR"]);
+                if mode == 1 {
+                    ui.slider("Decay", 0.05, 1.0, &mut self.decay);
+                }
+                self.filter.mode = match mode {
+                    1 => FilterMode::Phosphor { decay: self.decay },
+                    2 => FilterMode::Or,
+                    _ => FilterMode::Off,
+                };
+                ui.checkbox("Integer scale", &mut self.framebuffer.integer_scale);
+            });
+    }
+
+    fn emulator_window(&mut self, ui: &Ui, frame: &mut Frame) {
+        // Partway through a frame, after stepping or at a breakpoint, show what it's drawn so far
+        let intensity = if self.scheduler.at_frame_start() && !self.filter.output().is_empty() {
+            self.filter.output()
+        } else {
+            self.filter.preview(&self.chip8.display)
+        };
+        self.framebuffer.fill(intensity, WIDTH, HEIGHT);
+        if let Err(e) = self.framebuffer.upload(frame.display, frame.renderer) {
+            error!(target: logging::DISPLAY, "failed to upload framebuffer: {}", e);
+        }
+
+        let title = if self.scheduler.paused {
+            "Chip8 Emualtor (paused)###Chip8 Emualtor"
+        } else if self.chip8.idle() == Some(Idle::Halted) {
+            "Chip8 Emualtor (program finished)###Chip8 Emualtor"
+        } else {
+            "Chip8 Emualtor###Chip8 Emualtor"
+        };
+
+        // Room for the display at the requested scale plus the title bar and padding
+        let size = match self.scale {
+            Some(scale) => [
+                (WIDTH as u32 * scale) as f32 + 16.0,
+                (HEIGHT as u32 * scale) as f32 + 36.0,
+            ],
+            None => [600.0, 340.0],
+        };
+
+        ui.window(title)
+            .size(size, Condition::FirstUseEver)
+            .position([0.0, 20.0], Condition::FirstUseEver)
+            .scroll_bar(false)
+            .scrollable(false)
+            .build(|| {
+                if let Some(error) = &self.load_error {
+                    ui.text_colored(ERROR_COLOR, error);
+                } else if self.rom.is_none() {
+                    ui.text("No ROM loaded, use File > Load ROM or drop one onto the window");
+                }
+
+                self.framebuffer.draw(ui);
+                #[cfg(feature = "scripting")]
+                self.scripts.draw_overlay(ui, WIDTH);
+            });
+    }
+}
+
+/// Input for a register that takes values up to `max`, in hex with `digits` digits or in decimal
+///
+/// Returns the new value once Enter is pressed, or why it was refused
+fn register_input(
+    ui: &Ui,
+    label: &str,
+    value: u16,
+    max: u16,
+    digits: Option<usize>,
+) -> Option<Result<u16, String>> {
+    let mut input = value;
+    let format = match digits {
+        Some(digits) => format!("%0{}X", digits),
+        None => "%u".to_string(),
+    };
+
+    ui.set_next_item_width(60.0);
+    let entered = ui
+        .input_scalar(label, &mut input)
+        .display_format(format)
+        .chars_hexadecimal(digits.is_some())
+        .enter_returns_true(true)
+        .build();
+
+    if !entered {
+        return None;
+    }
+    if input > max {
+        let max = match digits {
+            Some(digits) => format!("{:0digits$X}", max, digits = digits),
+            None => max.to_string(),
+        };
+        return Some(Err(format!("{} can't be above {}", label, max)));
+    }
+    Some(Ok(input))
+}
+
+/// Input widgets for every register, for changing them while paused
+fn edit_registers(ui: &Ui, chip8: &mut Chip8, error: &mut Option<String>) {
+    let mut edit = |label: &str, value: u16, max: u16, digits: Option<usize>| match register_input(
+        ui, label, value, max, digits,
+    ) {
+        Some(Ok(value)) => {
+            *error = None;
+            Some(value)
+        }
+        Some(Err(e)) => {
+            *error = Some(e);
+            None
+        }
+        None => None,
+    };
+
+    // The instruction at PC is two bytes, both have to be in memory
+    if let Some(pc) = edit("PC", chip8.pc, 0xFFE, Some(3)) {
+        chip8.pc = pc;
+    }
+    if let Some(i) = edit("I", chip8.i, 0xFFF, Some(3)) {
+        chip8.i = i;
+    }
+    if let Some(sp) = edit("SP", chip8.sp, STACK_SIZE as u16, None) {
+        chip8.sp = sp;
+    }
+    if let Some(dt) = edit("DELAY TIMER", chip8.delay_timer, 0xFF, None) {
+        chip8.delay_timer = dt;
+    }
+    if let Some(st) = edit("SOUND TIMER", chip8.sound_timer, 0xFF, None) {
+        chip8.sound_timer = st;
+    }
+    for reg in 0..16 {
+        let label = format!("V[{:X}]", reg);
+        if let Some(value) = edit(&label, chip8.v[reg] as u16, 0xFF, Some(2)) {
+            chip8.v[reg] = value as u8;
+        }
+    }
+
+    if let Some(error) = error {
+        ui.text_colored(ERROR_COLOR, error.as_str());
+    }
+}
+
+/// Shows which CHIP-8 keys are down, clicking one holds it down until it's clicked again
+fn keypad(ui: &Ui, chip8: &mut Chip8, latched: &mut [bool; 16]) {
+    for row in KEYPAD {
+        for (column, key) in row.into_iter().enumerate() {
+            if column > 0 {
+                ui.same_line();
+            }
+            let label = format!("{:X}{}", key, if latched[key] { "*" } else { "" });
+            if ui
+                .selectable_config(format!("{}##key{}", label, key))
+                .selected(chip8.is_key_down(key))
+                .size([24.0, 24.0])
+                .build()
+            {
+                latched[key] = !latched[key];
+                chip8.set_key(key, latched[key]);
+            }
+        }
+    }
+}
+
+/// imgui key for a letter or digit in a keymap
+fn imgui_key(c: char) -> Option<Key> {
+    const LETTERS: [Key; 26] = [
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+    ];
+    const DIGITS: [Key; 10] = [
+        Key::Alpha0,
+        Key::Alpha1,
+        Key::Alpha2,
+        Key::Alpha3,
+        Key::Alpha4,
+        Key::Alpha5,
+        Key::Alpha6,
+        Key::Alpha7,
+        Key::Alpha8,
+        Key::Alpha9,
+    ];
+
+    match c {
+        'a'..='z' => Some(LETTERS[c as usize - 'a' as usize]),
+        '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        _ => None,
+    }
+}
+
+/// Host key for a control named in the ROM database
+fn control_key(control: &str) -> Option<Key> {
+    match control {
+        "up" => Some(Key::UpArrow),
+        "down" => Some(Key::DownArrow),
+        "left" => Some(Key::LeftArrow),
+        "right" => Some(Key::RightArrow),
+        "a" => Some(Key::Space),
+        "b" => Some(Key::Enter),
+        _ => None,
+    }
+}
@@ -0,0 +1,363 @@
+use crate::chip::START;
+use crate::opcodes::{Opcode, Operand, OPCODES};
+use anyhow::{anyhow, bail, Error};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+///End of memory
+const END: usize = 0x1000;
+
+///Output of the assembler
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+
+    ///Labels and their addresses, in source order
+    pub labels: Vec<(String, u16)>,
+
+    ///Address of every line that produced bytes and its line number, starting at 1
+    pub lines: Vec<(u16, usize)>,
+}
+
+impl Assembly {
+    /// Symbol map for the debugger, one `label ADDR NAME` or `line ADDR NUMBER` record per line
+    pub fn map(&self) -> String {
+        let mut map = String::new();
+
+        for (name, addr) in &self.labels {
+            let _ = writeln!(map, "label 0x{:03X} {}", addr, name);
+        }
+        for (addr, line) in &self.lines {
+            let _ = writeln!(map, "line 0x{:03X} {}", addr, line);
+        }
+
+        map
+    }
+}
+
+///Instruction or directive waiting for labels to be known
+struct Statement<'a> {
+    line: usize,
+    addr: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+///Why operands didn't fit an instruction
+enum Mismatch {
+    ///Wrong kind of operand, another instruction with the same mnemonic might fit
+    Shape,
+
+    ///Right kind but a bad value
+    Invalid(String),
+}
+
+/// Parses `0x1F`, `$1F`, `0b11111` or `31`
+pub fn parse_number(token: &str) -> Option<u32> {
+    let token = token.to_ascii_lowercase();
+
+    if let Some(hex) = token.strip_prefix("0x").or_else(|| token.strip_prefix('$')) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = token.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        token.parse().ok()
+    }
+}
+
+fn parse_register(token: &str) -> Option<u16> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u16),
+        _ => None,
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `token` is a fixed operand word of some instruction, such as `DT`
+fn is_literal(token: &str) -> bool {
+    OPCODES
+        .iter()
+        .flat_map(|o| o.operands)
+        .any(|o| matches!(o, Operand::Literal(word) if token.eq_ignore_ascii_case(word)))
+}
+
+/// Value of a number or label
+fn value(token: &str, labels: &HashMap<String, u16>, max: u32) -> Result<u16, String> {
+    let value = match parse_number(token) {
+        Some(value) => value,
+        None => match labels.get(token) {
+            Some(addr) => *addr as u32,
+            None if is_label(token) => return Err(format!("unknown label {:?}", token)),
+            None => return Err(format!("invalid number {:?}", token)),
+        },
+    };
+
+    if value > max {
+        return Err(format!("{} doesn't fit in {:#X}", token, max));
+    }
+    Ok(value as u16)
+}
+
+/// Operand of `LD I, LONG NNNN`, XO-CHIP's four byte load
+fn long_operand<'a>(mnemonic: &str, operands: &[&'a str]) -> Option<&'a str> {
+    match operands {
+        [i, long] if mnemonic == "LD" && i.eq_ignore_ascii_case("I") => {
+            let (word, operand) = long.split_once(char::is_whitespace)?;
+            word.eq_ignore_ascii_case("LONG").then(|| operand.trim())
+        }
+        _ => None,
+    }
+}
+
+/// Opcode for an instruction if the operands fit it
+fn fit(
+    instruction: &Opcode,
+    operands: &[&str],
+    labels: &HashMap<String, u16>,
+) -> Result<u16, Mismatch> {
+    let mut opcode = instruction.base;
+
+    for (&kind, token) in instruction.operands.iter().zip(operands) {
+        let field = match kind {
+            Operand::Literal(word) if token.eq_ignore_ascii_case(word) => 0,
+            Operand::Literal(_) => return Err(Mismatch::Shape),
+            Operand::X | Operand::Y => parse_register(token).ok_or(Mismatch::Shape)?,
+            _ if parse_register(token).is_some() || token.starts_with('[') => {
+                return Err(Mismatch::Shape)
+            }
+            _ => match value(token, labels, kind.max() as u32) {
+                Ok(value) => value,
+                // Words like DT or K are operands of another instruction
+                Err(_) if is_literal(token) => return Err(Mismatch::Shape),
+                Err(e) => return Err(Mismatch::Invalid(e)),
+            },
+        };
+        opcode |= kind.insert(field);
+    }
+
+    Ok(opcode)
+}
+
+fn encode(statement: &Statement, labels: &HashMap<String, u16>) -> Result<Vec<u8>, String> {
+    let operands = &statement.operands;
+
+    match statement.mnemonic.as_str() {
+        "DB" => {
+            return operands
+                .iter()
+                .map(|token| value(token, labels, 0xFF).map(|v| v as u8))
+                .collect()
+        }
+        "DW" => {
+            let mut bytes = Vec::new();
+            for token in operands {
+                bytes.extend(value(token, labels, 0xFFFF)?.to_be_bytes());
+            }
+            return Ok(bytes);
+        }
+        _ => {}
+    }
+
+    if let Some(operand) = long_operand(&statement.mnemonic, operands) {
+        let addr = value(operand, labels, 0xFFFF)?;
+        let [hi, lo] = addr.to_be_bytes();
+        return Ok(vec![0xF0, 0x00, hi, lo]);
+    }
+
+    let candidates: Vec<&Opcode> = OPCODES
+        .iter()
+        .filter(|o| o.mnemonic == statement.mnemonic && o.operands.len() == operands.len())
+        .collect();
+
+    if !OPCODES.iter().any(|o| o.mnemonic == statement.mnemonic) {
+        return Err(format!("unknown instruction {}", statement.mnemonic));
+    }
+
+    let mut invalid = None;
+    for instruction in candidates {
+        match fit(instruction, operands, labels) {
+            Ok(opcode) => return Ok(opcode.to_be_bytes().to_vec()),
+            Err(Mismatch::Invalid(e)) => invalid = invalid.or(Some(e)),
+            Err(Mismatch::Shape) => {}
+        }
+    }
+
+    Err(invalid.unwrap_or_else(|| {
+        format!(
+            "invalid operands for {}: {}",
+            statement.mnemonic,
+            operands.join(", ")
+        )
+    }))
+}
+
+/// Assembles Cowgod style mnemonics, the same syntax the disassembler writes
+///
+/// Lines hold an optional `label:`, then an instruction, `DB` bytes or `DW` words.
+/// Comments start with `;`
+pub fn assemble(source: &str) -> Result<Assembly, Error> {
+    let mut assembly = Assembly::default();
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut addr = START;
+
+    // First pass finds where everything goes
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut text = line.split(';').next().unwrap_or_default().trim();
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                bail!("line {}: invalid label {:?}", number, label);
+            }
+            if labels.insert(label.to_string(), addr as u16).is_some() {
+                bail!("line {}: label {:?} defined twice", number, label);
+            }
+            assembly.labels.push((label.to_string(), addr as u16));
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let operands: Vec<&str> = if rest.trim().is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+
+        let size = match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ if long_operand(&mnemonic, &operands).is_some() => 4,
+            _ => 2,
+        };
+
+        statements.push(Statement {
+            line: number,
+            addr,
+            mnemonic,
+            operands,
+        });
+
+        addr += size;
+        if addr > END {
+            bail!("line {}: program doesn't fit in memory", number);
+        }
+    }
+
+    // Second pass fills in the labels
+    for statement in &statements {
+        let bytes =
+            encode(statement, &labels).map_err(|e| anyhow!("line {}: {}", statement.line, e))?;
+        if !bytes.is_empty() {
+            assembly.lines.push((statement.addr as u16, statement.line));
+        }
+        assembly.rom.extend(bytes);
+    }
+
+    Ok(assembly)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn numbers_in_every_base() {
+        assert_eq!(parse_number("0x1F"), Some(31));
+        assert_eq!(parse_number("$1f"), Some(31));
+        assert_eq!(parse_number("0b11111"), Some(31));
+        assert_eq!(parse_number("31"), Some(31));
+        assert_eq!(parse_number("1F"), None);
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let assembly = assemble(
+            "start: CALL sprite  ; forwards\n\
+             JP start\n\
+             sprite:\n\
+             LD I, sprite\n\
+             RET",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.rom,
+            [0x22, 0x04, 0x12, 0x00, 0xA2, 0x04, 0x00, 0xEE]
+        );
+        assert_eq!(
+            assembly.labels,
+            [("start".to_string(), 0x200), ("sprite".to_string(), 0x204)]
+        );
+        assert_eq!(
+            assembly.lines,
+            [(0x200, 1), (0x202, 2), (0x204, 4), (0x206, 5)]
+        );
+    }
+
+    #[test]
+    fn directives_and_long_loads() {
+        let assembly = assemble(
+            "data: DB 1, 0x02, $FF\n\
+             DW 0x1234, data\n\
+             LD I, LONG 0x1234",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.rom,
+            [1, 2, 0xFF, 0x12, 0x34, 0x02, 0x00, 0xF0, 0x00, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn mnemonics_pick_the_instruction_their_operands_fit() {
+        let assembly =
+            assemble("cls\nLD V1, 0x20\nLD V1, V2\nLD V1, DT\nLD DT, V1\nADD I, V3").unwrap();
+        assert_eq!(
+            assembly.rom,
+            [0x00, 0xE0, 0x61, 0x20, 0x81, 0x20, 0xF1, 0x07, 0xF1, 0x15, 0xF3, 0x1E]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(error("CLS\nFOO V1"), "line 2: unknown instruction FOO");
+        assert_eq!(error("LD V1, 0x100"), "line 1: 0x100 doesn't fit in 0xFF");
+        assert_eq!(error("JP nowhere"), "line 1: unknown label \"nowhere\"");
+        assert_eq!(error("LD V1, 12z"), "line 1: invalid number \"12z\"");
+        assert_eq!(error("CLS V1"), "line 1: invalid operands for CLS: V1");
+        assert_eq!(error("a:\na: CLS"), "line 2: label \"a\" defined twice");
+        assert_eq!(error("1a: CLS"), "line 1: invalid label \"1a\"");
+        assert_eq!(error("DB 0x100"), "line 1: 0x100 doesn't fit in 0xFF");
+    }
+
+    #[test]
+    fn programs_must_fit_in_memory() {
+        let source = "CLS\n".repeat((END - START) / 2);
+        assert!(assemble(&source).is_ok());
+        assert_eq!(
+            error(&(source + "CLS")),
+            format!(
+                "line {}: program doesn't fit in memory",
+                (END - START) / 2 + 1
+            )
+        );
+    }
+}
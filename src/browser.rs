@@ -0,0 +1,212 @@
+use crate::romdb::{self, rom_hash};
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+///File extensions listed by the browser
+const ROM_EXTENSIONS: &[&str] = &["ch8", "c8", "rom", "sc8", "xo8"];
+
+///How many recently loaded ROMs to remember
+const MAX_RECENT: usize = 10;
+
+///ROM file found in the browsed directory
+#[derive(Debug, Clone)]
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha1: String,
+    pub title: Option<String>,
+}
+
+///Window for picking a ROM to load
+#[derive(Debug)]
+pub struct RomBrowser {
+    pub open: bool,
+    pub dir: PathBuf,
+
+    ///Most recently loaded ROM first
+    pub recent: Vec<PathBuf>,
+
+    ///Contents of the path text box
+    dir_input: String,
+
+    subdirs: Vec<PathBuf>,
+    entries: Vec<RomEntry>,
+    error: Option<String>,
+}
+
+impl RomBrowser {
+    pub fn new(dir: &Path) -> Self {
+        let mut browser = RomBrowser {
+            open: false,
+            dir: dir.to_path_buf(),
+            recent: Vec::new(),
+            dir_input: String::new(),
+            subdirs: Vec::new(),
+            entries: Vec::new(),
+            error: None,
+        };
+        browser.refresh();
+        browser
+    }
+
+    /// Moves to `dir` and lists it
+    pub fn change_dir(&mut self, dir: &Path) {
+        self.dir = dir.to_path_buf();
+        self.refresh();
+    }
+
+    /// Lists the current directory again
+    pub fn refresh(&mut self) {
+        self.dir_input = self.dir.display().to_string();
+        self.subdirs.clear();
+        self.entries.clear();
+        self.error = None;
+
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                self.error = Some(format!("{}: {}", self.dir.display(), e));
+                return;
+            }
+        };
+
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+
+            if path.is_dir() {
+                self.subdirs.push(path);
+                continue;
+            }
+
+            let is_rom = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+            if !is_rom {
+                continue;
+            }
+
+            if let Ok(rom) = fs::read(&path) {
+                let sha1 = rom_hash(&rom);
+                self.entries.push(RomEntry {
+                    title: romdb::bundled()
+                        .lookup(&sha1)
+                        .map(|(program, _)| program.title.clone()),
+                    size: rom.len() as u64,
+                    sha1,
+                    path,
+                });
+            }
+        }
+
+        self.subdirs.sort();
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    /// Remembers a ROM that was just loaded
+    pub fn add_recent(&mut self, path: &Path) {
+        self.recent.retain(|p| p != path);
+        self.recent.insert(0, path.to_path_buf());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    /// Draws the browser if it is open
+    ///
+    /// Returns the ROM the user picked, if any
+    pub fn draw(&mut self, ui: &Ui) -> Option<PathBuf> {
+        if !self.open {
+            return None;
+        }
+
+        let mut picked = None;
+        let mut change_dir = None;
+        let mut open = self.open;
+
+        ui.window("Load ROM")
+            .opened(&mut open)
+            .size([520.0, 400.0], Condition::FirstUseEver)
+            .position([620.0, 20.0], Condition::FirstUseEver)
+            .build(|| {
+                if ui
+                    .input_text("##dir", &mut self.dir_input)
+                    .enter_returns_true(true)
+                    .build()
+                {
+                    change_dir = Some(PathBuf::from(&self.dir_input));
+                }
+                ui.same_line();
+                if ui.button("Up") {
+                    if let Some(parent) = self.dir.parent() {
+                        change_dir = Some(parent.to_path_buf());
+                    }
+                }
+                ui.same_line();
+                if ui.button("Refresh") {
+                    change_dir = Some(self.dir.clone());
+                }
+
+                if let Some(error) = &self.error {
+                    ui.text_colored(ERROR_COLOR, error);
+                }
+
+                for dir in &self.subdirs {
+                    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+                    if ui.selectable(format!("[{}]", name)) {
+                        change_dir = Some(dir.clone());
+                    }
+                }
+
+                if let Some(_table) = ui.begin_table_header_with_flags(
+                    "roms",
+                    [
+                        TableColumnSetup::new("File"),
+                        TableColumnSetup::new("Title"),
+                        TableColumnSetup::new("Size"),
+                        TableColumnSetup::new("SHA-1"),
+                    ],
+                    TableFlags::ROW_BG | TableFlags::RESIZABLE,
+                ) {
+                    for entry in &self.entries {
+                        ui.table_next_row();
+
+                        ui.table_next_column();
+                        let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+                        if ui.selectable_config(name).span_all_columns(true).build() {
+                            picked = Some(entry.path.clone());
+                        }
+
+                        ui.table_next_column();
+                        ui.text(entry.title.as_deref().unwrap_or("-"));
+
+                        ui.table_next_column();
+                        ui.text(format!("{} B", entry.size));
+
+                        ui.table_next_column();
+                        ui.text(&entry.sha1[..8]);
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text(&entry.sha1);
+                        }
+                    }
+                }
+
+                if !self.recent.is_empty() {
+                    ui.separator();
+                    ui.text("Recent");
+                    for path in &self.recent {
+                        if ui.selectable(path.display().to_string()) {
+                            picked = Some(path.clone());
+                        }
+                    }
+                }
+            });
+
+        if let Some(dir) = change_dir {
+            self.change_dir(&dir);
+        }
+
+        self.open = open && picked.is_none();
+        picked
+    }
+}
@@ -0,0 +1,254 @@
+use crate::cheats::{Cheat, CheatFile, Comparison, Patch, Search};
+use crate::chip::Chip8;
+use crate::logging;
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+use log::warn;
+use std::path::PathBuf;
+
+///Candidates listed in the search results, narrow it down further to see them
+const MAX_RESULTS: usize = 200;
+
+///Window for finding values in memory and freezing them, cheats are kept per ROM
+#[derive(Debug)]
+pub struct CheatPanel {
+    pub open: bool,
+
+    file: CheatFile,
+
+    ///Where the cheats are saved, not at all if unset
+    path: Option<PathBuf>,
+
+    ///SHA-1 of the running ROM
+    rom: Option<String>,
+
+    search: Search,
+
+    ///Value for the equals search
+    value: u8,
+
+    error: Option<String>,
+}
+
+impl CheatPanel {
+    /// Loads the cheats saved at `path`
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let file = match &path {
+            Some(path) => CheatFile::load(path).unwrap_or_else(|e| {
+                warn!(target: logging::FRONTEND, "ignoring cheats: {:#}", e);
+                CheatFile::default()
+            }),
+            None => CheatFile::default(),
+        };
+
+        CheatPanel {
+            open: false,
+            file,
+            path,
+            rom: None,
+            search: Search::default(),
+            value: 0,
+            error: None,
+        }
+    }
+
+    /// Switches to the cheats of a newly loaded ROM
+    pub fn load_rom(&mut self, sha1: &str) {
+        if self.rom.as_deref() != Some(sha1) {
+            self.search = Search::default();
+        }
+        self.rom = Some(sha1.to_string());
+    }
+
+    fn cheats(&self) -> &[Cheat] {
+        self.rom
+            .as_ref()
+            .and_then(|sha1| self.file.roms.get(sha1))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// What the enabled cheats of the running ROM freeze
+    pub fn patches(&self) -> Vec<Patch> {
+        self.cheats()
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .map(|cheat| Patch {
+                location: cheat.location,
+                value: cheat.value,
+            })
+            .collect()
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        self.error = self.file.save(path).err().map(|e| format!("{:#}", e));
+    }
+
+    /// Draws the panel if it is open
+    pub fn draw(&mut self, ui: &Ui, chip8: &Chip8) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window("Cheats")
+            .opened(&mut open)
+            .size([420.0, 460.0], Condition::FirstUseEver)
+            .position([620.0, 80.0], Condition::FirstUseEver)
+            .build(|| {
+                let Some(sha1) = self.rom.clone() else {
+                    ui.text_disabled("No ROM loaded");
+                    return;
+                };
+
+                if let Some(error) = &self.error {
+                    ui.text_colored(ERROR_COLOR, error);
+                }
+
+                self.cheat_list(ui, &sha1);
+                ui.separator();
+                self.search_controls(ui, chip8, &sha1);
+            });
+        self.open = open;
+    }
+
+    fn cheat_list(&mut self, ui: &Ui, sha1: &str) {
+        let cheats = self.file.roms.entry(sha1.to_string()).or_default();
+        if cheats.is_empty() {
+            ui.text_disabled("No cheats, find a value below and freeze it");
+            return;
+        }
+
+        let mut changed = false;
+        let mut removed = None;
+        for (index, cheat) in cheats.iter_mut().enumerate() {
+            let _id = ui.push_id_usize(index);
+
+            changed |= ui.checkbox("##enabled", &mut cheat.enabled);
+            ui.same_line();
+            ui.text(format!("{:>3}", cheat.location));
+
+            ui.same_line();
+            ui.set_next_item_width(30.0);
+            ui.input_scalar("##value", &mut cheat.value)
+                .display_format("%02X")
+                .chars_hexadecimal(true)
+                .build();
+            changed |= ui.is_item_deactivated_after_edit();
+
+            ui.same_line();
+            ui.set_next_item_width(200.0);
+            ui.input_text("##name", &mut cheat.name)
+                .hint("Name")
+                .build();
+            changed |= ui.is_item_deactivated_after_edit();
+
+            ui.same_line();
+            if ui.small_button("x") {
+                removed = Some(index);
+            }
+        }
+
+        if let Some(index) = removed {
+            cheats.remove(index);
+            changed = true;
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    fn search_controls(&mut self, ui: &Ui, chip8: &Chip8, sha1: &str) {
+        if ui.button("New search") {
+            self.search.start(chip8);
+        }
+        if !self.search.is_started() {
+            ui.same_line();
+            ui.text_disabled("Snapshots memory and V0-VF");
+            return;
+        }
+        ui.same_line();
+        ui.text(format!("{} candidates", self.search.candidates.len()));
+
+        let mut comparison = None;
+        ui.set_next_item_width(30.0);
+        ui.input_scalar("##equals", &mut self.value)
+            .display_format("%02X")
+            .chars_hexadecimal(true)
+            .build();
+        ui.same_line();
+        if ui.button("Equals") {
+            comparison = Some(Comparison::Equals(self.value));
+        }
+        for (label, option) in [
+            ("Increased", Comparison::Increased),
+            ("Decreased", Comparison::Decreased),
+            ("Unchanged", Comparison::Unchanged),
+        ] {
+            ui.same_line();
+            if ui.button(label) {
+                comparison = Some(option);
+            }
+        }
+        if let Some(comparison) = comparison {
+            self.search.narrow(chip8, comparison);
+        }
+
+        if self.search.candidates.len() > MAX_RESULTS {
+            ui.text_disabled("Keep narrowing to list the candidates");
+            return;
+        }
+
+        let Some(_table) = ui.begin_table_header_with_flags(
+            "candidates",
+            [
+                TableColumnSetup::new("Location"),
+                TableColumnSetup::new("Before"),
+                TableColumnSetup::new("Now"),
+                TableColumnSetup::new(""),
+            ],
+            TableFlags::ROW_BG | TableFlags::SCROLL_Y,
+        ) else {
+            return;
+        };
+
+        let mut frozen = None;
+        for &location in &self.search.candidates {
+            let _id = ui.push_id(location.to_string());
+            let now = location.read(chip8);
+            ui.table_next_row();
+
+            ui.table_next_column();
+            ui.text(location.to_string());
+
+            ui.table_next_column();
+            if let Some(before) = self.search.previous(location) {
+                ui.text(format!("{:02X}", before));
+            }
+
+            ui.table_next_column();
+            ui.text(format!("{:02X}", now));
+
+            ui.table_next_column();
+            if ui.small_button("Freeze") {
+                frozen = Some(Cheat {
+                    name: String::new(),
+                    location,
+                    value: now,
+                    enabled: true,
+                });
+            }
+        }
+
+        if let Some(cheat) = frozen {
+            self.file
+                .roms
+                .entry(sha1.to_string())
+                .or_default()
+                .push(cheat);
+            self.save();
+        }
+    }
+}
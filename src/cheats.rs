@@ -0,0 +1,361 @@
+use crate::chip::Chip8;
+use anyhow::{anyhow, bail, Context, Error};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+///A byte of memory or a V register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Location {
+    Memory(u16),
+    Register(u8),
+}
+
+impl Location {
+    /// Parses `3F1` for memory or `V5` for a register
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.strip_prefix(['V', 'v']) {
+            Some(x) if x.len() == 1 => u8::from_str_radix(x, 16).ok().map(Location::Register),
+            _ => u16::from_str_radix(text.trim_start_matches("0x"), 16)
+                .ok()
+                .filter(|addr| *addr <= 0xFFF)
+                .map(Location::Memory),
+        }
+    }
+
+    pub fn read(self, chip8: &Chip8) -> u8 {
+        match self {
+            Location::Memory(addr) => chip8.memory[addr as usize % chip8.memory.len()],
+            Location::Register(x) => chip8.v[x as usize & 0xF],
+        }
+    }
+
+    pub fn write(self, chip8: &mut Chip8, value: u8) {
+        match self {
+            Location::Memory(addr) => {
+                let len = chip8.memory.len();
+                chip8.memory[addr as usize % len] = value;
+            }
+            Location::Register(x) => chip8.v[x as usize & 0xF] = value,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Memory(addr) => write!(f, "{:03X}", addr),
+            Location::Register(x) => write!(f, "V{:X}", x),
+        }
+    }
+}
+
+///A value written back at the start of every frame, see `Scheduler::patches`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Patch {
+    pub location: Location,
+    pub value: u8,
+}
+
+impl Patch {
+    pub fn apply(&self, chip8: &mut Chip8) {
+        self.location.write(chip8, self.value);
+    }
+}
+
+///A location frozen to a value while enabled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub location: Location,
+    pub value: u8,
+    pub enabled: bool,
+}
+
+///Cheats for every ROM, by SHA-1
+///
+///Saved as text, a `[SHA-1]` line for each ROM followed by `on LOCATION VALUE NAME` or
+///`off ...` lines, where LOCATION is a hex address or `V0` to `VF` and VALUE is a hex byte
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheatFile {
+    pub roms: BTreeMap<String, Vec<Cheat>>,
+}
+
+impl CheatFile {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut file = CheatFile::default();
+        let mut rom: Option<String> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(sha1) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let sha1 = sha1.trim().to_ascii_lowercase();
+                file.roms.entry(sha1.clone()).or_default();
+                rom = Some(sha1);
+                continue;
+            }
+
+            let Some(sha1) = &rom else {
+                bail!("line {}: cheat before the first [SHA-1] line", number);
+            };
+
+            let mut fields = line.splitn(4, char::is_whitespace);
+            let enabled = match fields.next() {
+                Some("on") => true,
+                Some("off") => false,
+                other => bail!("line {}: expected on or off, got {:?}", number, other),
+            };
+            let location = fields.next().unwrap_or_default();
+            let location = Location::parse(location)
+                .ok_or_else(|| anyhow!("line {}: invalid location {:?}", number, location))?;
+            let value = fields.next().unwrap_or_default();
+            let value = u8::from_str_radix(value.trim_start_matches("0x"), 16)
+                .map_err(|_| anyhow!("line {}: invalid value {:?}", number, value))?;
+            let name = fields.next().unwrap_or_default().trim().to_string();
+
+            file.roms.entry(sha1.clone()).or_default().push(Cheat {
+                name,
+                location,
+                value,
+                enabled,
+            });
+        }
+
+        Ok(file)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# Cheats by ROM SHA-1: on|off LOCATION VALUE NAME\n");
+
+        for (sha1, cheats) in &self.roms {
+            if cheats.is_empty() {
+                continue;
+            }
+            let _ = writeln!(text, "\n[{}]", sha1);
+            for cheat in cheats {
+                let state = if cheat.enabled { "on" } else { "off" };
+                let line = format!(
+                    "{} {} {:02X} {}",
+                    state, cheat.location, cheat.value, cheat.name
+                );
+                let _ = writeln!(text, "{}", line.trim_end());
+            }
+        }
+
+        text
+    }
+
+    /// Reads a cheat file, an empty one if it doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(CheatFile::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        CheatFile::parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+
+        fs::write(path, self.to_text())
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+///How a candidate's value has to compare with the last snapshot to stay in the search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equals(u8),
+    Increased,
+    Decreased,
+    Unchanged,
+}
+
+impl Comparison {
+    fn matches(self, before: u8, now: u8) -> bool {
+        match self {
+            Comparison::Equals(value) => now == value,
+            Comparison::Increased => now > before,
+            Comparison::Decreased => now < before,
+            Comparison::Unchanged => now == before,
+        }
+    }
+}
+
+///Memory and registers at one moment
+#[derive(Debug, Clone)]
+struct Snapshot {
+    memory: Vec<u8>,
+    v: [u8; 16],
+}
+
+impl Snapshot {
+    fn take(chip8: &Chip8) -> Self {
+        Snapshot {
+            memory: chip8.memory.to_vec(),
+            v: chip8.v,
+        }
+    }
+
+    fn get(&self, location: Location) -> u8 {
+        match location {
+            Location::Memory(addr) => self.memory[addr as usize % self.memory.len()],
+            Location::Register(x) => self.v[x as usize & 0xF],
+        }
+    }
+}
+
+///Narrows down where a game keeps a value by how it changes between frames
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    ///Values when the search started or was last narrowed, None before a search starts
+    snapshot: Option<Snapshot>,
+
+    pub candidates: Vec<Location>,
+}
+
+impl Search {
+    /// Starts over with every byte of memory and every register as a candidate
+    pub fn start(&mut self, chip8: &Chip8) {
+        self.candidates = (0..chip8.memory.len() as u16)
+            .map(Location::Memory)
+            .chain((0..16).map(Location::Register))
+            .collect();
+        self.snapshot = Some(Snapshot::take(chip8));
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// Keeps the candidates whose value now compares with the snapshot as asked, then snapshots again
+    pub fn narrow(&mut self, chip8: &Chip8, comparison: Comparison) {
+        let Some(snapshot) = &self.snapshot else {
+            return;
+        };
+
+        self.candidates
+            .retain(|&location| comparison.matches(snapshot.get(location), location.read(chip8)));
+        self.snapshot = Some(Snapshot::take(chip8));
+    }
+
+    /// Value of a candidate in the last snapshot
+    pub fn previous(&self, location: Location) -> Option<u8> {
+        self.snapshot.as_ref().map(|s| s.get(location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_parse_memory_and_registers() {
+        assert_eq!(Location::parse("3F1"), Some(Location::Memory(0x3F1)));
+        assert_eq!(Location::parse("0x3F1"), Some(Location::Memory(0x3F1)));
+        assert_eq!(Location::parse("V5"), Some(Location::Register(5)));
+        assert_eq!(Location::parse("vf"), Some(Location::Register(0xF)));
+        assert_eq!(Location::parse("1000"), None);
+        assert_eq!(Location::parse("V10"), None);
+        assert_eq!(Location::parse("VG"), None);
+        assert_eq!(Location::parse(""), None);
+    }
+
+    #[test]
+    fn cheat_files_survive_a_round_trip() {
+        let text = "# comment\n[ABCDEF]\non 3F1 09 Lives\noff VA ff\n\n[123456]\n";
+        let file = CheatFile::parse(text).unwrap();
+        assert_eq!(
+            file.roms["abcdef"],
+            vec![
+                Cheat {
+                    name: "Lives".to_string(),
+                    location: Location::Memory(0x3F1),
+                    value: 9,
+                    enabled: true,
+                },
+                Cheat {
+                    name: String::new(),
+                    location: Location::Register(0xA),
+                    value: 0xFF,
+                    enabled: false,
+                },
+            ]
+        );
+        assert!(file.roms["123456"].is_empty());
+
+        let reparsed = CheatFile::parse(&file.to_text()).unwrap();
+        assert_eq!(reparsed.roms["abcdef"], file.roms["abcdef"]);
+        // ROMs without cheats aren't written
+        assert!(!reparsed.roms.contains_key("123456"));
+    }
+
+    #[test]
+    fn cheat_file_errors_name_the_line() {
+        for (text, error) in [
+            ("on 3F1 09", "line 1: cheat before the first [SHA-1] line"),
+            ("[ab]\nmaybe 3F1 09", "line 2: expected on or off"),
+            ("[ab]\non 1000 09", "line 2: invalid location"),
+            ("[ab]\non 3F1 100", "line 2: invalid value"),
+        ] {
+            let e = CheatFile::parse(text).unwrap_err().to_string();
+            assert!(e.starts_with(error), "{}", e);
+        }
+    }
+
+    #[test]
+    fn searches_narrow_by_how_values_change() {
+        let mut chip8 = Chip8::new();
+        let mut search = Search::default();
+        search.start(&chip8);
+        assert!(search.is_started());
+
+        chip8.memory[0x300] = 5;
+        chip8.memory[0x301] = 5;
+        chip8.v[2] = 5;
+        search.narrow(&chip8, Comparison::Increased);
+        assert_eq!(
+            search.candidates,
+            vec![
+                Location::Memory(0x300),
+                Location::Memory(0x301),
+                Location::Register(2),
+            ]
+        );
+        assert_eq!(search.previous(Location::Memory(0x300)), Some(5));
+
+        chip8.memory[0x300] = 4;
+        chip8.v[2] = 6;
+        search.narrow(&chip8, Comparison::Unchanged);
+        assert_eq!(search.candidates, vec![Location::Memory(0x301)]);
+
+        chip8.memory[0x301] = 3;
+        let mut decreased = search.clone();
+        decreased.narrow(&chip8, Comparison::Decreased);
+        assert_eq!(decreased.candidates, vec![Location::Memory(0x301)]);
+
+        search.narrow(&chip8, Comparison::Equals(4));
+        assert!(search.candidates.is_empty());
+    }
+
+    #[test]
+    fn narrowing_before_starting_does_nothing() {
+        let mut search = Search::default();
+        search.narrow(&Chip8::new(), Comparison::Unchanged);
+        assert!(!search.is_started());
+        assert!(search.candidates.is_empty());
+    }
+}
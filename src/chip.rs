@@ -1,13 +1,33 @@
-use crate::sound;
+use crate::logging;
+use crate::symbols::Symbols;
+use anyhow::bail;
 use anyhow::Error;
 use anyhow::Result;
-use rand::Rng;
-use raylib::prelude::RaylibDrawHandle;
-use raylib::prelude::*;
+use log::{debug, error, warn};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::path::Path;
 
-const FONT: &[u8] = &[
+///Width of the display in pixels
+pub const WIDTH: usize = 64;
+
+///Height of the display in pixels
+pub const HEIGHT: usize = 32;
+
+///Nesting depth of subroutine calls
+pub const STACK_SIZE: usize = 16;
+
+///Where programs are loaded
+pub const START: usize = 0x200;
+
+///Where `load_fontset` puts the font
+pub const FONT_ADDR: usize = 0x000;
+
+///Sprites for the hex digits, 5 bytes each
+pub const FONT: &[u8] = &[
     0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
     0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
     0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
@@ -15,6 +35,163 @@ const FONT: &[u8] = &[
     0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
 
+///First bytes of every save state
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+
+///Stands for no key in a save state
+const NO_KEY: u8 = 0xFF;
+
+///Bumped whenever the save state layout changes
+const STATE_VERSION: u8 = 3;
+
+///Length of every save state: header, memory, display, V, keys, the u16 registers and stack,
+///the key wait, the flags, the fault and the random number generator
+pub const STATE_SIZE: usize = STATE_MAGIC.len()
+    + 1
+    + 4096
+    + WIDTH * HEIGHT
+    + 16
+    + 16
+    + 2 * (6 + STACK_SIZE)
+    + 4
+    + 2
+    + 3
+    + 32
+    + 16;
+
+///Where `load_fontset` puts the SUPER-CHIP big font, straight after the small one
+pub const BIG_FONT_ADDR: usize = 0x050;
+
+///SUPER-CHIP 8x10 sprites for the digits 0 to 9, 10 bytes each
+pub const BIG_FONT: &[u8] = &[
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E,
+    0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF,
+    0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xC0, 0xC0,
+    0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F,
+    0x03, 0x03, 0x3E, 0x7C,
+];
+
+///Behaviours that differ between CHIP-8 interpreters
+///
+///Named after the quirks in the community chip-8-database
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quirks {
+    ///8XY6/8XYE shift VX in place instead of shifting VY into VX
+    pub shift: bool,
+
+    ///FX55/FX65 add X to I instead of X + 1
+    pub memory_increment_by_x: bool,
+
+    ///FX55/FX65 leave I unchanged
+    pub memory_leave_i_unchanged: bool,
+
+    ///Sprites wrap around the edges of the screen instead of being clipped
+    pub wrap: bool,
+
+    ///BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump: bool,
+
+    ///DXYN waits for the next frame before drawing
+    pub vblank: bool,
+
+    ///8XY1/8XY2/8XY3 reset VF to 0
+    pub logic: bool,
+
+    ///FX0A finishes when the key goes down instead of when it's released
+    #[serde(default)]
+    pub key_press: bool,
+}
+
+impl Default for Quirks {
+    /// What this interpreter has always done
+    fn default() -> Self {
+        Quirks {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: true,
+            jump: false,
+            vblank: false,
+            logic: false,
+            key_press: false,
+        }
+    }
+}
+
+///Misuse of the call stack, stops the machine instead of panicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFault {
+    ///2NNN with every stack entry in use
+    Overflow { pc: u16 },
+
+    ///00EE with no call to return from
+    Underflow { pc: u16 },
+}
+
+impl fmt::Display for StackFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackFault::Overflow { pc } => {
+                write!(
+                    f,
+                    "stack overflow: CALL at {:#05X} with {} calls in progress",
+                    pc, STACK_SIZE
+                )
+            }
+            StackFault::Underflow { pc } => {
+                write!(
+                    f,
+                    "stack underflow: RET at {:#05X} outside a subroutine",
+                    pc
+                )
+            }
+        }
+    }
+}
+
+///A loop the program spins in without doing anything, see `Chip8::idle`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idle {
+    ///A 1NNN jumping to itself, the program has finished
+    Halted,
+
+    ///FX0A with no key pressed since it started, or the pressed key not yet released
+    WaitingForKey,
+
+    ///FX07 reading the delay timer until it reaches 0, then `3X00` and a jump back to the FX07
+    WaitingForTimer,
+}
+
+impl Idle {
+    /// Whether running it again changes nothing, so only a key can end it
+    pub fn is_stuck(self) -> bool {
+        matches!(self, Idle::Halted | Idle::WaitingForKey)
+    }
+}
+
+impl fmt::Display for Idle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Idle::Halted => write!(f, "program finished"),
+            Idle::WaitingForKey => write!(f, "waiting for a key"),
+            Idle::WaitingForTimer => write!(f, "waiting for the delay timer"),
+        }
+    }
+}
+
+///Progress of an FX0A, which waits for a key to be pressed and released
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct KeyWait {
+    ///Keys already down when the wait started, ignored until they're released
+    held: u16,
+
+    ///Key pressed since, the wait ends when it's released
+    pressed: Option<u8>,
+}
+
 #[derive(Debug)]
 ///Representation of CHIP-8 Virtual Machine
 pub struct Chip8 {
@@ -27,20 +204,68 @@ pub struct Chip8 {
     ///Index Register
     pub i: u16,
 
-    stack: [u16; 16],
+    stack: [u16; STACK_SIZE],
 
     pub sp: u16,
 
     pub delay_timer: u16,
     pub sound_timer: u16,
     pub opcode: u16,
-    key: [u8; 16],
+    pub(crate) key: [u8; 16],
 
     ///General Purpose Registers
     pub v: [u8; 16],
 
-    halt: u8,
+    ///Set while an FX0A is waiting
+    key_wait: Option<KeyWait>,
+
     pub draw_flag: u8,
+
+    pub quirks: Quirks,
+
+    ///Set at the start of every frame, DXYN waits for it with the vblank quirk
+    vblank: bool,
+
+    ///Source of CXNN random numbers, seed it for repeatable runs
+    ///
+    ///ChaCha rather than `StdRng` so save states can hold exactly where it is
+    rng: ChaCha12Rng,
+
+    ///Address and length of the memory the last instruction wrote to, by FX33 or FX55
+    pub last_write: Option<(u16, u16)>,
+
+    ///Address and length of the memory the last instruction read as data, by DXYN or FX65
+    pub last_read: Option<(u16, u16)>,
+
+    ///Set when the stack is misused, nothing runs until the next reset
+    pub fault: Option<StackFault>,
+
+    ///Labels and source lines of the loaded program, from a symbol file next to the ROM
+    pub symbols: Symbols,
+}
+
+///Reads a save state front to back
+struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            bail!("save state is cut short");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes([self.byte()?, self.byte()?]))
+    }
 }
 
 fn read_file(path: &Path) -> Result<Vec<u8>, std::io::Error> {
@@ -53,23 +278,161 @@ impl Chip8 {
         Chip8 {
             memory: [0; 4096],
             display: [0; 64 * 32],
-            pc: 0x200,
+            pc: START as u16,
             i: 0,
-            stack: [0; 16],
+            stack: [0; STACK_SIZE],
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
             opcode: 0,
             key: [0; 16],
             v: [0; 16],
-            halt: 0,
+            key_wait: None,
             draw_flag: 1,
+            quirks: Quirks::default(),
+            vblank: false,
+            rng: ChaCha12Rng::from_entropy(),
+            last_write: None,
+            last_read: None,
+            fault: None,
+            symbols: Symbols::default(),
         }
     }
 
+    /// Addresses of the CALLs in progress, outermost first
+    ///
+    /// Each returns to the instruction after its address
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..(self.sp as usize).min(STACK_SIZE)]
+    }
+
+    /// Makes CXNN return the same numbers every run
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    /// The machine as bytes for `load_state`, everything but the quirks and symbols
+    ///
+    /// The random number generator is saved as its seed and position, so a loaded state plays out
+    /// exactly like this machine would, whether or not it was saved
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(STATE_MAGIC);
+        state.push(STATE_VERSION);
+        state.extend_from_slice(&self.memory);
+        state.extend_from_slice(&self.display);
+        state.extend_from_slice(&self.v);
+        state.extend_from_slice(&self.key);
+        let registers = [
+            self.pc,
+            self.i,
+            self.sp,
+            self.delay_timer,
+            self.sound_timer,
+            self.opcode,
+        ];
+        for value in registers.iter().chain(&self.stack) {
+            state.extend_from_slice(&value.to_be_bytes());
+        }
+        let key_wait = self.key_wait.map_or([0; 4], |wait| {
+            let [held_high, held_low] = wait.held.to_be_bytes();
+            [1, held_high, held_low, wait.pressed.unwrap_or(NO_KEY)]
+        });
+        state.extend_from_slice(&key_wait);
+        state.extend_from_slice(&[self.draw_flag, self.vblank as u8]);
+
+        let (fault, fault_pc) = match self.fault {
+            None => (0, 0),
+            Some(StackFault::Overflow { pc }) => (1, pc),
+            Some(StackFault::Underflow { pc }) => (2, pc),
+        };
+        state.push(fault);
+        state.extend_from_slice(&fault_pc.to_be_bytes());
+        state.extend_from_slice(&self.rng.get_seed());
+        state.extend_from_slice(&self.rng.get_word_pos().to_be_bytes());
+        state
+    }
+
+    /// Restores a state from `save_state`, leaving the machine alone if it isn't valid
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let mut reader = StateReader { bytes: state };
+        if reader.take(STATE_MAGIC.len())? != STATE_MAGIC {
+            bail!("not a save state");
+        }
+        let version = reader.byte()?;
+        if version != STATE_VERSION {
+            bail!("save state version {} isn't supported", version);
+        }
+
+        let memory = reader.take(self.memory.len())?;
+        let display = reader.take(self.display.len())?;
+        let v = reader.take(self.v.len())?;
+        let key = reader.take(self.key.len())?;
+        let pc = reader.u16()?;
+        let i = reader.u16()?;
+        let sp = reader.u16()?;
+        let delay_timer = reader.u16()?;
+        let sound_timer = reader.u16()?;
+        let opcode = reader.u16()?;
+        let mut stack = [0; STACK_SIZE];
+        for entry in &mut stack {
+            *entry = reader.u16()?;
+        }
+        let key_wait = match (reader.byte()?, reader.u16()?, reader.byte()?) {
+            (0, _, _) => None,
+            (1, held, NO_KEY) => Some(KeyWait {
+                held,
+                pressed: None,
+            }),
+            (1, held, key) if key < 16 => Some(KeyWait {
+                held,
+                pressed: Some(key),
+            }),
+            (kind, _, key) => bail!("invalid key wait {} {} in save state", kind, key),
+        };
+        let draw_flag = reader.byte()?;
+        let vblank = reader.byte()? != 0;
+        let fault = match (reader.byte()?, reader.u16()?) {
+            (0, _) => None,
+            (1, pc) => Some(StackFault::Overflow { pc }),
+            (2, pc) => Some(StackFault::Underflow { pc }),
+            (kind, _) => bail!("invalid stack fault {} in save state", kind),
+        };
+        let seed: [u8; 32] = reader.take(32)?.try_into()?;
+        let word_pos = u128::from_be_bytes(reader.take(16)?.try_into()?);
+        if !reader.bytes.is_empty() {
+            bail!("save state has {} bytes too many", reader.bytes.len());
+        }
+        if sp as usize > STACK_SIZE {
+            bail!("invalid stack pointer {} in save state", sp);
+        }
+
+        self.memory.copy_from_slice(memory);
+        self.display.copy_from_slice(display);
+        self.v.copy_from_slice(v);
+        self.key.copy_from_slice(key);
+        self.pc = pc;
+        self.i = i;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.opcode = opcode;
+        self.stack = stack;
+        self.key_wait = key_wait;
+        self.draw_flag = draw_flag;
+        self.vblank = vblank;
+        self.fault = fault;
+        self.rng = ChaCha12Rng::from_seed(seed);
+        self.rng.set_word_pos(word_pos);
+        self.last_write = None;
+        self.last_read = None;
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
+        self.memory.fill(0);
         self.display.fill(0);
-        self.pc = 0x200;
+        self.pc = START as u16;
         self.i = 0;
         self.stack.fill(0);
         self.sp = 0;
@@ -78,8 +441,12 @@ impl Chip8 {
         self.opcode = 0;
         self.key.fill(0);
         self.v.fill(0);
-        self.halt = 0;
+        self.key_wait = None;
         self.draw_flag = 1;
+        self.vblank = false;
+        self.last_write = None;
+        self.last_read = None;
+        self.fault = None;
     }
 
     pub fn start(&mut self, rom: &str) -> Result<(), Error> {
@@ -89,21 +456,110 @@ impl Chip8 {
         //Contents of rom
         let rom: Vec<u8> = read_file(&path)?;
 
+        self.load_bytes(&rom)?;
+        self.symbols = Symbols::for_rom(path).unwrap_or_else(|e| {
+            warn!(target: logging::FRONTEND, "ignoring symbols: {:#}", e);
+            Symbols::default()
+        });
+        Ok(())
+    }
+
+    /// Resets the machine and starts `rom` from scratch
+    pub fn load_rom(&mut self, rom: &str) -> Result<(), Error> {
+        self.reset();
+        self.start(rom)
+    }
+
+    /// Resets the machine and starts a ROM that is already in memory
+    ///
+    /// Leaves the machine alone if the ROM doesn't fit
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let room = self.memory.len() - START;
+        if rom.len() > room {
+            bail!("ROM is {} bytes, only {} fit in memory", rom.len(), room);
+        }
+
+        self.reset();
+        self.symbols = Symbols::default();
         self.load_fontset();
-        self.load_program(&rom);
+        self.load_program(rom);
         Ok(())
     }
 
     pub fn load_fontset(&mut self) {
         for (i, v) in FONT.iter().enumerate() {
-            self.memory[i] = *v;
+            self.memory[FONT_ADDR + i] = *v;
+        }
+        for (i, v) in BIG_FONT.iter().enumerate() {
+            self.memory[BIG_FONT_ADDR + i] = *v;
+        }
+    }
+
+    /// The instruction at `addr`
+    fn instruction_at(&self, addr: u16) -> u16 {
+        let len = self.memory.len();
+        ((self.memory[addr as usize % len] as u16) << 8)
+            | (self.memory[(addr as usize + 1) % len] as u16)
+    }
+
+    /// The loop the program is spinning in, if it's one that only a key or the timers end
+    ///
+    /// Frontends can skip the loops that are `Idle::is_stuck` until the keys change
+    pub fn idle(&self) -> Option<Idle> {
+        if self.fault.is_some() {
+            return None;
         }
+
+        let opcode = self.instruction_at(self.pc);
+        if opcode == 0x1000 | (self.pc & 0x0FFF) {
+            return Some(Idle::Halted);
+        }
+        if opcode & 0xF0FF == 0xF00A {
+            // Running it again would change nothing until a key goes down or up
+            let keys = self.keys_down();
+            let waiting = match self.key_wait {
+                Some(KeyWait {
+                    pressed: Some(key), ..
+                }) => keys & 1 << key != 0,
+                Some(KeyWait { held, .. }) => keys == held,
+                None => false,
+            };
+            if waiting {
+                return Some(Idle::WaitingForKey);
+            }
+        }
+
+        // PC can be on any of the loop's three instructions
+        let timer_loop = (0..3).any(|i| {
+            let start = self.pc.wrapping_sub(i * 2) & 0x0FFF;
+            let read = self.instruction_at(start);
+            read & 0xF0FF == 0xF007
+                && self.instruction_at(start + 2) == 0x3000 | (read & 0x0F00)
+                && self.instruction_at(start + 4) == 0x1000 | start
+        });
+        if timer_loop && self.delay_timer > 0 {
+            return Some(Idle::WaitingForTimer);
+        }
+
+        None
     }
 
+    /// Fetches, decodes and executes a single instruction
+    ///
+    /// Timers are not touched, see `tick_timers`
     pub fn emulate_cycle(&mut self) {
+        self.last_write = None;
+        self.last_read = None;
+        if self.fault.is_some() {
+            return;
+        }
+
         //Opcode
-        self.opcode = ((self.memory[self.pc as usize] as u16) << 8)
-            | (self.memory[(self.pc + 1) as usize] as u16);
+        self.opcode = self.instruction_at(self.pc);
+        // Only an FX0A that runs again carries on waiting
+        if self.opcode & 0xF0FF != 0xF00A {
+            self.key_wait = None;
+        }
 
         //First byte of opcode
         let first_byte: u16 = self.opcode & 0xF000;
@@ -125,8 +581,18 @@ impl Chip8 {
             0xD000 => self.op_d(),
             0xE000 => self.op_e(),
             0xF000 => self.op_f(),
-            _ => println!("NO OPCODe"),
+            _ => self.unknown_opcode(),
         };
+    }
+
+    /// Leaves PC where it is, so the machine stays on the opcode
+    fn unknown_opcode(&self) {
+        warn!(target: logging::CPU, "unknown opcode {:04X} at {:03X}", self.opcode, self.pc);
+    }
+
+    /// Decrements the delay and sound timers, called once per 60Hz frame
+    pub fn tick_timers(&mut self) {
+        self.vblank = true;
 
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
@@ -156,12 +622,18 @@ impl Chip8 {
                 self.pc += 2;
             }
             0x000E => {
+                if self.sp == 0 {
+                    let fault = StackFault::Underflow { pc: self.pc };
+                    error!(target: logging::CPU, "{}", fault);
+                    self.fault = Some(fault);
+                    return;
+                }
                 self.sp -= 1;
                 self.pc = self.stack[self.sp as usize];
                 self.pc += 2;
             }
 
-            _ => println!("Unkown Opcode"),
+            _ => self.unknown_opcode(),
         };
     }
 
@@ -169,11 +641,6 @@ impl Chip8 {
     ///
     /// Sets Program Counter to NNN
     pub fn op_1(&mut self) {
-        if self.opcode & 0x0FFF == self.pc {
-            self.halt = 1;
-            //println!("INFINTE LOOP");
-        }
-
         self.pc = self.opcode & 0x0FFF;
     }
 
@@ -183,6 +650,12 @@ impl Chip8 {
     ///
     /// PC is then set to NNN
     pub fn op_2(&mut self) {
+        if self.sp as usize >= STACK_SIZE {
+            let fault = StackFault::Overflow { pc: self.pc };
+            error!(target: logging::CPU, "{}", fault);
+            self.fault = Some(fault);
+            return;
+        }
         self.stack[self.sp as usize] = self.pc;
         self.sp += 1;
         self.pc = self.opcode & 0xFFF;
@@ -315,16 +788,25 @@ impl Chip8 {
 
             0x0001 => {
                 self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.logic {
+                    self.v[0xF] = 0;
+                }
                 self.pc += 2;
             }
 
             0x0002 => {
                 self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.logic {
+                    self.v[0xF] = 0;
+                }
                 self.pc += 2;
             }
 
             0x0003 => {
                 self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.logic {
+                    self.v[0xF] = 0;
+                }
                 self.pc += 2;
             }
 
@@ -353,9 +835,11 @@ impl Chip8 {
             }
 
             0x0006 => {
-                self.v[0xF] = self.v[x as usize] & 1;
-                self.v[x as usize] = self.v[y as usize] >> 1;
+                let source = if self.quirks.shift { x } else { y };
+                let value = self.v[source as usize];
 
+                self.v[x as usize] = value >> 1;
+                self.v[0xF] = value & 1;
                 self.pc += 2;
             }
 
@@ -366,19 +850,20 @@ impl Chip8 {
                     self.v[0xF] = 1;
                 }
 
-                self.v[x as usize] = self.v[y as usize] - self.v[x as usize];
+                self.v[x as usize] = self.v[y as usize].wrapping_sub(self.v[x as usize]);
                 self.pc += 2;
             }
 
             0x000E => {
-                self.v[0xF] = self.v[x as usize] & 0x80;
-                self.v[x as usize] = self.v[y as usize] << 1;
+                let source = if self.quirks.shift { x } else { y };
+                let value = self.v[source as usize];
+
+                self.v[x as usize] = value << 1;
+                self.v[0xF] = value >> 7;
                 self.pc += 2;
             }
 
-            _ => {
-                println!("NO OPCODE");
-            }
+            _ => self.unknown_opcode(),
         };
     }
 
@@ -407,17 +892,24 @@ impl Chip8 {
     /// BNNN - PC = NNN + V0
     ///
     /// Jump to address NNN + V0
+    ///
+    /// With the jump quirk this is BXNN, jumping to XNN + VX
     pub fn op_b(&mut self) {
-        self.pc += (self.v[0] as u16) + (self.opcode & 0x0FFF);
+        let register = if self.quirks.jump {
+            (self.opcode & 0x0F00) >> 8
+        } else {
+            0
+        };
+
+        self.pc = ((self.v[register as usize] as u16) + (self.opcode & 0x0FFF)) & 0x0FFF;
     }
 
     /// CXKK - VX = (random byte) & (KK)
     ///
     ///
     pub fn op_c(&mut self) {
-        let mut rng = rand::thread_rng();
         let x: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
-        let r: u16 = rng.gen();
+        let r: u16 = self.rng.gen();
         self.v[x as usize] = (r & (self.opcode & 0x00FF)) as u8;
         self.pc += 2;
     }
@@ -426,28 +918,48 @@ impl Chip8 {
     ///
     /// set VF to 01 if any set pixels are changed to unset, and 00 otherwise
     pub fn op_d(&mut self) {
+        if self.quirks.vblank {
+            if !self.vblank {
+                // Try again once the next frame starts
+                return;
+            }
+            self.vblank = false;
+        }
+
         let x: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
         let y: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
         //Height
         let h: u8 = (self.opcode & 0x000F) as u8;
         self.v[0xF] = 0;
+        self.last_read = Some((self.i, h as u16));
 
-        for yline in 0..h {
-            let mut pixel = self.memory[(self.i + (yline as u16)) as usize];
+        //The starting position always wraps, the rest of the sprite depends on the wrap quirk
+        let start_x = self.v[x as usize] as usize % WIDTH;
+        let start_y = self.v[y as usize] as usize % HEIGHT;
+
+        for yline in 0..h as usize {
+            let pixel = self.memory[(self.i as usize + yline) % self.memory.len()];
 
             for xline in 0..8 {
                 if (pixel & (0x80 >> xline)) != 0 {
-                    let mut y_calc: u64 = (self.v[y as usize] + yline) as u64;
-                    y_calc = y_calc.wrapping_mul(64);
-
-                    let inner: u64 = self.v[x as usize] as u64 + xline as u64 + y_calc;
+                    let mut px = start_x + xline;
+                    let mut py = start_y + yline;
+
+                    if px >= WIDTH || py >= HEIGHT {
+                        if !self.quirks.wrap {
+                            continue;
+                        }
+                        px %= WIDTH;
+                        py %= HEIGHT;
+                    }
 
-                    if self.display[(inner % 2048) as usize] == 1 {
+                    let index = (py * WIDTH) + px;
+                    if self.display[index] == 1 {
                         self.v[0xF] = 1;
                     }
 
-                    self.display[(inner % 2048) as usize] ^= 1;
+                    self.display[index] ^= 1;
                 }
             }
         }
@@ -471,7 +983,7 @@ impl Chip8 {
         let x: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
         match self.opcode as u8 {
             0x009E => {
-                if self.key[self.v[x as usize] as usize] != 0 {
+                if self.key[self.v[x as usize] as usize & 0xF] != 0 {
                     self.pc += 4;
                 } else {
                     self.pc += 2;
@@ -479,13 +991,13 @@ impl Chip8 {
             }
 
             0x00A1 => {
-                if self.key[self.v[x as usize] as usize] == 0 {
+                if self.key[self.v[x as usize] as usize & 0xF] == 0 {
                     self.pc += 4;
                 } else {
                     self.pc += 2;
                 }
             }
-            _ => println!("NO OPCODe"),
+            _ => self.unknown_opcode(),
         };
     }
 
@@ -495,7 +1007,7 @@ impl Chip8 {
     ///
     /// Store delay timer value in register VX
     ///
-    /// FX0A - Wait for key press, store the value of key in register VX
+    /// FX0A - Wait for a key to be pressed and released, store the key in register VX
     ///
     /// FX15 - delay_timer = VX
     ///
@@ -507,6 +1019,8 @@ impl Chip8 {
     ///
     /// hexadecimal digit stored in register VX
     ///
+    /// FX30 - Store address of the SUPER-CHIP big font sprite for the digit in VX
+    ///
     /// FX33 - Store BCD representation of value in register VX
     ///
     /// in memory locations I, I+1, and I+2
@@ -525,21 +1039,7 @@ impl Chip8 {
                 self.pc += 2;
             }
 
-            0x000A => {
-                let mut key_press = 0;
-                for i in 0..16 {
-                    if self.key[i as usize] != 0 {
-                        self.v[x as usize] = i as u8;
-                        key_press = 1;
-                    }
-                }
-
-                if key_press == 0 {
-                    return;
-                }
-
-                self.pc += 2;
-            }
+            0x000A => self.wait_for_key(x),
 
             0x0015 => {
                 self.delay_timer = self.v[x as usize] as u16;
@@ -552,25 +1052,33 @@ impl Chip8 {
             }
 
             0x001E => {
-                self.i += self.v[x as usize] as u16;
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
                 self.pc += 2;
             }
 
             0x0029 => {
-                self.i = (self.v[x as usize] * 5) as u16;
+                self.i = FONT_ADDR as u16 + (self.v[x as usize] & 0xF) as u16 * 5;
+                self.pc += 2;
+            }
+
+            0x0030 => {
+                self.i = BIG_FONT_ADDR as u16 + (self.v[x as usize] & 0xF) as u16 * 10;
                 self.pc += 2;
             }
 
             0x0033 => {
+                let len = self.memory.len();
+
                 //Hundreds Digit
-                self.memory[self.i as usize] = (self.v[x as usize] / 100) % 10;
+                self.memory[self.i as usize % len] = (self.v[x as usize] / 100) % 10;
 
                 //Tens Digit
-                self.memory[(self.i + 1) as usize] = (self.v[x as usize] / 10) % 10;
+                self.memory[(self.i as usize + 1) % len] = (self.v[x as usize] / 10) % 10;
 
                 //Ones digit
-                self.memory[(self.i + 2) as usize] = (self.v[x as usize]) % 10;
+                self.memory[(self.i as usize + 2) % len] = (self.v[x as usize]) % 10;
 
+                self.last_write = Some((self.i, 3));
                 self.pc += 2;
             }
 
@@ -578,10 +1086,13 @@ impl Chip8 {
                 let mut i: u16 = 0x00;
 
                 while i <= x as u16 {
-                    self.memory[(self.i + i) as usize] = self.v[i as usize];
+                    let len = self.memory.len();
+                    self.memory[(self.i as usize + i as usize) % len] = self.v[i as usize];
                     i += 1;
                 }
 
+                self.last_write = Some((self.i, x as u16 + 1));
+                self.increment_i_after_load_store(x);
                 self.pc += 2;
             }
 
@@ -589,95 +1100,326 @@ impl Chip8 {
                 let mut i: u16 = 0x00;
 
                 while i <= x as u16 {
-                    self.v[i as usize] = self.memory[(self.i + i) as usize];
+                    self.v[i as usize] =
+                        self.memory[(self.i as usize + i as usize) % self.memory.len()];
                     i += 1;
                 }
 
+                self.last_read = Some((self.i, x as u16 + 1));
+                self.increment_i_after_load_store(x);
                 self.pc += 2;
             }
-            _ => println!("NO OPCODE"),
+            _ => self.unknown_opcode(),
         }
     }
 
-    pub fn check_keys(&mut self, rl: &mut RaylibDrawHandle) {
-        //If '1' key is pressed or down
-        self.key[0x1] =
-            (rl.is_key_pressed(KeyboardKey::KEY_ONE) || rl.is_key_down(KeyboardKey::KEY_ONE)) as u8;
+    /// FX0A, runs again until a key that wasn't already down is pressed and released
+    ///
+    /// The key is stored in VX, the lowest one if several are pressed at once. With the
+    /// `key_press` quirk the wait ends as soon as the key goes down.
+    fn wait_for_key(&mut self, x: u8) {
+        let keys = self.keys_down();
+        let mut wait = self.key_wait.unwrap_or(KeyWait {
+            held: keys,
+            pressed: None,
+        });
+        wait.held &= keys;
+
+        if wait.pressed.is_none() {
+            let fresh = keys & !wait.held;
+            if fresh != 0 {
+                wait.pressed = Some(fresh.trailing_zeros() as u8);
+            }
+        }
 
-        //If '2' key is pressed or down
-        self.key[0x2] =
-            (rl.is_key_pressed(KeyboardKey::KEY_TWO) || rl.is_key_down(KeyboardKey::KEY_TWO)) as u8;
+        match wait.pressed {
+            Some(key) if self.quirks.key_press || keys & 1 << key == 0 => {
+                self.v[x as usize] = key;
+                self.key_wait = None;
+                self.pc += 2;
+            }
+            _ => self.key_wait = Some(wait),
+        }
+    }
 
-        //If '3' key  is pressed or down
-        self.key[0x3] = (rl.is_key_pressed(KeyboardKey::KEY_THREE)
-            || rl.is_key_down(KeyboardKey::KEY_THREE)) as u8;
+    /// Moves I past the registers FX55/FX65 just stored or loaded, depending on the quirks
+    fn increment_i_after_load_store(&mut self, x: u8) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
 
-        //If '4' key is pressed or down
-        self.key[0xC] = (rl.is_key_pressed(KeyboardKey::KEY_FOUR)
-            || rl.is_key_down(KeyboardKey::KEY_FOUR)) as u8;
+        let increment = if self.quirks.memory_increment_by_x {
+            x as u16
+        } else {
+            x as u16 + 1
+        };
+        self.i = self.i.wrapping_add(increment);
+    }
 
-        //If 'Q' key is pressed or down
-        self.key[0x4] =
-            (rl.is_key_pressed(KeyboardKey::KEY_Q) || rl.is_key_down(KeyboardKey::KEY_Q)) as u8;
+    /// Sets the pressed state of one of the 16 keys on the hex keypad
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if (self.key[key] != 0) != pressed {
+            debug!(target: logging::INPUT, "key {:X} {}", key, if pressed { "down" } else { "up" });
+        }
+        self.key[key] = pressed as u8;
+    }
 
-        //If 'W' key is pressed or down
-        self.key[0x5] =
-            (rl.is_key_pressed(KeyboardKey::KEY_W) || rl.is_key_down(KeyboardKey::KEY_W)) as u8;
+    /// The 16 keys as a mask, key 0 as bit 0
+    fn keys_down(&self) -> u16 {
+        self.key
+            .iter()
+            .enumerate()
+            .filter(|(_, &down)| down != 0)
+            .fold(0, |mask, (key, _)| mask | 1 << key)
+    }
 
-        //If 'E' key is pressed or down
-        self.key[0x6] =
-            (rl.is_key_pressed(KeyboardKey::KEY_E) || rl.is_key_down(KeyboardKey::KEY_E)) as u8;
+    /// Whether one of the 16 keys on the hex keypad is down
+    pub fn is_key_down(&self, key: usize) -> bool {
+        self.key[key] != 0
+    }
 
-        //If 'R' key is pressed or down
-        self.key[0xD] =
-            (rl.is_key_pressed(KeyboardKey::KEY_R) || rl.is_key_down(KeyboardKey::KEY_R)) as u8;
+    ///Loads rom into memory of CHIP-8 Virtual Machine, `load_bytes` checks it fits
+    fn load_program(&mut self, rom: &[u8]) {
+        self.memory[START..START + rom.len()].copy_from_slice(rom);
+    }
+}
 
-        //If 'A' key is pressed or down
-        self.key[0x7] =
-            (rl.is_key_pressed(KeyboardKey::KEY_A) || rl.is_key_down(KeyboardKey::KEY_A)) as u8;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        //If 'S' key is pressed or down
-        self.key[0x8] =
-            (rl.is_key_pressed(KeyboardKey::KEY_S) || rl.is_key_down(KeyboardKey::KEY_S)) as u8;
+    ///Offset of PC in a save state, I and SP follow it
+    const STATE_PC: usize = STATE_MAGIC.len() + 1 + 4096 + WIDTH * HEIGHT + 16 + 16;
 
-        //If 'D' key is pressed or down
-        self.key[0x9] =
-            (rl.is_key_pressed(KeyboardKey::KEY_D) || rl.is_key_down(KeyboardKey::KEY_D)) as u8;
+    /// A machine running `rom`
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(rom).unwrap();
+        chip8
+    }
 
-        //If 'F' Key is pressed or down
-        self.key[0xE] =
-            (rl.is_key_pressed(KeyboardKey::KEY_F) || rl.is_key_down(KeyboardKey::KEY_F)) as u8;
+    ///FX0A into V0, then a jump to itself
+    const KEY_WAIT: [u8; 4] = [0xF0, 0x0A, 0x12, 0x02];
 
-        self.key[0xA] =
-            (rl.is_key_pressed(KeyboardKey::KEY_Z) || rl.is_key_down(KeyboardKey::KEY_Z)) as u8;
+    fn run(chip8: &mut Chip8, cycles: usize) {
+        for _ in 0..cycles {
+            chip8.emulate_cycle();
+        }
+    }
 
-        self.key[0x0] =
-            (rl.is_key_pressed(KeyboardKey::KEY_X) || rl.is_key_down(KeyboardKey::KEY_X)) as u8;
+    #[test]
+    fn key_wait_ignores_keys_already_held() {
+        let mut chip8 = machine(&KEY_WAIT);
+        chip8.set_key(5, true);
+        run(&mut chip8, 5);
+        chip8.set_key(5, false);
+        run(&mut chip8, 5);
+        assert_eq!(chip8.pc, 0x200);
+
+        // Pressed again after the release, it counts
+        chip8.set_key(5, true);
+        run(&mut chip8, 1);
+        chip8.set_key(5, false);
+        run(&mut chip8, 1);
+        assert_eq!((chip8.pc, chip8.v[0]), (0x202, 5));
+    }
 
-        self.key[0xB] =
-            (rl.is_key_pressed(KeyboardKey::KEY_C) || rl.is_key_down(KeyboardKey::KEY_C)) as u8;
+    #[test]
+    fn key_wait_ends_on_release() {
+        let mut chip8 = machine(&KEY_WAIT);
+        run(&mut chip8, 1);
+        chip8.set_key(3, true);
+        run(&mut chip8, 5);
+        assert_eq!(chip8.pc, 0x200);
+
+        chip8.set_key(3, false);
+        run(&mut chip8, 1);
+        assert_eq!((chip8.pc, chip8.v[0]), (0x202, 3));
+    }
 
-        self.key[0xF] =
-            (rl.is_key_pressed(KeyboardKey::KEY_V) || rl.is_key_down(KeyboardKey::KEY_V)) as u8;
+    #[test]
+    fn key_press_quirk_ends_the_wait_on_press() {
+        let mut chip8 = machine(&KEY_WAIT);
+        chip8.quirks.key_press = true;
+        run(&mut chip8, 1);
+        chip8.set_key(3, true);
+        run(&mut chip8, 1);
+        assert_eq!((chip8.pc, chip8.v[0]), (0x202, 3));
     }
 
-    /// Draws graphics to raylib window
-    pub fn draw_graphics(&self, display: &mut RaylibDrawHandle) {
-        for y in 0..32 {
-            for x in 0..64 {
-                if self.display[(y * 64) + x] != 0 {
-                    display.draw_rectangle((x * 10) as i32, (y * 10) as i32, 10, 10, Color::WHITE);
-                } else {
-                    display.draw_rectangle((x * 10) as i32, (y * 10) as i32, 10, 10, Color::BLACK);
-                }
+    #[test]
+    fn key_wait_takes_the_lowest_fresh_key() {
+        let mut chip8 = machine(&KEY_WAIT);
+        chip8.set_key(2, true);
+        run(&mut chip8, 1);
+        chip8.set_key(9, true);
+        chip8.set_key(4, true);
+        run(&mut chip8, 1);
+        chip8.set_key(4, false);
+        run(&mut chip8, 1);
+        assert_eq!((chip8.pc, chip8.v[0]), (0x202, 4));
+    }
+
+    #[test]
+    fn timers_run_during_a_key_wait() {
+        // DT = ST = 5, then wait
+        let mut chip8 = machine(&[0x60, 0x05, 0xF0, 0x15, 0xF0, 0x18, 0xF1, 0x0A]);
+        run(&mut chip8, 4);
+        for _ in 0..3 {
+            chip8.tick_timers();
+            run(&mut chip8, 10);
+        }
+        assert_eq!(chip8.pc, 0x206);
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (2, 2));
+    }
+
+    #[test]
+    fn key_wait_survives_save_states() {
+        let mut chip8 = machine(&KEY_WAIT);
+        chip8.set_key(5, true);
+        run(&mut chip8, 1);
+        chip8.set_key(3, true);
+        run(&mut chip8, 1);
+        let state = chip8.save_state();
+
+        let mut loaded = Chip8::new();
+        loaded.load_state(&state).unwrap();
+        run(&mut loaded, 1);
+        assert_eq!(loaded.pc, 0x200);
+
+        // 5 was held before the wait, so releasing it does nothing, releasing 3 ends it
+        loaded.set_key(5, false);
+        run(&mut loaded, 1);
+        assert_eq!(loaded.pc, 0x200);
+        loaded.set_key(3, false);
+        run(&mut loaded, 1);
+        assert_eq!((loaded.pc, loaded.v[0]), (0x202, 3));
+    }
+
+    #[test]
+    fn jump_to_itself_is_halted() {
+        let mut chip8 = machine(&[0x00, 0xE0, 0x12, 0x02]);
+        assert_eq!(chip8.idle(), None);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.idle(), Some(Idle::Halted));
+        assert!(Idle::Halted.is_stuck());
+    }
+
+    #[test]
+    fn key_wait_is_idle_until_a_key_changes() {
+        let mut chip8 = machine(&KEY_WAIT);
+        // Not until the wait has started
+        assert_eq!(chip8.idle(), None);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.idle(), Some(Idle::WaitingForKey));
+
+        chip8.set_key(7, true);
+        assert_eq!(chip8.idle(), None);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.idle(), Some(Idle::WaitingForKey));
+        chip8.set_key(7, false);
+        assert_eq!(chip8.idle(), None);
+    }
+
+    #[test]
+    fn delay_loop_is_waiting_for_the_timer() {
+        // DT = 3, then FX07, 3X00, jump back to the FX07
+        let mut chip8 = machine(&[0x60, 0x03, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04]);
+        run(&mut chip8, 2);
+        // On each of the loop's instructions
+        for _ in 0..3 {
+            assert_eq!(chip8.idle(), Some(Idle::WaitingForTimer));
+            run(&mut chip8, 1);
+        }
+        assert!(!Idle::WaitingForTimer.is_stuck());
+
+        chip8.delay_timer = 0;
+        assert_eq!(chip8.idle(), None);
+    }
+
+    #[test]
+    fn save_state_leaves_random_numbers_alone() {
+        // C0FF, jump back to it
+        let rom = [0xC0, 0xFF, 0x12, 0x00];
+        let mut plain = machine(&rom);
+        let mut saved = machine(&rom);
+        plain.seed(7);
+        saved.seed(7);
+
+        let mut state = Vec::new();
+        for step in 0..200 {
+            if step == 100 {
+                state = saved.save_state();
             }
+            saved.save_state();
+            plain.emulate_cycle();
+            saved.emulate_cycle();
+            assert_eq!(plain.v[0], saved.v[0]);
+        }
+
+        // A loaded state carries on with the same numbers
+        let mut loaded = Chip8::new();
+        loaded.load_state(&state).unwrap();
+        let mut replay = machine(&rom);
+        replay.seed(7);
+        for _ in 0..100 {
+            replay.emulate_cycle();
+        }
+        for _ in 0..100 {
+            loaded.emulate_cycle();
+            replay.emulate_cycle();
+            assert_eq!(loaded.v[0], replay.v[0]);
         }
     }
 
-    ///Loads rom into memory of CHIP-8 Virtual Machine
-    pub fn load_program(&mut self, rom: &[u8]) {
-        for (i, v) in rom.iter().enumerate() {
-            self.memory[(i + 0x200) as usize] = *v;
+    #[test]
+    fn key_skips_use_the_low_nibble_of_vx() {
+        // V0 = 0x25, skip if key 5 is down, skip if it isn't
+        let mut chip8 = machine(&[0x60, 0x25, 0xE0, 0x9E, 0x00, 0x00, 0xE0, 0xA1]);
+        chip8.set_key(5, true);
+        for _ in 0..3 {
+            chip8.emulate_cycle();
         }
+        assert_eq!(chip8.pc, 0x208);
+    }
+
+    #[test]
+    fn load_state_rejects_a_stack_pointer_out_of_range() {
+        // 00EE
+        let mut chip8 = machine(&[0x00, 0xEE]);
+        let mut state = chip8.save_state();
+        state[STATE_PC + 4..STATE_PC + 6].copy_from_slice(&17u16.to_be_bytes());
+        assert!(chip8.load_state(&state).is_err());
+
+        // Still the machine from before, which faults instead of panicking
+        assert_eq!(chip8.sp, 0);
+        chip8.emulate_cycle();
+        assert_eq!(chip8.fault, Some(StackFault::Underflow { pc: 0x200 }));
+    }
+
+    #[test]
+    fn save_states_keep_i_past_the_end_of_memory() {
+        // I = FFF, V0 = 10, I += V0
+        let mut chip8 = machine(&[0xAF, 0xFF, 0x60, 0x10, 0xF0, 0x1E]);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.i, 0x100F);
+
+        let mut loaded = Chip8::new();
+        loaded.load_state(&chip8.save_state()).unwrap();
+        assert_eq!(loaded.i, 0x100F);
+        assert_eq!(loaded.pc, 0x206);
+    }
+
+    #[test]
+    fn save_states_keep_pc_past_the_end_of_memory() {
+        let mut chip8 = machine(&[]);
+        chip8.pc = 0xFFE;
+        // 0000 at the end of memory, which steps PC on to 0x1000
+        chip8.emulate_cycle();
+        assert_eq!(chip8.pc, 0x1000);
+
+        let mut loaded = Chip8::new();
+        loaded.load_state(&chip8.save_state()).unwrap();
+        assert_eq!(loaded.pc, 0x1000);
     }
 }
@@ -0,0 +1,672 @@
+use crate::app::{App, Options};
+use crate::chip::{Chip8, HEIGHT, WIDTH};
+use crate::coverage::Coverage;
+use crate::keymap::Keymap;
+use crate::palette::Palette;
+use crate::platform::{Platform, PLATFORMS};
+use crate::profiler::Profiler;
+use crate::romdb::{self, Overrides, QuirkSet, RomProfile};
+use crate::scheduler::Scheduler;
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+use crate::settings::Settings;
+use crate::symbols::Symbols;
+use crate::tui::{Glyphs, Tui};
+use crate::{asm, disasm, logging, support, tui};
+use anyhow::{anyhow, bail, Context, Error};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  error, or a failed test
+  2  bad command line";
+
+///CHIP-8 emulator and tools
+///
+///Runs a ROM in the GUI when no subcommand is given
+#[derive(Debug, Parser)]
+#[command(
+    name = "chip8-rust",
+    version,
+    args_conflicts_with_subcommands = true,
+    after_help = EXIT_CODES
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: RunArgs,
+
+    /// What to log, as in RUST_LOG (e.g. warn,cpu=debug), categories are cpu, display, input, audio and frontend
+    #[arg(long, global = true, value_name = "FILTER")]
+    log: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a ROM in a window or the terminal
+    Run(RunArgs),
+
+    /// Run a ROM for a number of frames without any display
+    Headless(HeadlessArgs),
+
+    /// Disassemble a ROM into source `asm` accepts
+    ///
+    /// Labels come from ROM.sym or ROM.map next to the ROM when there is one
+    Disasm {
+        rom: PathBuf,
+
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Assemble source into a ROM
+    Asm {
+        source: PathBuf,
+
+        /// ROM to write, defaults to the source with a .ch8 extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Also write a symbol map for the debugger
+        #[arg(long, value_name = "FILE")]
+        map: Option<PathBuf>,
+    },
+
+    /// Print the hash, size and detected platform of ROMs
+    Info {
+        #[arg(required = true)]
+        roms: Vec<PathBuf>,
+    },
+
+    /// Run conformance ROMs and compare their screens with the expected ones
+    Test(TestArgs),
+}
+
+///How to emulate, on top of what the ROM database recommends
+#[derive(Debug, Clone, Args)]
+struct EmulationArgs {
+    /// Platform to emulate: originalChip8, modernChip8, chip48, superchip or xochip
+    #[arg(long, value_parser = parse_platform)]
+    platform: Option<Platform>,
+
+    /// Quirks to change, comma separated, `no-` turns one off (e.g. shift,no-vblank)
+    #[arg(long, value_parser = QuirkSet::parse)]
+    quirks: Option<QuirkSet>,
+
+    /// Instructions per frame
+    #[arg(long, value_name = "CYCLES", value_parser = clap::value_parser!(u32).range(1..))]
+    speed: Option<u32>,
+
+    /// Seed for random numbers, for repeatable runs
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl EmulationArgs {
+    fn overrides(&self) -> Overrides {
+        Overrides {
+            platform: self.platform,
+            quirk_changes: self.quirks.clone().unwrap_or_default(),
+            cycles_per_frame: self.speed,
+            ..Overrides::default()
+        }
+    }
+
+    /// Loads `rom` into a new machine set up the way the database and the arguments say
+    fn machine(&self, path: &Path, rom: &[u8]) -> Result<(Chip8, Scheduler, RomProfile), Error> {
+        let mut profile = romdb::bundled().profile(rom);
+        profile.apply(&self.overrides());
+
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_bytes(rom)
+            .with_context(|| path.display().to_string())?;
+        chip8.quirks = profile.quirks;
+        if let Some(seed) = self.seed {
+            chip8.seed(seed);
+        }
+
+        Ok((chip8, Scheduler::new(profile.cycles_per_frame), profile))
+    }
+}
+
+/// Exits with a usage error for a ROM that doesn't fit in memory, like a bad argument
+fn usage_error(error: Error) -> ! {
+    Cli::command()
+        .error(ErrorKind::InvalidValue, format!("{:#}", error))
+        .exit()
+}
+
+#[derive(Debug, Clone, Args)]
+struct RunArgs {
+    /// ROM to run, the GUI starts in the ROM browser without one
+    rom: Option<PathBuf>,
+
+    #[command(flatten)]
+    emulation: EmulationArgs,
+
+    /// Screen pixels per CHIP-8 pixel
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=64))]
+    scale: Option<u32>,
+
+    /// Colours of lit and unlit pixels as ON:OFF (e.g. "#33ff66:#000000")
+    #[arg(long, value_parser = parse_palette)]
+    palette: Option<Palette>,
+
+    /// Host key for each CHIP-8 key 0 through F (e.g. x123qweasdzc4rfv)
+    #[arg(long, value_parser = Keymap::parse)]
+    keymap: Option<Keymap>,
+
+    /// Start with the window fullscreen
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Turn the beeper off
+    #[arg(long)]
+    mute: bool,
+
+    /// Run in the terminal instead of a window
+    #[arg(long)]
+    tui: bool,
+
+    /// Draw the terminal display with braille, 2x4 pixels per character
+    #[arg(long, requires = "tui")]
+    braille: bool,
+
+    /// How long a terminal key counts as held after it's pressed, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = tui::DEFAULT_KEY_HOLD.as_millis() as u64, requires = "tui")]
+    key_hold: u64,
+
+    /// Directory the ROM browser starts in, the last one used by default
+    #[arg(long, value_name = "DIR")]
+    rom_dir: Option<PathBuf>,
+
+    /// Settings file, defaults to settings.toml in the user's config directory
+    #[arg(long, value_name = "FILE")]
+    settings: Option<PathBuf>,
+
+    /// Rhai script to run alongside the ROM
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE", conflicts_with = "tui")]
+    script: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct HeadlessArgs {
+    rom: PathBuf,
+
+    #[command(flatten)]
+    emulation: EmulationArgs,
+
+    /// Frames to run, 60 per second
+    #[arg(long, default_value_t = 600)]
+    frames: u32,
+
+    /// Print the screen when done
+    #[arg(long)]
+    screen: bool,
+
+    /// Stop once the program halts or waits for a key, failing if --frames runs out first
+    #[arg(long)]
+    until_idle: bool,
+
+    /// Write a profile of the run as collapsed stacks, for flame graph tools
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+
+    /// Write the disassembly marked with what the run executed, read and wrote
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<PathBuf>,
+
+    /// Rhai script to drive the run, `stop()` in it ends the run before --frames
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct TestArgs {
+    /// ROMs to test, each with its expected screen in a .screen file next to it
+    ///
+    /// Defaults to every ROM in --dir that has one
+    roms: Vec<PathBuf>,
+
+    #[arg(long, value_name = "DIR", default_value = "roms")]
+    dir: PathBuf,
+
+    /// Frames to run each ROM for
+    #[arg(long, default_value_t = 300)]
+    frames: u32,
+
+    /// Save the screens as the expected ones instead of comparing
+    #[arg(long)]
+    bless: bool,
+
+    /// Write a coverage report for each ROM into this directory, see `headless --coverage`
+    #[arg(long, value_name = "DIR")]
+    coverage: Option<PathBuf>,
+}
+
+fn parse_platform(id: &str) -> Result<Platform, String> {
+    Platform::from_id(id).ok_or_else(|| {
+        let ids: Vec<&str> = PLATFORMS.iter().map(|p| p.id()).collect();
+        format!("expected one of {}", ids.join(", "))
+    })
+}
+
+fn parse_palette(colors: &str) -> Result<Palette, Error> {
+    let (on, off) = colors
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected ON:OFF colours"))?;
+    Palette::from_hex(on, off)
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>, Error> {
+    fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// The display as text, `#` for lit pixels and `.` for unlit ones
+pub fn screen_text(chip8: &Chip8) -> String {
+    chip8
+        .display
+        .chunks(WIDTH)
+        .take(HEIGHT)
+        .map(|row| {
+            let mut line: String = row
+                .iter()
+                .map(|&p| if p != 0 { '#' } else { '.' })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+/// Parses the command line and runs what it asks for
+pub fn run() -> ExitCode {
+    let cli = Cli::parse();
+
+    // The terminal frontend owns the terminal, its log only goes to memory
+    let tui = match &cli.command {
+        None => cli.run.tui,
+        Some(Command::Run(args)) => args.tui,
+        Some(_) => false,
+    };
+    logging::init(cli.log.as_deref(), !tui);
+
+    let result = match cli.command {
+        None => run_rom(cli.run),
+        Some(Command::Run(args)) => run_rom(args),
+        Some(Command::Headless(args)) => headless(args),
+        Some(Command::Disasm { rom, output }) => disassemble(&rom, output.as_deref()),
+        Some(Command::Asm {
+            source,
+            output,
+            map,
+        }) => assemble(&source, output, map.as_deref()),
+        Some(Command::Info { roms }) => info(&roms),
+        Some(Command::Test(args)) => test(args),
+    };
+    // Drops from a flood that was still going when the run ended
+    log::logger().flush();
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_rom(args: RunArgs) -> Result<ExitCode, Error> {
+    let settings_path = args.settings.clone().or_else(Settings::default_path);
+    let settings = match &settings_path {
+        Some(path) => Settings::load(path)?,
+        None => Settings::default(),
+    };
+
+    if args.tui {
+        let Some(path) = &args.rom else {
+            Cli::command()
+                .error(ErrorKind::MissingRequiredArgument, "--tui needs a ROM")
+                .exit();
+        };
+
+        let (mut chip8, mut scheduler, _) = args
+            .emulation
+            .machine(path, &read_rom(path)?)
+            .unwrap_or_else(|e| usage_error(e));
+        let glyphs = if args.braille {
+            Glyphs::Braille
+        } else {
+            Glyphs::HalfBlock
+        };
+
+        let keymap = match args.keymap {
+            Some(keymap) => keymap,
+            None => Keymap::parse(&settings.keymap)?,
+        };
+
+        let mut tui = Tui::new(keymap, Duration::from_millis(args.key_hold), glyphs);
+        tui.run(&mut chip8, &mut scheduler)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut overrides = args.emulation.overrides();
+    overrides.palette = args.palette;
+
+    let options = Options {
+        overrides,
+        keymap: args.keymap,
+        rom_dir: args.rom_dir,
+        fullscreen: args.fullscreen,
+        scale: args.scale,
+        seed: args.emulation.seed,
+        mute: args.mute,
+    };
+
+    let mut window = settings.window.clone();
+    window.fullscreen |= args.fullscreen;
+    let system = support::init(file!(), &window, &settings.layout);
+
+    let mut app = App::new(Scheduler::default(), options, settings, settings_path);
+    if let Some(path) = &args.rom {
+        app.load_rom(path);
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &args.script {
+        app.load_script(path);
+    }
+
+    system.main_loop(move |_, ui, frame| {
+        app.frame(ui, frame);
+    });
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn headless(args: HeadlessArgs) -> Result<ExitCode, Error> {
+    let rom = read_rom(&args.rom)?;
+    let (mut chip8, mut scheduler, _) = args
+        .emulation
+        .machine(&args.rom, &rom)
+        .unwrap_or_else(|e| usage_error(e));
+    chip8.symbols = Symbols::for_rom(&args.rom).unwrap_or_else(|e| {
+        warn!(target: logging::FRONTEND, "ignoring symbols: {:#}", e);
+        Symbols::default()
+    });
+
+    let mut profiler = Profiler::new(chip8.memory.len());
+    if args.profile.is_some() {
+        profiler.start(&chip8);
+    }
+
+    let mut coverage = Coverage::new(chip8.memory.len());
+
+    #[cfg(feature = "scripting")]
+    let mut script = match &args.script {
+        Some(path) => Some(Script::load(path, &mut chip8, &mut scheduler)?),
+        None => None,
+    };
+
+    let mut frames = 0;
+    while frames < args.frames {
+        if args.until_idle && chip8.idle().is_some_and(|idle| idle.is_stuck()) {
+            break;
+        }
+
+        let on_step = |pc, chip8: &Chip8| {
+            profiler.record(pc, chip8);
+            coverage.record(pc, chip8);
+        };
+
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &mut script {
+            for line in script.take_output() {
+                println!("{}", line);
+            }
+            if script.stopped() {
+                break;
+            }
+            script.run_frame(&mut chip8, &mut scheduler, on_step)?;
+            frames += 1;
+            continue;
+        }
+
+        scheduler.run_frame_with(&mut chip8, on_step);
+        frames += 1;
+    }
+
+    #[cfg(feature = "scripting")]
+    if let Some(script) = &mut script {
+        for line in script.take_output() {
+            println!("{}", line);
+        }
+        for text in script.overlay() {
+            println!("text: {},{} {}", text.x, text.y, text.text);
+        }
+    }
+
+    if let Some(path) = &args.profile {
+        fs::write(path, profiler.collapsed(&chip8.symbols))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    if let Some(path) = &args.coverage {
+        fs::write(path, coverage.report(&rom, &chip8.symbols))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    let v: Vec<String> = chip8.v.iter().map(|v| format!("{:02X}", v)).collect();
+    println!("frames: {}", frames);
+    println!("pc: {:#05X}", chip8.pc);
+    println!("i: {:#05X}", chip8.i);
+    println!("v: {}", v.join(" "));
+    println!("dt: {}", chip8.delay_timer);
+    println!("st: {}", chip8.sound_timer);
+    if let Some(fault) = chip8.fault {
+        println!("fault: {}", fault);
+    }
+    let idle = chip8.idle();
+    if let Some(idle) = idle {
+        println!("idle: {}", idle);
+    }
+    if args.coverage.is_some() {
+        println!("coverage: {}", coverage.summary(&rom));
+    }
+
+    if args.screen {
+        print!("{}", screen_text(&chip8));
+    }
+
+    if args.until_idle && !idle.is_some_and(|idle| idle.is_stuck()) {
+        eprintln!("error: still running after {} frames", frames);
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn disassemble(rom: &Path, output: Option<&Path>) -> Result<ExitCode, Error> {
+    let symbols = Symbols::for_rom(rom)?;
+    let source = disasm::to_source(&disasm::disassemble_with(&read_rom(rom)?, &symbols));
+
+    match output {
+        Some(path) => fs::write(path, source)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => print!("{}", source),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn assemble(source: &Path, output: Option<PathBuf>, map: Option<&Path>) -> Result<ExitCode, Error> {
+    let text = fs::read_to_string(source)
+        .with_context(|| format!("failed to read {}", source.display()))?;
+    let assembly =
+        asm::assemble(&text).with_context(|| format!("failed to assemble {}", source.display()))?;
+
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    fs::write(&output, &assembly.rom)
+        .with_context(|| format!("failed to write {}", output.display()))?;
+
+    if let Some(map) = map {
+        fs::write(map, assembly.map())
+            .with_context(|| format!("failed to write {}", map.display()))?;
+    }
+
+    println!("{}: {} bytes", output.display(), assembly.rom.len());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn info(roms: &[PathBuf]) -> Result<ExitCode, Error> {
+    for (index, path) in roms.iter().enumerate() {
+        let rom = read_rom(path)?;
+        let profile = romdb::bundled().profile(&rom);
+
+        if index > 0 {
+            println!();
+        }
+        println!("file: {}", path.display());
+        println!("size: {}", rom.len());
+        println!("sha1: {}", profile.sha1);
+        if let Some(title) = &profile.title {
+            println!("title: {}", title);
+        }
+        if !profile.authors.is_empty() {
+            println!("authors: {}", profile.authors.join(", "));
+        }
+        println!("known: {}", profile.known);
+        println!("platform: {}", profile.platform.id());
+        println!("cycles per frame: {}", profile.cycles_per_frame);
+
+        let quirks = profile.quirks;
+        let enabled: Vec<&str> = QuirkSet::NAMES
+            .iter()
+            .zip([
+                quirks.shift,
+                quirks.memory_increment_by_x,
+                quirks.memory_leave_i_unchanged,
+                quirks.wrap,
+                quirks.jump,
+                quirks.vblank,
+                quirks.logic,
+                quirks.key_press,
+            ])
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| *name)
+            .collect();
+        println!("quirks: {}", enabled.join(","));
+
+        for note in &profile.notes {
+            println!("note: {}", note);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Conformance ROMs in `dir`: those with an expected screen next to them
+fn find_tests(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("failed to list {}", dir.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext != "screen"))
+        .filter(|path| path.with_extension("screen").is_file())
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+fn test(args: TestArgs) -> Result<ExitCode, Error> {
+    let roms = if args.roms.is_empty() {
+        find_tests(&args.dir)?
+    } else {
+        args.roms
+    };
+    if roms.is_empty() {
+        bail!("no ROMs with a .screen file in {}", args.dir.display());
+    }
+
+    // Same numbers every run
+    let emulation = EmulationArgs {
+        platform: None,
+        quirks: None,
+        speed: None,
+        seed: Some(0),
+    };
+
+    if let Some(dir) = &args.coverage {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+
+    let mut failed = 0;
+    for path in &roms {
+        let rom = read_rom(path)?;
+        let (mut chip8, mut scheduler, _) = match emulation.machine(path, &rom) {
+            Ok(machine) => machine,
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}", path.display());
+                println!("{:#}", e);
+                continue;
+            }
+        };
+        let mut coverage = Coverage::new(chip8.memory.len());
+        for _ in 0..args.frames {
+            scheduler.run_frame_with(&mut chip8, |pc, chip8| coverage.record(pc, chip8));
+        }
+
+        let screen = screen_text(&chip8);
+        let expected_path = path.with_extension("screen");
+
+        if args.bless {
+            fs::write(&expected_path, &screen)
+                .with_context(|| format!("failed to write {}", expected_path.display()))?;
+            println!("blessed {}", path.display());
+        } else {
+            let expected = fs::read_to_string(&expected_path)
+                .with_context(|| format!("failed to read {}", expected_path.display()))?;
+
+            if expected.trim_end() == screen.trim_end() {
+                println!("ok   {}", path.display());
+            } else {
+                failed += 1;
+                println!("FAIL {}", path.display());
+                println!("expected:");
+                print!("{}", expected);
+                println!("got:");
+                print!("{}", screen);
+            }
+        }
+
+        if let Some(dir) = &args.coverage {
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            let report_path = dir.join(name).with_extension("cov");
+            let symbols = Symbols::for_rom(path)?;
+            fs::write(&report_path, coverage.report(&rom, &symbols))
+                .with_context(|| format!("failed to write {}", report_path.display()))?;
+            println!("     coverage: {}", coverage.summary(&rom));
+        }
+    }
+
+    if args.bless {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("{} passed, {} failed", roms.len() - failed, failed);
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,209 @@
+use crate::chip::Chip8;
+use crate::disasm::{self, Line};
+use crate::scheduler::Scheduler;
+use crate::symbols::Symbols;
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+
+const BREAKPOINT_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
+///Window listing the ROM's disassembly, for setting breakpoints and following PC
+#[derive(Debug)]
+pub struct CodeViewer {
+    pub open: bool,
+
+    lines: Vec<Line>,
+
+    ///Scroll to PC whenever it moves while paused
+    follow_pc: bool,
+
+    ///PC last frame, if paused then
+    paused_pc: Option<u16>,
+
+    ///Label or address typed in to break at
+    break_input: String,
+    error: Option<String>,
+}
+
+impl CodeViewer {
+    pub fn new() -> Self {
+        CodeViewer {
+            open: false,
+            lines: Vec::new(),
+            follow_pc: true,
+            paused_pc: None,
+            break_input: String::new(),
+            error: None,
+        }
+    }
+
+    /// Disassembles a newly loaded ROM
+    pub fn load(&mut self, rom: &[u8], symbols: &Symbols) {
+        self.lines = disasm::disassemble_with(rom, symbols);
+        self.error = None;
+    }
+
+    /// Index of the line holding `addr`
+    fn line_at(&self, addr: u16) -> Option<usize> {
+        let index = self.lines.partition_point(|line| line.addr <= addr);
+        let line = self.lines.get(index.checked_sub(1)?)?;
+        (addr < line.addr + line.bytes.len() as u16).then_some(index - 1)
+    }
+
+    /// Draws the listing if it is open, clicking a line toggles a breakpoint on it
+    pub fn draw(&mut self, ui: &Ui, chip8: &Chip8, scheduler: &mut Scheduler) {
+        if !self.open {
+            return;
+        }
+
+        let symbols = &chip8.symbols;
+        let mut open = self.open;
+        ui.window("Disassembly")
+            .opened(&mut open)
+            .size([520.0, 460.0], Condition::FirstUseEver)
+            .position([620.0, 40.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.set_next_item_width(140.0);
+                if ui
+                    .input_text("Break at", &mut self.break_input)
+                    .hint("Label or address")
+                    .enter_returns_true(true)
+                    .build()
+                {
+                    let name = self.break_input.trim();
+                    let hex = name.trim_start_matches("0x").trim_start_matches('$');
+                    let addr = u16::from_str_radix(hex, 16)
+                        .ok()
+                        .filter(|addr| *addr <= 0xFFF);
+                    // A label wins over hex, `add` could be either
+                    match symbols.address(name).or(addr) {
+                        Some(addr) => {
+                            scheduler.breakpoints.insert(addr);
+                            self.break_input.clear();
+                            self.error = None;
+                        }
+                        None => self.error = Some(format!("No label or address {:?}", name)),
+                    }
+                }
+                ui.same_line();
+                ui.checkbox("Follow PC", &mut self.follow_pc);
+
+                if let Some(error) = &self.error {
+                    ui.text_colored(ERROR_COLOR, error);
+                }
+
+                let mut removed = None;
+                for &addr in &scheduler.breakpoints {
+                    let _id = ui.push_id_usize(addr as usize);
+                    if ui.small_button("x") {
+                        removed = Some(addr);
+                    }
+                    ui.same_line();
+                    match symbols.describe(addr) {
+                        Some(name) => ui.text(format!("{:03X} {}", addr, name)),
+                        None => ui.text(format!("{:03X}", addr)),
+                    }
+                }
+                if let Some(addr) = removed {
+                    scheduler.breakpoints.remove(&addr);
+                }
+
+                if scheduler.paused && scheduler.breakpoints.contains(&chip8.pc) {
+                    ui.text_colored(BREAKPOINT_COLOR, format!("Breakpoint at {:03X}", chip8.pc));
+                }
+                if let Some((number, text)) = symbols.source_line(chip8.pc) {
+                    ui.text(format!("Line {}:", number));
+                    ui.same_line();
+                    ui.text_disabled(text.trim());
+                }
+                ui.separator();
+
+                if self.lines.is_empty() {
+                    ui.text_disabled("No ROM loaded");
+                    return;
+                }
+
+                self.listing(ui, chip8, scheduler);
+            });
+        self.open = open;
+    }
+
+    fn listing(&mut self, ui: &Ui, chip8: &Chip8, scheduler: &mut Scheduler) {
+        let symbols = &chip8.symbols;
+        let Some(_table) = ui.begin_table_header_with_sizing(
+            "code",
+            [
+                TableColumnSetup::new(""),
+                TableColumnSetup::new("Addr"),
+                TableColumnSetup::new("Bytes"),
+                TableColumnSetup::new("Label"),
+                TableColumnSetup::new("Instruction"),
+                TableColumnSetup::new("Source"),
+            ],
+            TableFlags::ROW_BG | TableFlags::RESIZABLE | TableFlags::SCROLL_Y,
+            [0.0, 0.0],
+            0.0,
+        ) else {
+            return;
+        };
+
+        let row_height = ui.text_line_height_with_spacing();
+        let current = self.line_at(chip8.pc);
+        if self.follow_pc && scheduler.paused && self.paused_pc != Some(chip8.pc) {
+            if let Some(index) = current {
+                let height = ui.window_size()[1];
+                ui.set_scroll_y((index as f32 * row_height - height / 3.0).max(0.0));
+            }
+        }
+        self.paused_pc = scheduler.paused.then_some(chip8.pc);
+
+        let mut toggled = None;
+        let clipper = ListClipper::new(self.lines.len() as i32)
+            .items_height(row_height)
+            .begin(ui);
+        for index in clipper.iter() {
+            let line = &self.lines[index as usize];
+            ui.table_next_row();
+
+            ui.table_next_column();
+            if scheduler.breakpoints.contains(&line.addr) {
+                ui.text_colored(BREAKPOINT_COLOR, "*");
+                ui.same_line();
+            }
+            if ui
+                .selectable_config(format!("##line{}", line.addr))
+                .span_all_columns(true)
+                .selected(current == Some(index as usize))
+                .build()
+            {
+                toggled = Some(line.addr);
+            }
+
+            ui.table_next_column();
+            ui.text(format!("{:03X}", line.addr));
+
+            ui.table_next_column();
+            let bytes: String = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            ui.text_disabled(bytes);
+
+            ui.table_next_column();
+            if let Some(label) = &line.label {
+                ui.text(label);
+            }
+
+            ui.table_next_column();
+            ui.text(&line.text);
+
+            ui.table_next_column();
+            if let Some((number, text)) = symbols.source_line(line.addr) {
+                ui.text_disabled(format!("{:>4}  {}", number, text.trim()));
+            }
+        }
+
+        if let Some(addr) = toggled {
+            if !scheduler.breakpoints.remove(&addr) {
+                scheduler.breakpoints.insert(addr);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::logging::{self, Entry};
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+use log::Level;
+
+///Levels in the order of the level combo, each showing itself and everything more severe
+const LEVELS: [Level; 5] = [
+    Level::Error,
+    Level::Warn,
+    Level::Info,
+    Level::Debug,
+    Level::Trace,
+];
+
+const LEVEL_NAMES: [&str; 5] = ["Error", "Warn", "Info", "Debug", "Trace"];
+
+///Recent log messages, filtered by level, category and text
+#[derive(Debug)]
+pub struct LogConsole {
+    pub open: bool,
+
+    ///Index into `LEVELS` of the least severe level shown
+    level: usize,
+
+    ///Whether each of `logging::CATEGORIES` is shown, then everything else
+    categories: [bool; logging::CATEGORIES.len() + 1],
+
+    ///Only messages containing this are shown, ignoring case
+    search: String,
+
+    ///Keep the newest message in view
+    auto_scroll: bool,
+}
+
+impl Default for LogConsole {
+    fn default() -> Self {
+        LogConsole {
+            open: false,
+            level: LEVELS.len() - 1,
+            categories: [true; logging::CATEGORIES.len() + 1],
+            search: String::new(),
+            auto_scroll: true,
+        }
+    }
+}
+
+impl LogConsole {
+    pub fn draw(&mut self, ui: &Ui) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window("Log")
+            .opened(&mut open)
+            .size([560.0, 300.0], Condition::FirstUseEver)
+            .position([40.0, 420.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.set_next_item_width(90.0);
+                ui.combo_simple_string("Level", &mut self.level, &LEVEL_NAMES);
+                ui.same_line();
+                ui.set_next_item_width(160.0);
+                ui.input_text("##search", &mut self.search)
+                    .hint("Search")
+                    .build();
+                ui.same_line();
+                ui.checkbox("Auto-scroll", &mut self.auto_scroll);
+                ui.same_line();
+                if ui.button("Clear") {
+                    logging::clear();
+                }
+
+                let names = logging::CATEGORIES.iter().chain(&["other"]);
+                for (i, (name, shown)) in names.zip(&mut self.categories).enumerate() {
+                    if i > 0 {
+                        ui.same_line();
+                    }
+                    ui.checkbox(name, shown);
+                }
+                ui.separator();
+
+                ui.child_window("##messages").build(|| {
+                    let search = self.search.to_lowercase();
+                    logging::recent(|entries| {
+                        for entry in entries.iter().filter(|entry| self.shows(entry, &search)) {
+                            ui.text_colored(
+                                level_color(entry.level),
+                                format!(
+                                    "{:8.3} {:5} {}: {}",
+                                    entry.time.as_secs_f32(),
+                                    entry.level,
+                                    entry.target,
+                                    entry.message
+                                ),
+                            );
+                        }
+                    });
+                    if self.auto_scroll && ui.scroll_y() >= ui.scroll_max_y() {
+                        ui.set_scroll_here_y_with_ratio(1.0);
+                    }
+                });
+            });
+        self.open = open;
+    }
+
+    fn shows(&self, entry: &Entry, search: &str) -> bool {
+        let category = logging::CATEGORIES
+            .iter()
+            .position(|name| *name == entry.target)
+            .unwrap_or(logging::CATEGORIES.len());
+
+        entry.level <= LEVELS[self.level]
+            && self.categories[category]
+            && (search.is_empty() || entry.message.to_lowercase().contains(search))
+    }
+}
+
+fn level_color(level: Level) -> [f32; 4] {
+    match level {
+        Level::Error => ERROR_COLOR,
+        Level::Warn => [1.0, 0.8, 0.2, 1.0],
+        Level::Info => [1.0, 1.0, 1.0, 1.0],
+        Level::Debug | Level::Trace => [0.6, 0.6, 0.6, 1.0],
+    }
+}
@@ -0,0 +1,305 @@
+use crate::chip::Chip8;
+use crate::disasm::{self, Line};
+use crate::symbols::Symbols;
+use std::fmt;
+use std::fmt::Write as _;
+
+///Branch outcomes, a skip instruction that went both ways has both bits set
+const FELL_THROUGH: u8 = 1;
+const SKIPPED: u8 = 2;
+
+///Instruction, branch and data totals for one ROM
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    ///Instructions the disassembler found in the ROM
+    pub instructions: usize,
+    pub executed: usize,
+
+    ///Ways the skip instructions can go, two each
+    pub branches: usize,
+    pub branches_taken: usize,
+
+    ///ROM bytes the disassembler took for data
+    pub data: usize,
+    pub data_read: usize,
+
+    ///ROM bytes written to, code or data
+    pub written: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} instructions ({:.1}%), {}/{} branches ({:.1}%), {}/{} data bytes read ({:.1}%), {} bytes written",
+            self.executed,
+            self.instructions,
+            percent(self.executed, self.instructions),
+            self.branches_taken,
+            self.branches,
+            percent(self.branches_taken, self.branches),
+            self.data_read,
+            self.data,
+            percent(self.data_read, self.data),
+            self.written
+        )
+    }
+}
+
+///Which bytes of memory a run executed, read as data and wrote
+#[derive(Debug)]
+pub struct Coverage {
+    ///Bytes fetched as part of an instruction
+    executed: Vec<bool>,
+
+    ///Bytes DXYN drew or FX65 loaded
+    read: Vec<bool>,
+
+    ///Bytes FX33 or FX55 stored to
+    written: Vec<bool>,
+
+    ///Ways each skip instruction went, see `FELL_THROUGH` and `SKIPPED`
+    branches: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new(size: usize) -> Self {
+        Coverage {
+            executed: vec![false; size],
+            read: vec![false; size],
+            written: vec![false; size],
+            branches: vec![0; size],
+        }
+    }
+
+    /// Notes what the instruction at `pc` that just ran touched, see `Scheduler::update_with`
+    pub fn record(&mut self, pc: u16, chip8: &Chip8) {
+        if chip8.fault.is_some() {
+            return;
+        }
+
+        let len = self.executed.len();
+        let pc = pc as usize;
+        self.executed[pc % len] = true;
+        self.executed[(pc + 1) % len] = true;
+
+        if is_skip(chip8.opcode) {
+            let next = chip8.pc as usize;
+            if next == pc + 2 {
+                self.branches[pc % len] |= FELL_THROUGH;
+            } else if next > pc + 2 {
+                self.branches[pc % len] |= SKIPPED;
+            }
+        }
+
+        if let Some((start, count)) = chip8.last_read {
+            mark(&mut self.read, start, count);
+        }
+        if let Some((start, count)) = chip8.last_write {
+            mark(&mut self.written, start, count);
+        }
+    }
+
+    /// Totals for a ROM loaded at 0x200
+    pub fn summary(&self, rom: &[u8]) -> Summary {
+        self.summarize(&disasm::disassemble(rom))
+    }
+
+    fn summarize(&self, lines: &[Line]) -> Summary {
+        let mut summary = Summary::default();
+
+        for line in lines {
+            let addr = line.addr as usize;
+            let range = addr..addr + line.bytes.len();
+
+            if line.is_data() {
+                summary.data += line.bytes.len();
+                summary.data_read += range.clone().filter(|&a| self.read[a]).count();
+            } else {
+                summary.instructions += 1;
+                if self.executed[addr] {
+                    summary.executed += 1;
+                }
+                if is_skip(opcode(&line.bytes)) {
+                    summary.branches += 2;
+                    summary.branches_taken += self.branches[addr].count_ones() as usize;
+                }
+            }
+            summary.written += range.filter(|&a| self.written[a]).count();
+        }
+
+        summary
+    }
+
+    /// Disassembly of a ROM loaded at 0x200 with what happened to each line, and the summary on top
+    ///
+    /// Each line starts with `X` if it ran, `!` if it's an instruction that never did, then `R` if
+    /// any of its bytes were read as data and `W` if any were written. Skip instructions that only
+    /// ever went one way are noted at the end of the line.
+    pub fn report(&self, rom: &[u8], symbols: &Symbols) -> String {
+        let lines = disasm::disassemble_with(rom, symbols);
+        let mut text = String::new();
+
+        let _ = writeln!(text, "; {}", self.summarize(&lines));
+        let _ = writeln!(
+            text,
+            "; X ran, ! never ran, R read as data, W written, * branch only went one way"
+        );
+        let _ = writeln!(text);
+
+        for line in &lines {
+            if let Some(label) = &line.label {
+                let _ = writeln!(text, "{:5}{}:", "", label);
+            }
+
+            let addr = line.addr as usize;
+            let range = addr..addr + line.bytes.len();
+            let executed = if range.clone().any(|a| self.executed[a]) {
+                'X'
+            } else if line.is_data() {
+                ' '
+            } else {
+                '!'
+            };
+            let read = if range.clone().any(|a| self.read[a]) {
+                'R'
+            } else {
+                ' '
+            };
+            let written = if range.clone().any(|a| self.written[a]) {
+                'W'
+            } else {
+                ' '
+            };
+
+            let branch = match self.branches[addr] {
+                _ if line.is_data() || !is_skip(opcode(&line.bytes)) => "",
+                FELL_THROUGH => "  * never skipped",
+                SKIPPED => "  * always skipped",
+                _ => "",
+            };
+
+            let bytes: String = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let _ = writeln!(
+                text,
+                "{}{}{}  {:03X}  {:<16}  {}{}",
+                executed, read, written, line.addr, bytes, line.text, branch
+            );
+        }
+
+        text
+    }
+}
+
+/// SE, SNE, SKP and SKNP, which go one of two ways
+fn is_skip(opcode: u16) -> bool {
+    match opcode & 0xF000 {
+        0x3000 | 0x4000 => true,
+        0x5000 | 0x9000 => opcode & 0x000F == 0,
+        0xE000 => matches!(opcode & 0x00FF, 0x9E | 0xA1),
+        _ => false,
+    }
+}
+
+fn opcode(bytes: &[u8]) -> u16 {
+    match bytes {
+        [hi, lo, ..] => u16::from_be_bytes([*hi, *lo]),
+        _ => 0,
+    }
+}
+
+fn mark(bytes: &mut [bool], start: u16, count: u16) {
+    let len = bytes.len();
+    for addr in start as usize..start as usize + count as usize {
+        bytes[addr % len] = true;
+    }
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///LD V0 0, SE V0 0 over a CLS, FX65 and FX55 at 0x210, then a jump to itself and data
+    const ROM: [u8; 20] = [
+        0x60, 0x00, 0x30, 0x00, 0x00, 0xE0, 0xA2, 0x10, 0xF1, 0x65, 0xF0, 0x55, 0x12, 0x0C, 0x00,
+        0x00, 0x12, 0x34, 0x56, 0x78,
+    ];
+
+    fn covered(cycles: usize) -> Coverage {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(&ROM).unwrap();
+        let mut coverage = Coverage::new(chip8.memory.len());
+        for _ in 0..cycles {
+            let pc = chip8.pc;
+            chip8.emulate_cycle();
+            coverage.record(pc, &chip8);
+        }
+        coverage
+    }
+
+    #[test]
+    fn bytes_are_sorted_into_executed_read_and_written() {
+        let coverage = covered(10);
+        assert!(coverage.executed[0x200..0x204].iter().all(|&b| b));
+        assert!(!coverage.executed[0x204] && !coverage.executed[0x205]);
+        assert!(coverage.executed[0x206..0x20E].iter().all(|&b| b));
+        assert_eq!(coverage.read[0x20F..0x213], [false, true, true, false]);
+        assert_eq!(coverage.written[0x20F..0x212], [false, true, false]);
+        assert_eq!(coverage.branches[0x202], SKIPPED);
+    }
+
+    #[test]
+    fn summary_counts_and_percentages() {
+        let summary = covered(10).summary(&ROM);
+        assert_eq!(
+            summary,
+            Summary {
+                instructions: 7,
+                executed: 6,
+                branches: 2,
+                branches_taken: 1,
+                data: 6,
+                data_read: 2,
+                written: 1,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "6/7 instructions (85.7%), 1/2 branches (50.0%), 2/6 data bytes read (33.3%), 1 bytes written"
+        );
+    }
+
+    #[test]
+    fn nothing_run_is_zero_percent_and_nothing_to_run_is_all_of_it() {
+        let summary = covered(0).summary(&ROM);
+        assert_eq!((summary.executed, summary.branches_taken), (0, 0));
+        assert!(summary.to_string().starts_with("0/7 instructions (0.0%)"));
+        assert_eq!(
+            Coverage::new(4096).summary(&[]).to_string(),
+            "0/0 instructions (100.0%), 0/0 branches (100.0%), 0/0 data bytes read (100.0%), 0 bytes written"
+        );
+    }
+
+    #[test]
+    fn report_marks_each_line() {
+        let report = covered(10).report(&ROM, &Symbols::default());
+        let line = |addr: &str| {
+            report
+                .lines()
+                .find(|l| l.get(5..8) == Some(addr))
+                .unwrap_or_else(|| panic!("no line at {} in\n{}", addr, report))
+        };
+
+        assert!(line("202").starts_with("X  ") && line("202").ends_with("* always skipped"));
+        assert!(line("204").starts_with("!  "));
+        assert!(line("210").starts_with(" RW"));
+    }
+}
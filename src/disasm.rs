@@ -0,0 +1,210 @@
+use crate::analyzer;
+use crate::chip::START;
+use crate::opcodes;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+///Most bytes in one `DB` line
+const DATA_PER_LINE: usize = 8;
+
+///One line of a disassembly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+
+    ///Name of the line when something jumps, calls or points I at it
+    pub label: Option<String>,
+
+    ///Instruction, or a `DB` directive for data
+    pub text: String,
+}
+
+impl Line {
+    /// Whether the line is data rather than an instruction
+    pub fn is_data(&self) -> bool {
+        self.text.starts_with("DB ")
+    }
+}
+
+///What made an address worth naming, later variants win
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Data,
+    Jump,
+    Call,
+}
+
+impl Target {
+    fn label(self, addr: usize) -> String {
+        match self {
+            Target::Data => format!("data_{:03X}", addr),
+            Target::Jump => format!("code_{:03X}", addr),
+            Target::Call => format!("sub_{:03X}", addr),
+        }
+    }
+}
+
+fn opcode_at(rom: &[u8], offset: usize) -> Option<u16> {
+    Some(((*rom.get(offset)? as u16) << 8) | *rom.get(offset + 1)? as u16)
+}
+
+/// Length of the reachable instruction at `offset`, `None` if it's data
+fn instruction_len(rom: &[u8], code: &[bool], offset: usize) -> Option<usize> {
+    if !code[offset] {
+        return None;
+    }
+
+    match opcode_at(rom, offset)? {
+        0xF000 => opcode_at(rom, offset + 2).map(|_| 4),
+        opcode => opcodes::decode(opcode).map(|_| 2),
+    }
+}
+
+/// Disassembles a ROM loaded at 0x200, following its code so data isn't shown as instructions
+pub fn disassemble(rom: &[u8]) -> Vec<Line> {
+    disassemble_with(rom, &Symbols::default())
+}
+
+/// Same as `disassemble`, naming addresses after the labels in `symbols` where it has them
+pub fn disassemble_with(rom: &[u8], symbols: &Symbols) -> Vec<Line> {
+    let analysis = analyzer::analyze(rom);
+    let code = &analysis.code;
+
+    // Addresses the code refers to
+    let mut targets: HashMap<usize, Target> = HashMap::new();
+    for offset in (0..rom.len()).filter(|&o| code[o]) {
+        let Some(opcode) = opcode_at(rom, offset) else {
+            continue;
+        };
+        let (target, addr) = match opcode & 0xF000 {
+            0x1000 => (Target::Jump, opcode & 0x0FFF),
+            0x2000 => (Target::Call, opcode & 0x0FFF),
+            0xA000 => (Target::Data, opcode & 0x0FFF),
+            _ if opcode == 0xF000 => match opcode_at(rom, offset + 2) {
+                Some(addr) => (Target::Data, addr),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let entry = targets.entry(addr as usize).or_insert(target);
+        *entry = (*entry).max(target);
+    }
+    // Labelled addresses start lines even when nothing refers to them
+    for &addr in symbols.labels.keys() {
+        targets.entry(addr as usize).or_insert(Target::Data);
+    }
+
+    // Split the ROM into instructions and runs of data
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let len = instruction_len(rom, code, offset).unwrap_or_else(|| {
+            let mut len = 1;
+            while offset + len < rom.len()
+                && len < DATA_PER_LINE
+                && !code[offset + len]
+                && !targets.contains_key(&(START + offset + len))
+            {
+                len += 1;
+            }
+            len
+        });
+        spans.push((offset, len));
+        offset += len;
+    }
+
+    // Only name addresses that start a line, so every label can be assembled
+    let labels: HashMap<usize, String> = spans
+        .iter()
+        .filter_map(|&(offset, _)| {
+            let addr = START + offset;
+            let label = match symbols.label(addr as u16) {
+                Some(name) => name.to_string(),
+                None => targets.get(&addr)?.label(addr),
+            };
+            Some((addr, label))
+        })
+        .collect();
+    let symbol = |addr: u16| labels.get(&(addr as usize)).cloned();
+
+    spans
+        .into_iter()
+        .map(|(offset, len)| {
+            let bytes = rom[offset..offset + len].to_vec();
+
+            let text = match instruction_len(rom, code, offset) {
+                Some(4) => {
+                    let addr = opcode_at(rom, offset + 2).unwrap_or_default();
+                    let operand = symbol(addr).unwrap_or_else(|| format!("0x{:04X}", addr));
+                    format!("LD I, LONG {}", operand)
+                }
+                Some(_) => opcodes::format(opcode_at(rom, offset).unwrap_or_default(), symbol)
+                    .unwrap_or_default(),
+                None => {
+                    let data: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                    format!("DB {}", data.join(", "))
+                }
+            };
+
+            Line {
+                addr: (START + offset) as u16,
+                label: labels.get(&(START + offset)).cloned(),
+                bytes,
+                text,
+            }
+        })
+        .collect()
+}
+
+/// Writes a disassembly as source the assembler accepts, with addresses and bytes in comments
+pub fn to_source(lines: &[Line]) -> String {
+    let mut source = String::new();
+
+    for line in lines {
+        if let Some(label) = &line.label {
+            let _ = writeln!(source, "{}:", label);
+        }
+
+        let bytes: String = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let _ = writeln!(
+            source,
+            "    {:<32}; {:03X}: {}",
+            line.text, line.addr, bytes
+        );
+    }
+
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn bundled_roms_reassemble_to_the_same_bytes() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("roms");
+        let mut checked = 0;
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if !matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("ch8" | "rom")
+            ) {
+                continue;
+            }
+
+            let rom = std::fs::read(&path).unwrap();
+            let source = to_source(&disassemble(&rom));
+            let assembly =
+                asm::assemble(&source).unwrap_or_else(|e| panic!("{}: {:#}", path.display(), e));
+            assert!(assembly.rom == rom, "{} changed", path.display());
+            checked += 1;
+        }
+
+        assert!(checked > 0);
+    }
+}
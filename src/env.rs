@@ -0,0 +1,456 @@
+use crate::cheats::Location;
+use crate::chip::Chip8;
+use crate::romdb;
+use crate::scheduler::Scheduler;
+use anyhow::{anyhow, bail, Error};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+///Definitions bundled with the emulator, covering the games in `roms/` worth training on
+const BUNDLED: &str = include_str!("../data/games.toml");
+
+///How to play a ROM as an environment: its actions, rewards and when it's over
+#[derive(Debug, Clone, Deserialize)]
+pub struct Game {
+    pub title: String,
+
+    ///SHA-1s of the ROMs this describes
+    pub roms: Vec<String>,
+
+    ///Keys held down for each action, one hex digit per key, "" for none
+    #[serde(deserialize_with = "keys::deserialize")]
+    pub actions: Vec<u16>,
+
+    ///Frames to run after a reset before the game takes input
+    #[serde(default)]
+    pub start_frames: u32,
+
+    #[serde(default)]
+    pub rewards: Vec<Reward>,
+
+    ///The episode ends when any of these holds
+    #[serde(default)]
+    pub done: Vec<Condition>,
+}
+
+///A value whose change from one frame to the next is a reward, such as a score
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Reward {
+    #[serde(with = "location")]
+    pub location: Location,
+
+    ///Reward per unit the value goes up, negative for a penalty
+    #[serde(default = "one")]
+    pub scale: f32,
+}
+
+///A value that ends the episode, such as lives running out
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Condition {
+    #[serde(with = "location")]
+    pub location: Location,
+    pub equals: u8,
+}
+
+#[derive(Deserialize)]
+struct GameList {
+    #[serde(default)]
+    game: Vec<Game>,
+}
+
+impl Game {
+    /// Reads `[[game]]` tables in the format of `data/games.toml`
+    pub fn parse(text: &str) -> Result<Vec<Game>, Error> {
+        let games = toml::from_str::<GameList>(text)?.game;
+        if let Some(game) = games.iter().find(|game| game.actions.is_empty()) {
+            bail!("{} has no actions", game.title);
+        }
+        Ok(games)
+    }
+
+    /// The bundled definition for a ROM, if there is one
+    pub fn find(rom: &[u8]) -> Option<&'static Game> {
+        let sha1 = romdb::rom_hash(rom);
+        bundled().iter().find(|game| {
+            game.roms
+                .iter()
+                .any(|hash| hash.eq_ignore_ascii_case(&sha1))
+        })
+    }
+
+    fn is_over(&self, chip8: &Chip8) -> bool {
+        self.done
+            .iter()
+            .any(|condition| condition.location.read(chip8) == condition.equals)
+    }
+}
+
+/// Definitions bundled with the emulator
+pub fn bundled() -> &'static [Game] {
+    static GAMES: OnceLock<Vec<Game>> = OnceLock::new();
+    GAMES.get_or_init(|| Game::parse(BUNDLED).expect("bundled game definitions are valid"))
+}
+
+///How an environment runs episodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    ///Frames each action is held for, the rewards of all of them are added up
+    pub frame_skip: u32,
+
+    ///Chance each frame that the previous action is held again instead of the new one
+    pub sticky_actions: f32,
+
+    ///Frames after which an episode is cut short
+    pub max_frames: Option<u32>,
+
+    ///Seed for sticky actions and CXNN, random if unset
+    pub seed: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            frame_skip: 4,
+            sticky_actions: 0.0,
+            max_frames: None,
+            seed: None,
+        }
+    }
+}
+
+///What one call to `Env::step` did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub reward: f32,
+
+    ///The game is over, see `Game::done`
+    pub done: bool,
+
+    ///The episode hit `Options::max_frames` or the machine faulted
+    pub truncated: bool,
+
+    ///Frames since the reset, not counting `Game::start_frames`
+    pub frames: u32,
+}
+
+///A windowless machine played one action at a time, for reinforcement learning
+///
+///Observations are the display, one byte per pixel, 1 when lit
+#[derive(Debug)]
+pub struct Env {
+    pub chip8: Chip8,
+    pub game: Game,
+    pub options: Options,
+    scheduler: Scheduler,
+    rom: Vec<u8>,
+    rng: StdRng,
+
+    ///Action held during the last frame, for sticky actions
+    action: usize,
+
+    ///Values of the reward locations at the end of the last frame
+    values: Vec<u8>,
+
+    frames: u32,
+    over: bool,
+}
+
+impl Env {
+    pub fn new(rom: Vec<u8>, game: Game, options: Options) -> Result<Self, Error> {
+        if game.actions.is_empty() {
+            bail!("{} has no actions", game.title);
+        }
+        if options.frame_skip == 0 {
+            bail!("frame skip must be at least 1");
+        }
+        if !(0.0..1.0).contains(&options.sticky_actions) {
+            bail!("sticky action chance must be at least 0 and below 1");
+        }
+
+        let rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(&rom)?;
+
+        let mut env = Env {
+            chip8,
+            game,
+            options,
+            scheduler: Scheduler::default(),
+            rom,
+            rng,
+            action: 0,
+            values: Vec::new(),
+            frames: 0,
+            over: false,
+        };
+        env.reset();
+        Ok(env)
+    }
+
+    /// An environment using the bundled definition for the ROM
+    pub fn for_rom(rom: Vec<u8>, options: Options) -> Result<Self, Error> {
+        let game = Game::find(&rom).ok_or_else(|| {
+            anyhow!(
+                "no game definition for ROM {}, pass one in the format of data/games.toml",
+                romdb::rom_hash(&rom)
+            )
+        })?;
+        Env::new(rom, game.clone(), options)
+    }
+
+    /// Starts a new episode, returning the first observation
+    pub fn reset(&mut self) -> &[u8] {
+        let profile = romdb::bundled().profile(&self.rom);
+
+        self.chip8
+            .load_bytes(&self.rom)
+            .expect("the ROM fit when the environment was made");
+        self.chip8.quirks = profile.quirks;
+        self.chip8.seed(self.rng.gen());
+        self.scheduler = Scheduler::new(profile.cycles_per_frame);
+        for _ in 0..self.game.start_frames {
+            self.scheduler.run_frame(&mut self.chip8);
+        }
+
+        self.action = 0;
+        self.values = self.read_values();
+        self.frames = 0;
+        self.over = false;
+        self.observation()
+    }
+
+    /// Holds the keys of `action` for `Options::frame_skip` frames
+    pub fn step(&mut self, action: usize) -> Result<Step, Error> {
+        if action >= self.game.actions.len() {
+            bail!(
+                "action {} out of range, {} has {}",
+                action,
+                self.game.title,
+                self.game.actions.len()
+            );
+        }
+        if self.over {
+            bail!("the episode is over, reset to start another");
+        }
+
+        let mut step = Step {
+            reward: 0.0,
+            done: false,
+            truncated: false,
+            frames: self.frames,
+        };
+        for _ in 0..self.options.frame_skip {
+            if self.rng.gen::<f32>() >= self.options.sticky_actions {
+                self.action = action;
+            }
+            let keys = self.game.actions[self.action];
+            for key in 0..16 {
+                self.chip8.set_key(key, keys & (1 << key) != 0);
+            }
+
+            self.scheduler.run_frame(&mut self.chip8);
+            self.frames += 1;
+            step.reward += self.take_reward();
+
+            step.done = self.game.is_over(&self.chip8);
+            step.truncated = self.chip8.fault.is_some()
+                || self
+                    .options
+                    .max_frames
+                    .is_some_and(|max| self.frames >= max);
+            if step.done || step.truncated {
+                break;
+            }
+        }
+
+        self.over = step.done || step.truncated;
+        step.frames = self.frames;
+        Ok(step)
+    }
+
+    pub fn observation(&self) -> &[u8] {
+        &self.chip8.display
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.game.actions.len()
+    }
+
+    fn read_values(&self) -> Vec<u8> {
+        self.game
+            .rewards
+            .iter()
+            .map(|reward| reward.location.read(&self.chip8))
+            .collect()
+    }
+
+    /// Reward for the changes since the last call
+    fn take_reward(&mut self) -> f32 {
+        let values = self.read_values();
+        let reward = self
+            .game
+            .rewards
+            .iter()
+            .zip(values.iter().zip(&self.values))
+            .map(|(reward, (&new, &old))| (new as f32 - old as f32) * reward.scale)
+            .sum();
+        self.values = values;
+        reward
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+///Serde helpers reading locations the way the cheat file writes them
+mod location {
+    use crate::cheats::Location;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Location, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Location::parse(&text)
+            .ok_or_else(|| D::Error::custom(format!("{} is not an address or V register", text)))
+    }
+}
+
+///Serde helpers reading actions as hex key digits into key masks
+mod keys {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u16>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|keys| {
+                keys.chars().try_fold(0u16, |mask, key| {
+                    key.to_digit(16)
+                        .map(|key| mask | 1 << key)
+                        .ok_or_else(|| D::Error::custom(format!("{} is not a key", key)))
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Adds 1 to V1 once a frame, timed with the delay timer
+    const COUNTER: [u8; 16] = [
+        0x61, 0x00, 0x60, 0x01, 0xF0, 0x15, 0xF2, 0x07, 0x32, 0x00, 0x12, 0x06, 0x71, 0x01, 0x12,
+        0x02,
+    ];
+
+    fn game(extra: &str) -> Game {
+        let text = format!(
+            "[[game]]\ntitle = \"Counter\"\nroms = []\nactions = [\"\", \"5\"]\n{}",
+            extra
+        );
+        Game::parse(&text).unwrap().remove(0)
+    }
+
+    fn env(game: Game, max_frames: Option<u32>) -> Env {
+        let options = Options {
+            max_frames,
+            seed: Some(1),
+            ..Options::default()
+        };
+        Env::new(COUNTER.to_vec(), game, options).unwrap()
+    }
+
+    fn parse_error(game: &str) -> String {
+        let text = format!("[[game]]\ntitle = \"Bad\"\nroms = []\n{}", game);
+        format!("{:#}", Game::parse(&text).unwrap_err())
+    }
+
+    #[test]
+    fn actions_are_key_masks() {
+        let game = Game::parse(
+            "[[game]]\ntitle = \"Keys\"\nroms = []\nactions = [\"\", \"1\", \"4C\", \"fa\"]",
+        )
+        .unwrap()
+        .remove(0);
+        assert_eq!(
+            game.actions,
+            [0, 1 << 1, 1 << 4 | 1 << 0xC, 1 << 0xF | 1 << 0xA]
+        );
+    }
+
+    #[test]
+    fn bad_games_are_rejected() {
+        assert_eq!(parse_error("actions = []"), "Bad has no actions");
+        assert!(parse_error("actions = [\"1G\"]").contains("G is not a key"));
+        assert!(
+            parse_error("actions = [\"1\"]\n[[game.rewards]]\nlocation = \"V10\"")
+                .contains("V10 is not an address or V register")
+        );
+        assert!(
+            parse_error("actions = [\"1\"]\n[[game.done]]\nlocation = \"1000\"\nequals = 0")
+                .contains("1000 is not an address or V register")
+        );
+    }
+
+    #[test]
+    fn bundled_games_parse() {
+        assert!(!bundled().is_empty());
+    }
+
+    #[test]
+    fn rewards_follow_the_value_times_the_scale() {
+        let mut up = env(game("[[game.rewards]]\nlocation = \"V1\""), None);
+        let step = up.step(1).unwrap();
+        assert!(up.chip8.v[1] > 0);
+        assert_eq!(step.reward, up.chip8.v[1] as f32);
+        assert_eq!(step.frames, 4);
+        assert!(up.chip8.is_key_down(5));
+
+        let mut down = env(
+            game("[[game.rewards]]\nlocation = \"V1\"\nscale = -0.5"),
+            None,
+        );
+        let step = down.step(0).unwrap();
+        assert_eq!(step.reward, down.chip8.v[1] as f32 * -0.5);
+        assert!(step.reward < 0.0);
+    }
+
+    #[test]
+    fn episodes_end_when_done() {
+        let mut env = env(game("[[game.done]]\nlocation = \"V1\"\nequals = 6"), None);
+        let mut step = env.step(0).unwrap();
+        while !step.done {
+            assert!(!step.truncated);
+            step = env.step(0).unwrap();
+        }
+        assert_eq!(env.chip8.v[1], 6);
+        assert!(env.step(0).is_err());
+
+        env.reset();
+        assert_eq!(env.step(0).unwrap().frames, 4);
+    }
+
+    #[test]
+    fn episodes_are_cut_short_at_max_frames() {
+        let mut env = env(game(""), Some(6));
+        let step = env.step(0).unwrap();
+        assert!(!step.truncated);
+        let step = env.step(0).unwrap();
+        assert!(step.truncated && !step.done);
+        assert_eq!(step.frames, 6);
+        assert!(env.step(0).is_err());
+    }
+
+    #[test]
+    fn actions_out_of_range_are_errors() {
+        let mut env = env(game(""), None);
+        assert!(env.step(2).is_err());
+    }
+}
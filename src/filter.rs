@@ -0,0 +1,139 @@
+/// How the framebuffer is post-processed before it is handed to the renderer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// Raw framebuffer, pixels are either fully on or fully off
+    Off,
+
+    /// Lit pixels fade out over several frames instead of switching off instantly
+    ///
+    /// `decay` is the fraction of brightness lost every frame (0.0 - 1.0)
+    Phosphor { decay: f32 },
+
+    /// A pixel is lit if it was lit in either of the last two frames
+    Or,
+}
+
+///Flicker reduction filter sitting between the CHIP-8 framebuffer and the renderer
+///
+///Games that XOR-redraw their sprites every frame leave them switched off for part
+///of the time, this keeps some of that history around so they don't flicker.
+#[derive(Debug)]
+pub struct DisplayFilter {
+    pub mode: FilterMode,
+
+    ///Brightness of every pixel after filtering (0.0 - 1.0)
+    intensity: Vec<f32>,
+
+    ///Raw framebuffer of the previous frame
+    previous: Vec<u8>,
+
+    ///Output for the frame being fed in, before it replaces `intensity`
+    next: Vec<f32>,
+}
+
+impl DisplayFilter {
+    pub fn new(mode: FilterMode) -> Self {
+        DisplayFilter {
+            mode,
+            intensity: Vec::new(),
+            previous: Vec::new(),
+            next: Vec::new(),
+        }
+    }
+
+    /// Forget the pixel history, e.g. after loading a new ROM
+    pub fn reset(&mut self) {
+        self.intensity.clear();
+        self.previous.clear();
+    }
+
+    /// Feeds one finished frame of the CHIP-8 framebuffer through the filter
+    ///
+    /// Call it once per emulated frame, the decay is counted in frames.
+    /// Returns the brightness of every pixel, in the same layout as `display`
+    pub fn apply(&mut self, display: &[u8]) -> &[f32] {
+        self.filter(display);
+        std::mem::swap(&mut self.intensity, &mut self.next);
+        self.previous.copy_from_slice(display);
+        &self.intensity
+    }
+
+    /// What `apply` would return for `display`, without feeding it in
+    ///
+    /// For a frame that's still being drawn, e.g. while stepping through it
+    pub fn preview(&mut self, display: &[u8]) -> &[f32] {
+        self.filter(display);
+        &self.next
+    }
+
+    /// Brightness of every pixel after the last frame fed in, empty if there's been none since a reset
+    pub fn output(&self) -> &[f32] {
+        &self.intensity
+    }
+
+    fn filter(&mut self, display: &[u8]) {
+        if self.intensity.len() != display.len() {
+            self.intensity = vec![0.0; display.len()];
+            self.previous = display.to_vec();
+        }
+        self.next.resize(display.len(), 0.0);
+
+        for (i, pixel) in display.iter().enumerate() {
+            let lit = *pixel != 0;
+
+            self.next[i] = match self.mode {
+                FilterMode::Off => lit as u8 as f32,
+                FilterMode::Phosphor { decay } => {
+                    if lit {
+                        1.0
+                    } else {
+                        self.intensity[i] * (1.0 - decay.clamp(0.0, 1.0))
+                    }
+                }
+                FilterMode::Or => (lit || self.previous[i] != 0) as u8 as f32,
+            };
+        }
+    }
+}
+
+impl Default for DisplayFilter {
+    fn default() -> Self {
+        DisplayFilter::new(FilterMode::Off)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phosphor_fades_once_per_frame_fed_in() {
+        let mut filter = DisplayFilter::new(FilterMode::Phosphor { decay: 0.5 });
+        filter.apply(&[1, 0]);
+        assert_eq!(filter.apply(&[0, 0]), &[0.5, 0.0]);
+
+        // Looking at the frame being drawn doesn't count as a frame
+        assert_eq!(filter.preview(&[0, 1]), &[0.25, 1.0]);
+        assert_eq!(filter.preview(&[0, 1]), &[0.25, 1.0]);
+        assert_eq!(filter.output(), &[0.5, 0.0]);
+
+        assert_eq!(filter.apply(&[0, 0]), &[0.25, 0.0]);
+    }
+
+    #[test]
+    fn or_keeps_pixels_lit_in_the_previous_frame() {
+        let mut filter = DisplayFilter::new(FilterMode::Or);
+        filter.apply(&[1, 0, 0]);
+        assert_eq!(filter.apply(&[0, 1, 0]), &[1.0, 1.0, 0.0]);
+        assert_eq!(filter.apply(&[0, 0, 0]), &[0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn reset_forgets_the_history() {
+        let mut filter = DisplayFilter::new(FilterMode::Phosphor { decay: 0.5 });
+        filter.apply(&[1]);
+        filter.reset();
+        assert!(filter.output().is_empty());
+        assert_eq!(filter.apply(&[0]), &[0.0]);
+    }
+}
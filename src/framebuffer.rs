@@ -0,0 +1,145 @@
+use crate::palette::Palette;
+use anyhow::{Context, Error};
+use glium::texture::{RawImage2d, Texture2d};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior};
+use glium::{Display, Rect};
+use imgui::{Image, TextureId, Ui};
+use imgui_glium_renderer::{Renderer, Texture};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::rc::Rc;
+
+///CHIP-8 display uploaded to the GPU as a single texture every frame
+///
+///Drawing is one textured quad no matter the resolution, instead of one rectangle per pixel
+pub struct Framebuffer {
+    pub palette: Palette,
+
+    ///Only scale the display by whole numbers, keeping every pixel the same size
+    pub integer_scale: bool,
+
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+    texture: Option<(TextureId, Rc<Texture2d>)>,
+}
+
+impl Framebuffer {
+    pub fn new(palette: Palette) -> Self {
+        Framebuffer {
+            palette,
+            integer_scale: false,
+            width: 0,
+            height: 0,
+            rgba: Vec::new(),
+            texture: None,
+        }
+    }
+
+    /// Converts pixel brightness (0.0 - 1.0) into RGBA using the palette
+    pub fn fill(&mut self, intensity: &[f32], width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.rgba.resize(width * height * 4, 0xFF);
+
+        for (pixel, value) in self.rgba.chunks_exact_mut(4).zip(intensity) {
+            let channels = pixel.iter_mut().zip(self.palette.on).zip(self.palette.off);
+            for ((channel, on), off) in channels {
+                *channel = (off as f32 + (on as f32 - off as f32) * value) as u8;
+            }
+            pixel[3] = 0xFF;
+        }
+    }
+
+    /// Writes what was last filled in to a PNG, `scale` screen pixels per CHIP-8 pixel
+    pub fn save_png(&self, path: &Path, scale: u32) -> Result<(), Error> {
+        let scale = scale.max(1) as usize;
+        let (width, height) = (self.width * scale, self.height * scale);
+
+        let mut data = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = (y / scale * self.width + x / scale) * 4;
+                data.extend_from_slice(&self.rgba[pixel..pixel + 3]);
+            }
+        }
+
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Copies the RGBA buffer into the texture, creating and registering it with the renderer if needed
+    pub fn upload(&mut self, display: &Display, renderer: &mut Renderer) -> Result<(), Error> {
+        let (width, height) = (self.width as u32, self.height as u32);
+
+        if let Some((_, texture)) = &self.texture {
+            if texture.width() == width && texture.height() == height {
+                let image = RawImage2d::from_raw_rgba(self.rgba.clone(), (width, height));
+                let rect = Rect {
+                    left: 0,
+                    bottom: 0,
+                    width,
+                    height,
+                };
+                texture.write(rect, image);
+                return Ok(());
+            }
+        }
+
+        // First frame or the resolution changed
+        let image = RawImage2d::from_raw_rgba(self.rgba.clone(), (width, height));
+        let texture = Rc::new(Texture2d::new(display, image)?);
+        let sampler = SamplerBehavior {
+            magnify_filter: MagnifySamplerFilter::Nearest,
+            minify_filter: MinifySamplerFilter::Nearest,
+            ..Default::default()
+        };
+        let gpu_texture = Texture {
+            texture: texture.clone(),
+            sampler,
+        };
+
+        let id = match &self.texture {
+            Some((id, _)) => {
+                renderer.textures().replace(*id, gpu_texture);
+                *id
+            }
+            None => renderer.textures().insert(gpu_texture),
+        };
+        self.texture = Some((id, texture));
+
+        Ok(())
+    }
+
+    /// Draws the display as large as fits in the current window, keeping its aspect ratio
+    pub fn draw(&self, ui: &Ui) {
+        let Some((id, _)) = &self.texture else {
+            return;
+        };
+
+        let avail = ui.content_region_avail();
+        let mut scale = (avail[0] / self.width as f32).min(avail[1] / self.height as f32);
+        if self.integer_scale {
+            scale = scale.floor().max(1.0);
+        }
+
+        let size = [self.width as f32 * scale, self.height as f32 * scale];
+
+        // Center inside the window
+        let cursor = ui.cursor_pos();
+        ui.set_cursor_pos([
+            cursor[0] + ((avail[0] - size[0]) / 2.0).max(0.0),
+            cursor[1] + ((avail[1] - size[1]) / 2.0).max(0.0),
+        ]);
+
+        Image::new(*id, size).build(ui);
+    }
+}
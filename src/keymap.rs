@@ -0,0 +1,49 @@
+use anyhow::{anyhow, Error};
+
+/// Host key for each CHIP-8 key 0 through F
+///
+/// Maps the hex keypad onto the left side of a QWERTY keyboard:
+///
+/// ```text
+/// 1 2 3 C        1 2 3 4
+/// 4 5 6 D   <-   Q W E R
+/// 7 8 9 E        A S D F
+/// A 0 B F        Z X C V
+/// ```
+pub const DEFAULT_KEYMAP: &str = "x123qweasdzc4rfv";
+
+///Which host key presses which key on the hex keypad
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    keys: [char; 16],
+}
+
+impl Keymap {
+    /// Parses a keymap written as 16 characters, one per CHIP-8 key in order 0 through F
+    pub fn parse(keys: &str) -> Result<Self, Error> {
+        let chars: Vec<char> = keys.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+        let keys: [char; 16] = chars
+            .try_into()
+            .map_err(|_| anyhow!("keymap must have exactly 16 characters: {:?}", keys))?;
+
+        Ok(Keymap { keys })
+    }
+
+    /// CHIP-8 key pressed by the host key `c`, if any
+    pub fn key_for(&self, c: char) -> Option<usize> {
+        let c = c.to_ascii_lowercase();
+        self.keys.iter().position(|k| *k == c)
+    }
+
+    /// Host key bound to CHIP-8 key `key`
+    pub fn host_key(&self, key: usize) -> char {
+        self.keys[key]
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::parse(DEFAULT_KEYMAP).unwrap()
+    }
+}
@@ -0,0 +1,24 @@
+pub mod analyzer;
+pub mod asm;
+pub mod cheats;
+pub mod chip;
+pub mod coverage;
+pub mod disasm;
+pub mod env;
+pub mod filter;
+pub mod keymap;
+pub mod logging;
+pub mod opcodes;
+pub mod palette;
+pub mod platform;
+pub mod romdb;
+pub mod scheduler;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod settings;
+#[cfg(feature = "gui")]
+pub mod sound;
+pub mod symbols;
+pub mod ui;
+#[cfg(feature = "gui")]
+pub mod window;
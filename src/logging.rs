@@ -0,0 +1,306 @@
+use env_logger::Builder;
+use log::{Level, Log, Metadata, Record};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+///Instructions, faults and unknown opcodes
+pub const CPU: &str = "cpu";
+
+///Drawing and uploading the framebuffer
+pub const DISPLAY: &str = "display";
+
+///Keys and keymaps
+pub const INPUT: &str = "input";
+
+///Sound output
+pub const AUDIO: &str = "audio";
+
+///Windows, files and settings
+pub const FRONTEND: &str = "frontend";
+
+///Targets the emulator logs to, for filtering
+pub const CATEGORIES: [&str; 5] = [CPU, DISPLAY, INPUT, AUDIO, FRONTEND];
+
+///Messages kept for `recent`
+const HISTORY: usize = 1000;
+
+///Messages one call site may log per `WINDOW`, the rest are counted and dropped
+const BURST: u32 = 5;
+const WINDOW: Duration = Duration::from_secs(1);
+
+///A message kept for the log console
+#[derive(Debug, Clone)]
+pub struct Entry {
+    ///Time since logging started
+    pub time: Duration,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+///Module and line of a log macro
+type CallSite = (Option<&'static str>, Option<u32>);
+
+///First message a call site dropped in its current window
+#[derive(Debug, Clone, PartialEq)]
+struct Dropped {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+///How often a call site logged in the current window
+struct Limit {
+    start: Instant,
+    count: u32,
+    suppressed: u32,
+    dropped: Option<Dropped>,
+}
+
+///Messages per call site, so one flood doesn't drown out everything else
+#[derive(Default)]
+struct RateLimit {
+    sites: HashMap<CallSite, Limit>,
+}
+
+impl RateLimit {
+    /// Counts a message against its call site, returning whether to log it
+    ///
+    /// `dropped` describes the message, it's only called for the first one dropped in a window
+    fn admit(&mut self, site: CallSite, now: Instant, dropped: impl FnOnce() -> Dropped) -> bool {
+        let limit = self.sites.entry(site).or_insert(Limit {
+            start: now,
+            count: 0,
+            suppressed: 0,
+            dropped: None,
+        });
+
+        if limit.count >= BURST {
+            limit.suppressed += 1;
+            limit.dropped.get_or_insert_with(dropped);
+            return false;
+        }
+        limit.count += 1;
+        true
+    }
+
+    /// Ends the windows that are over, returning the first message each dropped and how many
+    /// it dropped in all
+    fn expire(&mut self, now: Instant) -> Vec<(CallSite, Dropped, u32)> {
+        let mut expired = Vec::new();
+        self.sites.retain(|site, limit| {
+            if now.duration_since(limit.start) < WINDOW {
+                return true;
+            }
+            if let Some(dropped) = limit.dropped.take() {
+                expired.push((*site, dropped, limit.suppressed));
+            }
+            false
+        });
+        expired
+    }
+}
+
+///Writes to stderr through env_logger and keeps recent messages, rate limited per call site
+struct Logger {
+    ///Decides what is logged, and writes it when `stderr` is set
+    env: env_logger::Logger,
+    stderr: bool,
+    start: Instant,
+    limits: Mutex<RateLimit>,
+    history: Mutex<VecDeque<Entry>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Installs the logger, warnings and up unless `RUST_LOG` or `filters` say otherwise
+///
+/// `filters` uses the `RUST_LOG` syntax, such as `cpu=debug,input=trace`. Without `stderr`
+/// messages only reach `recent`, for frontends that own the terminal.
+pub fn init(filters: Option<&str>, stderr: bool) {
+    let mut builder = Builder::new();
+    builder.filter_level(log::LevelFilter::Warn);
+    builder.parse_default_env();
+    if let Some(filters) = filters {
+        builder.parse_filters(filters);
+    }
+
+    let logger = LOGGER.get_or_init(|| Logger {
+        env: builder.build(),
+        stderr,
+        start: Instant::now(),
+        limits: Mutex::new(RateLimit::default()),
+        history: Mutex::new(VecDeque::with_capacity(HISTORY)),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(logger.env.filter());
+    }
+}
+
+/// Calls `f` with the kept messages, oldest first
+pub fn recent<T>(f: impl FnOnce(&VecDeque<Entry>) -> T) -> T {
+    match LOGGER.get() {
+        Some(logger) => f(&logger.history.lock().unwrap()),
+        None => f(&VecDeque::new()),
+    }
+}
+
+/// Reports call sites that stopped logging after messages were dropped
+///
+/// Logging does this too, frontends call it now and then so the last flood is reported even if
+/// nothing else is logged. `log::logger().flush()` reports the drops still counting as well.
+pub fn report_dropped() {
+    if let Some(logger) = LOGGER.get() {
+        logger.report_dropped(Instant::now());
+    }
+}
+
+/// Forgets the kept messages
+pub fn clear() {
+    if let Some(logger) = LOGGER.get() {
+        logger.history.lock().unwrap().clear();
+    }
+}
+
+impl Logger {
+    fn report_dropped(&self, now: Instant) {
+        let expired = self.limits.lock().unwrap().expire(now);
+        for ((module, line), dropped, count) in expired {
+            let message = match count {
+                1 => dropped.message,
+                _ => format!(
+                    "{} ({} similar messages dropped)",
+                    dropped.message,
+                    count - 1
+                ),
+            };
+            self.write(dropped.level, &dropped.target, module, line, message);
+        }
+    }
+
+    /// Writes a message that got past the filter and the rate limit
+    fn write(
+        &self,
+        level: Level,
+        target: &str,
+        module: Option<&'static str>,
+        line: Option<u32>,
+        message: String,
+    ) {
+        if self.stderr {
+            self.env.log(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(level)
+                    .target(target)
+                    .module_path_static(module)
+                    .line(line)
+                    .build(),
+            );
+        }
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(Entry {
+            time: self.start.elapsed(),
+            level,
+            target: target.to_string(),
+            message,
+        });
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.env.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.env.matches(record) {
+            return;
+        }
+
+        let now = Instant::now();
+        self.report_dropped(now);
+        let site = (record.module_path_static(), record.line());
+        let admitted = self.limits.lock().unwrap().admit(site, now, || Dropped {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        });
+        if admitted {
+            self.write(
+                record.level(),
+                record.target(),
+                site.0,
+                site.1,
+                record.args().to_string(),
+            );
+        }
+    }
+
+    /// Also reports drops from windows that haven't ended yet, for when the program is finishing
+    fn flush(&self) {
+        self.report_dropped(Instant::now() + WINDOW);
+        self.env.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SITE: CallSite = (Some("chip8_rust::chip"), Some(1));
+
+    fn message(text: &str) -> Dropped {
+        Dropped {
+            level: Level::Warn,
+            target: CPU.to_string(),
+            message: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn bursts_are_cut_off_per_call_site() {
+        let mut limits = RateLimit::default();
+        let now = Instant::now();
+
+        for n in 0..BURST + 3 {
+            let admitted = limits.admit(SITE, now, || message(&n.to_string()));
+            assert_eq!(admitted, n < BURST);
+        }
+        // Another call site has its own limit
+        assert!(limits.admit((SITE.0, Some(2)), now, || message("other")));
+    }
+
+    #[test]
+    fn drops_are_reported_once_the_window_ends() {
+        let mut limits = RateLimit::default();
+        let now = Instant::now();
+        for n in 0..BURST + 3 {
+            limits.admit(SITE, now, || message(&n.to_string()));
+        }
+
+        assert!(limits.expire(now + WINDOW / 2).is_empty());
+        assert_eq!(
+            limits.expire(now + WINDOW),
+            vec![(SITE, message(&BURST.to_string()), 3)]
+        );
+        // Reported once, and the next window starts fresh
+        assert!(limits.expire(now + WINDOW * 2).is_empty());
+        assert!(limits.admit(SITE, now + WINDOW, || message("again")));
+    }
+
+    #[test]
+    fn windows_without_drops_end_quietly() {
+        let mut limits = RateLimit::default();
+        let now = Instant::now();
+        limits.admit(SITE, now, || message("only"));
+
+        assert!(limits.expire(now + WINDOW).is_empty());
+        assert!(limits.sites.is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+use crate::chip::{Chip8, BIG_FONT, BIG_FONT_ADDR, FONT, FONT_ADDR};
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+
+///Bytes shown on each row
+const ROW_BYTES: usize = 16;
+
+///Frames a write stays highlighted for while it fades out
+const FADE_FRAMES: u64 = 90;
+
+///Screen pixels per bit in the graphics column
+const BIT_WIDTH: f32 = 2.0;
+
+const PC_COLOR: [f32; 4] = [0.15, 0.45, 0.15, 1.0];
+const I_COLOR: [f32; 4] = [0.15, 0.3, 0.6, 1.0];
+const SELECTED_COLOR: [f32; 4] = [0.6, 0.45, 0.1, 1.0];
+const MATCH_COLOR: [f32; 4] = [0.35, 0.3, 0.1, 1.0];
+const FONT_COLOR: [f32; 4] = [0.6, 0.7, 1.0, 1.0];
+const WRITE_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
+///Hex editor window over the whole of memory
+#[derive(Debug)]
+pub struct MemoryViewer {
+    pub open: bool,
+
+    ///Host frames drawn so far
+    frame: u64,
+
+    ///Frame each byte was last written by FX33, FX55 or by hand
+    written: Vec<Option<u64>>,
+
+    ///Byte being edited
+    selected: Option<usize>,
+    value_input: String,
+    focus_value: bool,
+
+    goto_input: String,
+
+    ///Hex bytes to look for, like `A2 EA`
+    search_input: String,
+    matches: Vec<usize>,
+    search_len: usize,
+    current_match: usize,
+
+    ///Row to bring into view next frame
+    scroll_to: Option<usize>,
+
+    error: Option<String>,
+}
+
+impl MemoryViewer {
+    pub fn new(size: usize) -> Self {
+        MemoryViewer {
+            open: false,
+            frame: 0,
+            written: vec![None; size],
+            selected: None,
+            value_input: String::new(),
+            focus_value: false,
+            goto_input: String::new(),
+            search_input: String::new(),
+            matches: Vec::new(),
+            search_len: 0,
+            current_match: 0,
+            scroll_to: None,
+            error: None,
+        }
+    }
+
+    /// Forgets recent writes and search results, for when a ROM is loaded
+    pub fn reset(&mut self) {
+        self.written.fill(None);
+        self.matches.clear();
+    }
+
+    /// Notes what an instruction that just ran wrote, see `Scheduler::update_with`
+    pub fn record(&mut self, chip8: &Chip8) {
+        if let Some((start, len)) = chip8.last_write {
+            for addr in start..start + len {
+                self.mark_written(addr as usize);
+            }
+        }
+    }
+
+    fn mark_written(&mut self, addr: usize) {
+        let len = self.written.len();
+        self.written[addr % len] = Some(self.frame);
+    }
+
+    /// Selects a byte and scrolls to it
+    fn goto(&mut self, addr: usize, memory: &[u8]) {
+        let addr = addr.min(memory.len() - 1);
+        self.selected = Some(addr);
+        self.value_input = format!("{:02X}", memory[addr]);
+        self.scroll_to = Some(addr / ROW_BYTES);
+    }
+
+    fn search(&mut self, memory: &[u8]) {
+        self.matches.clear();
+        self.current_match = 0;
+
+        let pattern = match parse_bytes(&self.search_input) {
+            Some(pattern) if !pattern.is_empty() => pattern,
+            _ => {
+                self.error = Some(format!("Not hex bytes: {:?}", self.search_input));
+                return;
+            }
+        };
+
+        self.search_len = pattern.len();
+        self.matches = memory
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, window)| *window == pattern.as_slice())
+            .map(|(addr, _)| addr)
+            .collect();
+
+        match self.matches.first() {
+            Some(&addr) => self.goto(addr, memory),
+            None => self.error = Some("No matches".to_string()),
+        }
+    }
+
+    /// Moves `step` matches forward or back, wrapping around
+    fn next_match(&mut self, step: isize, memory: &[u8]) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let count = self.matches.len() as isize;
+        self.current_match = (self.current_match as isize + step).rem_euclid(count) as usize;
+        self.goto(self.matches[self.current_match], memory);
+    }
+
+    fn is_match(&self, addr: usize) -> bool {
+        let index = self
+            .matches
+            .partition_point(|&start| start + self.search_len <= addr);
+        self.matches.get(index).is_some_and(|&start| start <= addr)
+    }
+
+    /// Draws the editor if it is open, writing edited bytes straight into memory
+    pub fn draw(&mut self, ui: &Ui, chip8: &mut Chip8) {
+        self.frame += 1;
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window("Memory")
+            .opened(&mut open)
+            .size([720.0, 420.0], Condition::FirstUseEver)
+            .position([620.0, 460.0], Condition::FirstUseEver)
+            .build(|| {
+                self.toolbar(ui, chip8);
+                ui.separator();
+                self.rows(ui, chip8);
+            });
+        self.open = open;
+    }
+
+    fn toolbar(&mut self, ui: &Ui, chip8: &mut Chip8) {
+        ui.set_next_item_width(60.0);
+        if ui
+            .input_text("Go to", &mut self.goto_input)
+            .chars_hexadecimal(true)
+            .enter_returns_true(true)
+            .build()
+        {
+            match usize::from_str_radix(self.goto_input.trim(), 16) {
+                Ok(addr) if addr < chip8.memory.len() => {
+                    self.error = None;
+                    self.goto(addr, &chip8.memory);
+                }
+                _ => self.error = Some(format!("No address {:?}", self.goto_input)),
+            }
+        }
+        ui.same_line();
+        if ui.button("PC") {
+            self.goto(chip8.pc as usize, &chip8.memory);
+        }
+        ui.same_line();
+        if ui.button("I") {
+            self.goto(chip8.i as usize, &chip8.memory);
+        }
+
+        ui.same_line();
+        ui.set_next_item_width(140.0);
+        if ui
+            .input_text("##search", &mut self.search_input)
+            .hint("Find bytes")
+            .enter_returns_true(true)
+            .build()
+        {
+            self.error = None;
+            self.search(&chip8.memory);
+        }
+        ui.same_line();
+        if ui.button("<") {
+            self.next_match(-1, &chip8.memory);
+        }
+        ui.same_line();
+        if ui.button(">") {
+            self.next_match(1, &chip8.memory);
+        }
+        if !self.matches.is_empty() {
+            ui.same_line();
+            ui.text(format!("{}/{}", self.current_match + 1, self.matches.len()));
+        }
+
+        if let Some(addr) = self.selected {
+            ui.text(format!("{:03X}:", addr));
+            ui.same_line();
+            ui.set_next_item_width(30.0);
+            if self.focus_value {
+                ui.set_keyboard_focus_here();
+                self.focus_value = false;
+            }
+            if ui
+                .input_text("##value", &mut self.value_input)
+                .chars_hexadecimal(true)
+                .auto_select_all(true)
+                .enter_returns_true(true)
+                .build()
+            {
+                match u8::from_str_radix(self.value_input.trim(), 16) {
+                    Ok(value) => {
+                        chip8.memory[addr] = value;
+                        self.mark_written(addr);
+                        self.error = None;
+                        // Carry on typing into the next byte
+                        self.goto(addr + 1, &chip8.memory);
+                        self.focus_value = true;
+                    }
+                    Err(_) => self.error = Some(format!("Not a byte: {:?}", self.value_input)),
+                }
+            }
+            ui.same_line();
+            ui.text_disabled("Enter writes the byte");
+        }
+
+        if let Some(error) = &self.error {
+            ui.text_colored(ERROR_COLOR, error);
+        }
+    }
+
+    fn rows(&mut self, ui: &Ui, chip8: &mut Chip8) {
+        let row_height = ui.text_line_height_with_spacing();
+        let row_count = chip8.memory.len().div_ceil(ROW_BYTES);
+
+        ui.child_window("##rows").build(|| {
+            if let Some(row) = self.scroll_to.take() {
+                let height = ui.window_size()[1];
+                ui.set_scroll_y((row as f32 * row_height - height / 3.0).max(0.0));
+            }
+
+            let draw_list = ui.get_window_draw_list();
+            let text_color = ui.style_color(StyleColor::Text);
+            let cell = ui.calc_text_size("00");
+            let line_height = ui.text_line_height();
+            let pc = chip8.pc as usize;
+            let i = chip8.i as usize;
+            let font = FONT_ADDR..FONT_ADDR + FONT.len();
+            let big_font = BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len();
+            let mut clicked = None;
+
+            let clipper = ListClipper::new(row_count as i32)
+                .items_height(row_height)
+                .begin(ui);
+            for row in clipper.iter() {
+                let start = row as usize * ROW_BYTES;
+                let end = (start + ROW_BYTES).min(chip8.memory.len());
+                let bytes = &chip8.memory[start..end];
+
+                ui.text_disabled(format!("{:03X}", start));
+
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    let addr = start + offset;
+                    ui.same_line();
+                    let pos = ui.cursor_screen_pos();
+                    let _id = ui.push_id_usize(addr);
+                    if ui.invisible_button("##byte", [cell[0], line_height]) {
+                        clicked = Some(addr);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(format!("{:03X}: {:#04X} ({})", addr, byte, byte));
+                    }
+
+                    let background = if self.selected == Some(addr) {
+                        Some(SELECTED_COLOR)
+                    } else if addr == pc || addr == pc + 1 {
+                        Some(PC_COLOR)
+                    } else if addr == i {
+                        Some(I_COLOR)
+                    } else if self.is_match(addr) {
+                        Some(MATCH_COLOR)
+                    } else {
+                        None
+                    };
+                    if let Some(color) = background {
+                        draw_list
+                            .add_rect(pos, [pos[0] + cell[0], pos[1] + line_height], color)
+                            .filled(true)
+                            .build();
+                    }
+
+                    let color = match self.written[addr] {
+                        Some(frame) if self.frame - frame < FADE_FRAMES => {
+                            let t = (self.frame - frame) as f32 / FADE_FRAMES as f32;
+                            lerp(WRITE_COLOR, text_color, t)
+                        }
+                        _ if font.contains(&addr) || big_font.contains(&addr) => FONT_COLOR,
+                        _ if byte == 0 => [text_color[0], text_color[1], text_color[2], 0.4],
+                        _ => text_color,
+                    };
+                    draw_list.add_text(pos, color, format!("{:02X}", byte));
+                }
+
+                ui.same_line();
+                let ascii: String = bytes
+                    .iter()
+                    .map(|&b| match b {
+                        0x20..=0x7E => b as char,
+                        _ => '.',
+                    })
+                    .collect();
+                ui.text(ascii);
+
+                // Each byte as a row of 8 pixels, the way DXYN would draw it
+                ui.same_line();
+                let pos = ui.cursor_screen_pos();
+                let byte_width = 8.0 * BIT_WIDTH + 3.0;
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    let x = pos[0] + offset as f32 * byte_width;
+                    for bit in 0..8 {
+                        let lit = byte & (0x80 >> bit) != 0;
+                        let color = if lit {
+                            text_color
+                        } else {
+                            [0.2, 0.2, 0.2, 1.0]
+                        };
+                        let left = x + bit as f32 * BIT_WIDTH;
+                        draw_list
+                            .add_rect(
+                                [left, pos[1] + 2.0],
+                                [left + BIT_WIDTH, pos[1] + line_height - 2.0],
+                                color,
+                            )
+                            .filled(true)
+                            .build();
+                    }
+                }
+                ui.dummy([byte_width * ROW_BYTES as f32, line_height]);
+            }
+
+            if let Some(addr) = clicked {
+                self.error = None;
+                self.goto(addr, &chip8.memory);
+                self.scroll_to = None;
+                self.focus_value = true;
+            }
+        });
+    }
+}
+
+/// Parses hex bytes such as `A2 EA`, `a2ea` or `0xA2 0xEA`
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text
+        .split_whitespace()
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn lerp(from: [f32; 4], to: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|c| from[c] + (to[c] - from[c]) * t)
+}
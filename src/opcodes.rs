@@ -0,0 +1,186 @@
+///Operand of an instruction, and where it sits in the opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    ///Register in the second nibble, VX
+    X,
+
+    ///Register in the third nibble, VY
+    Y,
+
+    ///Low byte, NN
+    Byte,
+
+    ///Low 12 bits, NNN
+    Addr,
+
+    ///Low nibble, N
+    Nibble,
+
+    ///Number in the second nibble, the plane mask of XO-CHIP's FN01
+    Plane,
+
+    ///Fixed word such as `I`, `DT` or `[I]`
+    Literal(&'static str),
+}
+
+impl Operand {
+    /// Bits of the opcode the operand fills in
+    pub fn mask(self) -> u16 {
+        match self {
+            Operand::X | Operand::Plane => 0x0F00,
+            Operand::Y => 0x00F0,
+            Operand::Byte => 0x00FF,
+            Operand::Addr => 0x0FFF,
+            Operand::Nibble => 0x000F,
+            Operand::Literal(_) => 0,
+        }
+    }
+
+    /// Value of the operand in `opcode`
+    pub fn extract(self, opcode: u16) -> u16 {
+        match self {
+            Operand::X | Operand::Plane => (opcode & 0x0F00) >> 8,
+            Operand::Y => (opcode & 0x00F0) >> 4,
+            _ => opcode & self.mask(),
+        }
+    }
+
+    /// Puts `value` in the operand's place
+    pub fn insert(self, value: u16) -> u16 {
+        match self {
+            Operand::X | Operand::Plane => (value & 0xF) << 8,
+            Operand::Y => (value & 0xF) << 4,
+            _ => value & self.mask(),
+        }
+    }
+
+    /// Largest value that fits
+    pub fn max(self) -> u16 {
+        match self {
+            Operand::Byte => 0xFF,
+            Operand::Addr => 0xFFF,
+            Operand::Literal(_) => 0,
+            _ => 0xF,
+        }
+    }
+}
+
+///An instruction in Cowgod's mnemonics
+#[derive(Debug, Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub operands: &'static [Operand],
+
+    ///Opcode with every operand zero
+    pub base: u16,
+}
+
+impl Opcode {
+    /// Bits that identify the instruction
+    pub fn mask(&self) -> u16 {
+        self.operands
+            .iter()
+            .fold(0xFFFF, |mask, o| mask & !o.mask())
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask() == self.base
+    }
+}
+
+use Operand::*;
+
+const fn op(mnemonic: &'static str, operands: &'static [Operand], base: u16) -> Opcode {
+    Opcode {
+        mnemonic,
+        operands,
+        base,
+    }
+}
+
+///Every instruction of CHIP-8, SUPER-CHIP and XO-CHIP, most specific first
+///
+///XO-CHIP's four byte `F000 NNNN` isn't here, it is written `LD I, LONG NNNN`
+pub const OPCODES: &[Opcode] = &[
+    op("CLS", &[], 0x00E0),
+    op("RET", &[], 0x00EE),
+    op("SCD", &[Nibble], 0x00C0),
+    op("SCU", &[Nibble], 0x00D0),
+    op("SCR", &[], 0x00FB),
+    op("SCL", &[], 0x00FC),
+    op("EXIT", &[], 0x00FD),
+    op("LOW", &[], 0x00FE),
+    op("HIGH", &[], 0x00FF),
+    op("SYS", &[Addr], 0x0000),
+    op("JP", &[Addr], 0x1000),
+    op("CALL", &[Addr], 0x2000),
+    op("SE", &[X, Byte], 0x3000),
+    op("SNE", &[X, Byte], 0x4000),
+    op("SE", &[X, Y], 0x5000),
+    op("SAVE", &[X, Y], 0x5002),
+    op("LOAD", &[X, Y], 0x5003),
+    op("LD", &[X, Byte], 0x6000),
+    op("ADD", &[X, Byte], 0x7000),
+    op("LD", &[X, Y], 0x8000),
+    op("OR", &[X, Y], 0x8001),
+    op("AND", &[X, Y], 0x8002),
+    op("XOR", &[X, Y], 0x8003),
+    op("ADD", &[X, Y], 0x8004),
+    op("SUB", &[X, Y], 0x8005),
+    op("SHR", &[X, Y], 0x8006),
+    op("SUBN", &[X, Y], 0x8007),
+    op("SHL", &[X, Y], 0x800E),
+    op("SNE", &[X, Y], 0x9000),
+    op("LD", &[Literal("I"), Addr], 0xA000),
+    op("JP", &[Literal("V0"), Addr], 0xB000),
+    op("RND", &[X, Byte], 0xC000),
+    op("DRW", &[X, Y, Nibble], 0xD000),
+    op("SKP", &[X], 0xE09E),
+    op("SKNP", &[X], 0xE0A1),
+    op("PLANE", &[Plane], 0xF001),
+    op("AUDIO", &[], 0xF002),
+    op("LD", &[X, Literal("DT")], 0xF007),
+    op("LD", &[X, Literal("K")], 0xF00A),
+    op("LD", &[Literal("DT"), X], 0xF015),
+    op("LD", &[Literal("ST"), X], 0xF018),
+    op("ADD", &[Literal("I"), X], 0xF01E),
+    op("LD", &[Literal("F"), X], 0xF029),
+    op("LD", &[Literal("HF"), X], 0xF030),
+    op("LD", &[Literal("B"), X], 0xF033),
+    op("PITCH", &[X], 0xF03A),
+    op("LD", &[Literal("[I]"), X], 0xF055),
+    op("LD", &[X, Literal("[I]")], 0xF065),
+    op("LD", &[Literal("R"), X], 0xF075),
+    op("LD", &[X, Literal("R")], 0xF085),
+];
+
+/// The instruction an opcode encodes, if any
+pub fn decode(opcode: u16) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|o| o.matches(opcode))
+}
+
+/// Writes out an opcode, asking `symbol` for a name for each address operand
+pub fn format(opcode: u16, symbol: impl Fn(u16) -> Option<String>) -> Option<String> {
+    let instruction = decode(opcode)?;
+
+    let operands: Vec<String> = instruction
+        .operands
+        .iter()
+        .map(|&operand| {
+            let value = operand.extract(opcode);
+            match operand {
+                X | Y => format!("V{:X}", value),
+                Byte => format!("0x{:02X}", value),
+                Addr => symbol(value).unwrap_or_else(|| format!("0x{:03X}", value)),
+                Nibble | Plane => value.to_string(),
+                Literal(word) => word.to_string(),
+            }
+        })
+        .collect();
+
+    if operands.is_empty() {
+        Some(instruction.mnemonic.to_string())
+    } else {
+        Some(format!("{} {}", instruction.mnemonic, operands.join(", ")))
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+///Colours of lit and unlit pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    #[serde(with = "hex")]
+    pub on: [u8; 3],
+
+    #[serde(with = "hex")]
+    pub off: [u8; 3],
+}
+
+impl Palette {
+    /// Builds a palette from two `#rrggbb` colours
+    pub fn from_hex(on: &str, off: &str) -> Result<Self, Error> {
+        Ok(Palette {
+            on: parse_hex(on)?,
+            off: parse_hex(off)?,
+        })
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            on: [0, 255, 0],
+            off: [0, 0, 0],
+        }
+    }
+}
+
+/// Parses a `#rrggbb` (or `rrggbb`) colour
+pub fn parse_hex(color: &str) -> Result<[u8; 3], Error> {
+    let hex = color.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| anyhow!("invalid colour {:?}, expected #rrggbb", color))?;
+
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+/// Writes a colour as `#rrggbb`
+pub fn to_hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+///Serde helpers storing colours as `#rrggbb`
+mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &[u8; 3], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 3], D::Error> {
+        let color = String::deserialize(deserializer)?;
+        super::parse_hex(&color).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,129 @@
+use crate::chip::Quirks;
+use serde::{Deserialize, Serialize};
+
+///Interpreters whose behaviour we can imitate
+///
+///Serialized as its chip-8-database id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Platform {
+    /// CHIP-8 on the COSMAC VIP
+    #[serde(rename = "originalChip8")]
+    OriginalChip8,
+
+    /// CHIP-8 the way most modern interpreters and Octo run it
+    #[serde(rename = "modernChip8")]
+    ModernChip8,
+
+    /// CHIP-48 on the HP-48
+    #[serde(rename = "chip48")]
+    Chip48,
+
+    /// SUPER-CHIP 1.1
+    #[serde(rename = "superchip")]
+    SuperChip,
+
+    /// XO-CHIP
+    #[serde(rename = "xochip")]
+    XoChip,
+}
+
+pub const PLATFORMS: [Platform; 5] = [
+    Platform::OriginalChip8,
+    Platform::ModernChip8,
+    Platform::Chip48,
+    Platform::SuperChip,
+    Platform::XoChip,
+];
+
+impl Platform {
+    /// Identifier used by the chip-8-database
+    pub fn id(self) -> &'static str {
+        match self {
+            Platform::OriginalChip8 => "originalChip8",
+            Platform::ModernChip8 => "modernChip8",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "superchip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        PLATFORMS.iter().copied().find(|p| p.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::OriginalChip8 => "CHIP-8 (COSMAC VIP)",
+            Platform::ModernChip8 => "CHIP-8 (modern)",
+            Platform::Chip48 => "CHIP-48",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        }
+    }
+
+    /// Quirks of the platform, as listed in the chip-8-database
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::OriginalChip8 => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: false,
+                jump: false,
+                vblank: true,
+                logic: true,
+                key_press: false,
+            },
+            Platform::ModernChip8 => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: false,
+                jump: false,
+                vblank: false,
+                logic: false,
+                key_press: false,
+            },
+            Platform::Chip48 => Quirks {
+                shift: true,
+                memory_increment_by_x: true,
+                memory_leave_i_unchanged: false,
+                wrap: false,
+                jump: true,
+                vblank: false,
+                logic: false,
+                key_press: false,
+            },
+            Platform::SuperChip => Quirks {
+                shift: true,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: true,
+                wrap: false,
+                jump: true,
+                vblank: false,
+                logic: false,
+                key_press: false,
+            },
+            Platform::XoChip => Quirks {
+                shift: false,
+                memory_increment_by_x: false,
+                memory_leave_i_unchanged: false,
+                wrap: true,
+                jump: false,
+                vblank: false,
+                logic: false,
+                key_press: false,
+            },
+        }
+    }
+
+    /// Instructions per frame that games for the platform usually expect
+    pub fn cycles_per_frame(self) -> u32 {
+        match self {
+            Platform::OriginalChip8 => 15,
+            Platform::ModernChip8 => 12,
+            Platform::Chip48 | Platform::SuperChip => 30,
+            Platform::XoChip => 100,
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use crate::chip::{Chip8, STACK_SIZE};
+use crate::opcodes;
+use crate::symbols::Symbols;
+use crate::ui::ERROR_COLOR;
+use imgui::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+
+///Addresses listed on the hot spots tab
+const HOT_SPOTS: usize = 64;
+
+///Instructions run in one call stack
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct StackCost {
+    ///Every instruction, draws included
+    instructions: u64,
+
+    ///DXYN instructions, including retries while waiting for vblank
+    draws: u64,
+}
+
+impl StackCost {
+    fn add(&mut self, draw: bool) {
+        self.instructions += 1;
+        if draw {
+            self.draws += 1;
+        }
+    }
+}
+
+///Totals for one subroutine, or the top level when `addr` is None
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subroutine {
+    pub addr: Option<u16>,
+    pub calls: u64,
+
+    ///Instructions run in it and in everything it calls
+    pub inclusive: u64,
+
+    ///Instructions run in it directly
+    pub exclusive: u64,
+
+    ///DXYN run in it directly
+    pub draws: u64,
+}
+
+///Counts executed instructions by address and by call stack
+#[derive(Debug)]
+pub struct Profiler {
+    pub open: bool,
+    pub recording: bool,
+
+    ///Times each address was executed
+    counts: Vec<u64>,
+
+    ///Cost of each call stack seen, by subroutine entry address, outermost first
+    stacks: HashMap<Vec<u16>, StackCost>,
+
+    ///Times each subroutine was called
+    calls: BTreeMap<u16, u64>,
+
+    ///Subroutines running now, outermost first
+    current: Vec<u16>,
+
+    instructions: u64,
+
+    export_path: String,
+    status: Option<Result<String, String>>,
+}
+
+impl Profiler {
+    pub fn new(size: usize) -> Self {
+        Profiler {
+            open: false,
+            recording: false,
+            counts: vec![0; size],
+            stacks: HashMap::new(),
+            calls: BTreeMap::new(),
+            current: Vec::new(),
+            instructions: 0,
+            export_path: "profile.folded".to_string(),
+            status: None,
+        }
+    }
+
+    /// Forgets everything recorded so far
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.stacks.clear();
+        self.calls.clear();
+        self.current.clear();
+        self.instructions = 0;
+    }
+
+    /// Starts recording, picking up the calls already in progress
+    pub fn start(&mut self, chip8: &Chip8) {
+        self.current = chip8
+            .stack()
+            .iter()
+            .map(|&call| call_target(chip8, call))
+            .collect();
+        self.recording = true;
+    }
+
+    /// Counts the instruction at `pc` that just ran, see `Scheduler::update_with`
+    pub fn record(&mut self, pc: u16, chip8: &Chip8) {
+        if !self.recording || chip8.fault.is_some() {
+            return;
+        }
+
+        let opcode = chip8.opcode;
+        let len = self.counts.len();
+        self.counts[pc as usize % len] += 1;
+        self.instructions += 1;
+
+        let draw = opcode & 0xF000 == 0xD000;
+        match self.stacks.get_mut(self.current.as_slice()) {
+            Some(cost) => cost.add(draw),
+            None => {
+                let mut cost = StackCost::default();
+                cost.add(draw);
+                self.stacks.insert(self.current.clone(), cost);
+            }
+        }
+
+        if opcode & 0xF000 == 0x2000 {
+            let target = opcode & 0x0FFF;
+            *self.calls.entry(target).or_default() += 1;
+            if self.current.len() < STACK_SIZE {
+                self.current.push(target);
+            }
+        } else if opcode == 0x00EE {
+            self.current.pop();
+        }
+    }
+
+    /// Totals for the top level and every subroutine, most instructions first
+    pub fn subroutines(&self) -> Vec<Subroutine> {
+        let mut totals: BTreeMap<Option<u16>, Subroutine> = BTreeMap::new();
+
+        for (stack, cost) in &self.stacks {
+            let innermost = stack.last().copied();
+            let entry = totals.entry(innermost).or_default();
+            entry.exclusive += cost.instructions;
+            entry.draws += cost.draws;
+
+            // Recursive calls would otherwise count twice
+            let distinct: HashSet<Option<u16>> =
+                stack.iter().map(|&addr| Some(addr)).chain([None]).collect();
+            for addr in distinct {
+                totals.entry(addr).or_default().inclusive += cost.instructions;
+            }
+        }
+
+        let mut subroutines: Vec<Subroutine> = totals
+            .into_iter()
+            .map(|(addr, mut totals)| {
+                totals.addr = addr;
+                totals.calls = addr.and_then(|a| self.calls.get(&a)).copied().unwrap_or(0);
+                totals
+            })
+            .collect();
+        subroutines.sort_by_key(|s| Reverse(s.inclusive));
+        subroutines
+    }
+
+    /// Recorded call stacks in the collapsed format flame graph tools read
+    ///
+    /// One `main;sub_2A0;sub_300 COUNT` line per stack, with DXYN split out into a `DXYN` frame
+    pub fn collapsed(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<String> = Vec::new();
+
+        for (stack, cost) in &self.stacks {
+            let path: Vec<String> = ["main".to_string()]
+                .into_iter()
+                .chain(stack.iter().map(|&addr| subroutine_name(addr, symbols)))
+                .collect();
+            let path = path.join(";");
+
+            let other = cost.instructions - cost.draws;
+            if other > 0 {
+                lines.push(format!("{} {}", path, other));
+            }
+            if cost.draws > 0 {
+                lines.push(format!("{};DXYN {}", path, cost.draws));
+            }
+        }
+
+        lines.sort();
+        let mut text = String::new();
+        for line in lines {
+            let _ = writeln!(text, "{}", line);
+        }
+        text
+    }
+
+    /// Draws the profiler if it is open
+    pub fn draw(&mut self, ui: &Ui, chip8: &Chip8, cycles_per_frame: u32) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window("Profiler")
+            .opened(&mut open)
+            .size([560.0, 420.0], Condition::FirstUseEver)
+            .position([620.0, 60.0], Condition::FirstUseEver)
+            .build(|| {
+                if self.recording {
+                    if ui.button("Stop") {
+                        self.recording = false;
+                    }
+                } else if ui.button("Record") {
+                    self.start(chip8);
+                }
+                ui.same_line();
+                if ui.button("Clear") {
+                    self.clear();
+                    if self.recording {
+                        self.start(chip8);
+                    }
+                }
+                ui.same_line();
+                let frames = self.instructions as f64 / cycles_per_frame.max(1) as f64;
+                ui.text(format!(
+                    "{} instructions, {:.0} frames at {} per frame",
+                    self.instructions, frames, cycles_per_frame
+                ));
+
+                ui.set_next_item_width(200.0);
+                ui.input_text("##export path", &mut self.export_path)
+                    .build();
+                ui.same_line();
+                if ui.button("Export collapsed stacks") {
+                    let path = &self.export_path;
+                    self.status = Some(
+                        std::fs::write(path, self.collapsed(&chip8.symbols))
+                            .map(|_| format!("Wrote {}, feed it to flamegraph.pl", path))
+                            .map_err(|e| format!("Failed to write {}: {}", path, e)),
+                    );
+                }
+                match &self.status {
+                    Some(Ok(message)) => ui.text_disabled(message),
+                    Some(Err(error)) => ui.text_colored(ERROR_COLOR, error),
+                    None => {}
+                }
+
+                let Some(_tabs) = ui.tab_bar("##profiler tabs") else {
+                    return;
+                };
+                if let Some(_tab) = ui.tab_item("Subroutines") {
+                    self.subroutine_table(ui, &chip8.symbols, frames);
+                }
+                if let Some(_tab) = ui.tab_item("Hot spots") {
+                    self.hot_spot_table(ui, chip8);
+                }
+            });
+        self.open = open;
+    }
+
+    fn subroutine_table(&self, ui: &Ui, symbols: &Symbols, frames: f64) {
+        let Some(_table) = ui.begin_table_header_with_flags(
+            "subroutines",
+            [
+                TableColumnSetup::new("Subroutine"),
+                TableColumnSetup::new("Calls"),
+                TableColumnSetup::new("Inclusive"),
+                TableColumnSetup::new("Exclusive"),
+                TableColumnSetup::new("DXYN"),
+                TableColumnSetup::new("Per frame"),
+            ],
+            TableFlags::ROW_BG | TableFlags::RESIZABLE | TableFlags::SCROLL_Y,
+        ) else {
+            return;
+        };
+
+        for subroutine in self.subroutines() {
+            ui.table_next_row();
+
+            ui.table_next_column();
+            match subroutine.addr {
+                Some(addr) => ui.text(subroutine_name(addr, symbols)),
+                None => ui.text("main"),
+            }
+
+            ui.table_next_column();
+            ui.text(subroutine.calls.to_string());
+
+            ui.table_next_column();
+            ui.text(format!(
+                "{} ({:.1}%)",
+                subroutine.inclusive,
+                percent(subroutine.inclusive, self.instructions)
+            ));
+
+            ui.table_next_column();
+            ui.text(subroutine.exclusive.to_string());
+
+            ui.table_next_column();
+            ui.text(subroutine.draws.to_string());
+
+            ui.table_next_column();
+            if frames > 0.0 {
+                ui.text(format!("{:.1}", subroutine.inclusive as f64 / frames));
+            }
+        }
+    }
+
+    fn hot_spot_table(&self, ui: &Ui, chip8: &Chip8) {
+        let mut hot: Vec<(usize, u64)> = self
+            .counts
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        hot.sort_by_key(|&(_, count)| Reverse(count));
+        hot.truncate(HOT_SPOTS);
+
+        let Some(_table) = ui.begin_table_header_with_flags(
+            "hot spots",
+            [
+                TableColumnSetup::new("Addr"),
+                TableColumnSetup::new("Instruction"),
+                TableColumnSetup::new("Count"),
+                TableColumnSetup::new("Share"),
+            ],
+            TableFlags::ROW_BG | TableFlags::RESIZABLE | TableFlags::SCROLL_Y,
+        ) else {
+            return;
+        };
+
+        for (addr, count) in hot {
+            ui.table_next_row();
+
+            ui.table_next_column();
+            match chip8.symbols.describe(addr as u16) {
+                Some(place) => ui.text(format!("{:03X} {}", addr, place)),
+                None => ui.text(format!("{:03X}", addr)),
+            }
+
+            ui.table_next_column();
+            let hi = chip8.memory[addr];
+            let lo = chip8.memory[(addr + 1) % chip8.memory.len()];
+            let symbol = |addr| chip8.symbols.label(addr).map(str::to_string);
+            let text = opcodes::format(u16::from_be_bytes([hi, lo]), symbol);
+            ui.text(text.unwrap_or_default());
+
+            ui.table_next_column();
+            ui.text(count.to_string());
+
+            ui.table_next_column();
+            ui.text(format!("{:.1}%", percent(count, self.instructions)));
+        }
+    }
+}
+
+/// Where the CALL at `call` goes
+fn call_target(chip8: &Chip8, call: u16) -> u16 {
+    let hi = chip8.memory[call as usize % chip8.memory.len()];
+    let lo = chip8.memory[(call as usize + 1) % chip8.memory.len()];
+    u16::from_be_bytes([hi, lo]) & 0x0FFF
+}
+
+/// Label of a subroutine, or `sub_XXX` like the disassembler calls it
+fn subroutine_name(addr: u16, symbols: &Symbols) -> String {
+    symbols
+        .label(addr)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("sub_{:03X}", addr))
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
@@ -0,0 +1,361 @@
+use crate::analyzer;
+use crate::chip::Quirks;
+use crate::palette::{parse_hex, Palette};
+use crate::platform::Platform;
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+///Database bundled with the emulator, covering the ROMs in `roms/`
+const BUNDLED: &str = include_str!("../data/programs.json");
+
+/// SHA-1 of a ROM as lowercase hex, the key of the database
+pub fn rom_hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+///One entry of `programs.json` in the chip-8-database
+#[derive(Debug, Clone, Deserialize)]
+pub struct Program {
+    pub title: String,
+
+    #[serde(default)]
+    pub authors: Vec<String>,
+
+    pub description: Option<String>,
+    pub release: Option<String>,
+
+    ///Known dumps of the program, by SHA-1
+    #[serde(default)]
+    pub roms: HashMap<String, RomInfo>,
+}
+
+///Metadata of a single ROM file of a program
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomInfo {
+    pub file: Option<String>,
+
+    ///Platforms the ROM runs on, best first
+    #[serde(default)]
+    pub platforms: Vec<String>,
+
+    ///Platforms the ROM runs on with extra quirks
+    #[serde(default)]
+    pub quirky_platforms: HashMap<String, QuirkSet>,
+
+    ///Instructions per frame
+    pub tickrate: Option<u32>,
+
+    ///Host controls (up, down, left, right, a, b) mapped to CHIP-8 keys
+    #[serde(default)]
+    pub keys: HashMap<String, u8>,
+
+    pub colors: Option<Colors>,
+}
+
+///Quirks overriding those of a platform, as named by the database
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuirkSet {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shift: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_increment_by_x: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_leave_i_unchanged: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrap: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jump: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vblank: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_press: Option<bool>,
+}
+
+impl QuirkSet {
+    ///Names of the quirks in the database
+    pub const NAMES: [&'static str; 8] = [
+        "shift",
+        "memoryIncrementByX",
+        "memoryLeaveIUnchanged",
+        "wrap",
+        "jump",
+        "vblank",
+        "logic",
+        "keyPress",
+    ];
+
+    /// Parses a list like `shift,wrap,no-vblank`, `no-` turns a quirk off
+    pub fn parse(list: &str) -> Result<Self, Error> {
+        let mut set = QuirkSet::default();
+
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let (name, value) = match name.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (name, true),
+            };
+
+            let quirk = match name.to_ascii_lowercase().as_str() {
+                "shift" => &mut set.shift,
+                "memoryincrementbyx" => &mut set.memory_increment_by_x,
+                "memoryleaveiunchanged" => &mut set.memory_leave_i_unchanged,
+                "wrap" => &mut set.wrap,
+                "jump" => &mut set.jump,
+                "vblank" => &mut set.vblank,
+                "logic" => &mut set.logic,
+                "keypress" => &mut set.key_press,
+                _ => {
+                    return Err(anyhow!(
+                        "unknown quirk {:?}, expected one of {}",
+                        name,
+                        QuirkSet::NAMES.join(", ")
+                    ))
+                }
+            };
+            *quirk = Some(value);
+        }
+
+        Ok(set)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == QuirkSet::default()
+    }
+
+    pub fn apply(&self, quirks: &mut Quirks) {
+        let fields = [
+            (self.shift, &mut quirks.shift),
+            (
+                self.memory_increment_by_x,
+                &mut quirks.memory_increment_by_x,
+            ),
+            (
+                self.memory_leave_i_unchanged,
+                &mut quirks.memory_leave_i_unchanged,
+            ),
+            (self.wrap, &mut quirks.wrap),
+            (self.jump, &mut quirks.jump),
+            (self.vblank, &mut quirks.vblank),
+            (self.logic, &mut quirks.logic),
+            (self.key_press, &mut quirks.key_press),
+        ];
+
+        for (value, quirk) in fields {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+///Colours of a ROM as `#rrggbb` strings
+#[derive(Debug, Clone, Deserialize)]
+pub struct Colors {
+    ///Colour of each pixel value, unlit first
+    #[serde(default)]
+    pub pixels: Vec<String>,
+    pub buzzer: Option<String>,
+    pub silence: Option<String>,
+}
+
+///ROM metadata in the chip-8-database format
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    programs: Vec<Program>,
+
+    ///SHA-1 to index in `programs`
+    hashes: HashMap<String, usize>,
+}
+
+impl RomDatabase {
+    /// Parses a `programs.json` from the chip-8-database
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let programs: Vec<Program> = serde_json::from_str(json)?;
+        let mut database = RomDatabase::default();
+        database.add(programs);
+        Ok(database)
+    }
+
+    /// Loads a `programs.json` from disk
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        RomDatabase::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Adds programs, replacing any already known under the same hash
+    pub fn add(&mut self, programs: Vec<Program>) {
+        for program in programs {
+            let index = self.programs.len();
+            for hash in program.roms.keys() {
+                self.hashes.insert(hash.to_ascii_lowercase(), index);
+            }
+            self.programs.push(program);
+        }
+    }
+
+    /// Merges another database into this one, its entries win
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.add(other.programs);
+    }
+
+    /// Program and ROM entry for a SHA-1
+    pub fn lookup(&self, sha1: &str) -> Option<(&Program, &RomInfo)> {
+        let program = &self.programs[*self.hashes.get(sha1)?];
+        let rom = program
+            .roms
+            .iter()
+            .find(|(hash, _)| hash.eq_ignore_ascii_case(sha1))
+            .map(|(_, rom)| rom)?;
+        Some((program, rom))
+    }
+
+    /// How to run a ROM: from the database if we know it, guessed from its contents otherwise
+    pub fn profile(&self, rom: &[u8]) -> RomProfile {
+        let sha1 = rom_hash(rom);
+
+        let Some((program, info)) = self.lookup(&sha1) else {
+            let analysis = analyzer::analyze(rom);
+            let platform = analysis.platform();
+            return RomProfile {
+                sha1,
+                title: None,
+                authors: Vec::new(),
+                platform,
+                cycles_per_frame: platform.cycles_per_frame(),
+                quirks: analysis.quirks(),
+                palette: None,
+                keys: Vec::new(),
+                known: false,
+                notes: analysis.notes(),
+            };
+        };
+
+        // First platform we know how to imitate
+        let known_platform = info
+            .platforms
+            .iter()
+            .chain(info.quirky_platforms.keys())
+            .find_map(|id| Platform::from_id(id));
+
+        let (platform, mut quirks, notes) = match known_platform {
+            Some(platform) => (platform, platform.quirks(), Vec::new()),
+            None => {
+                let analysis = analyzer::analyze(rom);
+                (analysis.platform(), analysis.quirks(), analysis.notes())
+            }
+        };
+
+        if let Some(extra) = info.quirky_platforms.get(platform.id()) {
+            extra.apply(&mut quirks);
+        }
+
+        let palette = info
+            .colors
+            .as_ref()
+            .filter(|colors| colors.pixels.len() >= 2)
+            .and_then(|colors| {
+                Some(Palette {
+                    off: parse_hex(&colors.pixels[0]).ok()?,
+                    on: parse_hex(&colors.pixels[1]).ok()?,
+                })
+            });
+
+        let mut keys: Vec<(String, u8)> = info
+            .keys
+            .iter()
+            .filter(|(_, key)| **key < 16)
+            .map(|(name, key)| (name.clone(), *key))
+            .collect();
+        keys.sort();
+
+        RomProfile {
+            sha1,
+            title: Some(program.title.clone()),
+            authors: program.authors.clone(),
+            platform,
+            cycles_per_frame: info.tickrate.unwrap_or_else(|| platform.cycles_per_frame()),
+            quirks,
+            palette,
+            keys,
+            known: true,
+            notes,
+        }
+    }
+}
+
+/// Database bundled with the emulator
+pub fn bundled() -> &'static RomDatabase {
+    static DATABASE: OnceLock<RomDatabase> = OnceLock::new();
+    DATABASE.get_or_init(|| RomDatabase::from_json(BUNDLED).expect("bundled ROM database is valid"))
+}
+
+///Everything needed to run a particular ROM well
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomProfile {
+    pub sha1: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub platform: Platform,
+    pub cycles_per_frame: u32,
+    pub quirks: Quirks,
+
+    ///Colours the ROM was designed for, if it cares
+    pub palette: Option<Palette>,
+
+    ///Host controls (up, down, left, right, a, b) and the CHIP-8 key each presses
+    pub keys: Vec<(String, u8)>,
+
+    ///Whether this came from the database rather than a guess
+    pub known: bool,
+
+    ///Why the platform and quirks were picked, when they were guessed
+    pub notes: Vec<String>,
+}
+
+impl RomProfile {
+    /// Applies the user's settings on top of the recommended ones
+    pub fn apply(&mut self, overrides: &Overrides) {
+        if let Some(platform) = overrides.platform {
+            self.platform = platform;
+            self.quirks = platform.quirks();
+            self.cycles_per_frame = platform.cycles_per_frame();
+        }
+        if let Some(quirks) = overrides.quirks {
+            self.quirks = quirks;
+        }
+        overrides.quirk_changes.apply(&mut self.quirks);
+        if let Some(cycles_per_frame) = overrides.cycles_per_frame {
+            self.cycles_per_frame = cycles_per_frame;
+        }
+        if let Some(palette) = overrides.palette {
+            self.palette = Some(palette);
+        }
+    }
+}
+
+///Settings the user chose over what the database recommends
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Overrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cycles_per_frame: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<Quirks>,
+
+    ///Single quirks changed on top of the others
+    #[serde(skip_serializing_if = "QuirkSet::is_empty")]
+    pub quirk_changes: QuirkSet,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+}
@@ -0,0 +1,236 @@
+use crate::cheats::Patch;
+use crate::chip::Chip8;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+///Instructions executed per frame unless told otherwise (~600Hz)
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
+
+///Length of one 60Hz frame
+pub const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+///Speeds offered for slow motion, relative to real time
+pub const SPEEDS: [f32; 3] = [1.0, 0.5, 0.25];
+
+///How long fast-forward may spend emulating per host frame
+const FAST_FORWARD_BUDGET: Duration = Duration::from_millis(12);
+
+///Drives a Chip8 at a fixed number of instructions per 60Hz frame
+///
+///Every frontend goes through this so games run at the same speed everywhere
+#[derive(Debug)]
+pub struct Scheduler {
+    pub cycles_per_frame: u32,
+
+    pub paused: bool,
+
+    ///Emulated frames per host frame, below 1.0 for slow motion
+    pub speed: f32,
+
+    ///Ignore `speed` and run as many frames as fit in a host frame
+    pub fast_forward: bool,
+
+    ///Addresses to pause at when execution reaches them, before their instruction runs
+    pub breakpoints: BTreeSet<u16>,
+
+    ///Values written into memory or registers at the start of every frame, for freezing them
+    pub patches: Vec<Patch>,
+
+    ///Instructions already executed in the current frame
+    cycle: u32,
+
+    ///Fractional frames owed when running in slow motion
+    pending: f32,
+
+    next_frame: Instant,
+}
+
+impl Scheduler {
+    pub fn new(cycles_per_frame: u32) -> Self {
+        Scheduler {
+            cycles_per_frame,
+            paused: false,
+            speed: 1.0,
+            fast_forward: false,
+            breakpoints: BTreeSet::new(),
+            patches: Vec::new(),
+            cycle: 0,
+            pending: 0.0,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Runs however many frames are due for one host frame, honouring pause, speed and fast-forward
+    pub fn update(&mut self, chip8: &mut Chip8) {
+        self.update_with(chip8, |_, _| {});
+    }
+
+    /// Same as `update`, calling `on_step` with the address of every instruction after it runs
+    pub fn update_with(&mut self, chip8: &mut Chip8, mut on_step: impl FnMut(u16, &Chip8)) {
+        self.update_frames(chip8, |scheduler, chip8| {
+            scheduler.run_frame_with(chip8, &mut on_step)
+        });
+    }
+
+    /// Same as `update`, running each frame that's due with `run_frame`
+    ///
+    /// For frontends that run frames their own way, `run_frame` should pause if it stops early
+    pub fn update_frames(
+        &mut self,
+        chip8: &mut Chip8,
+        mut run_frame: impl FnMut(&mut Scheduler, &mut Chip8),
+    ) {
+        if self.paused {
+            return;
+        }
+
+        if self.fast_forward {
+            let start = Instant::now();
+            while start.elapsed() < FAST_FORWARD_BUDGET && !self.paused {
+                run_frame(self, chip8);
+            }
+            return;
+        }
+
+        self.pending += self.speed;
+        while self.pending >= 1.0 {
+            run_frame(self, chip8);
+            self.pending -= 1.0;
+
+            if self.paused {
+                self.pending = 0.0;
+                return;
+            }
+        }
+    }
+
+    /// Runs the rest of the current frame's instructions, then ticks the timers once
+    pub fn run_frame(&mut self, chip8: &mut Chip8) {
+        self.run_frame_with(chip8, |_, _| {});
+    }
+
+    /// Same as `run_frame`, calling `on_step` after every instruction
+    ///
+    /// Stops partway and pauses if it reaches a breakpoint, the rest of the frame runs once resumed.
+    /// A program that has halted or is waiting for a key skips the rest of the frame, since the
+    /// keys only change between frames.
+    pub fn run_frame_with(&mut self, chip8: &mut Chip8, mut on_step: impl FnMut(u16, &Chip8)) {
+        self.start_frame(chip8);
+        while self.cycle < self.cycles_per_frame {
+            if chip8.idle().is_some_and(|idle| idle.is_stuck()) {
+                break;
+            }
+
+            let pc = chip8.pc;
+            chip8.emulate_cycle();
+            on_step(pc, chip8);
+            self.cycle += 1;
+
+            if self.breakpoints.contains(&chip8.pc) {
+                self.paused = true;
+                return;
+            }
+        }
+
+        chip8.tick_timers();
+        self.cycle = 0;
+    }
+
+    /// Executes a single instruction, ticking the timers if it was the last one of a frame
+    pub fn step_instruction(&mut self, chip8: &mut Chip8) {
+        self.step_instruction_with(chip8, |_, _| {});
+    }
+
+    /// Same as `step_instruction`, calling `on_step` after the instruction
+    pub fn step_instruction_with(
+        &mut self,
+        chip8: &mut Chip8,
+        mut on_step: impl FnMut(u16, &Chip8),
+    ) {
+        self.start_frame(chip8);
+        let pc = chip8.pc;
+        chip8.emulate_cycle();
+        on_step(pc, chip8);
+        self.cycle += 1;
+
+        if self.cycle >= self.cycles_per_frame {
+            chip8.tick_timers();
+            self.cycle = 0;
+        }
+    }
+
+    /// Whether the next instruction is the first of a frame
+    pub fn at_frame_start(&self) -> bool {
+        self.cycle == 0
+    }
+
+    /// Applies the patches if no instruction of the current frame has run yet
+    fn start_frame(&self, chip8: &mut Chip8) {
+        if self.cycle == 0 {
+            for patch in &self.patches {
+                patch.apply(chip8);
+            }
+        }
+    }
+
+    /// Sleeps until the next frame is due
+    ///
+    /// Only needed by frontends that aren't already paced by vsync
+    pub fn wait_for_next_frame(&mut self) {
+        self.next_frame += FRAME_TIME;
+
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        } else {
+            // Running behind, don't try to catch up
+            self.next_frame = now;
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new(DEFAULT_CYCLES_PER_FRAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Idle;
+
+    /// Instructions `run_frame_with` runs for one frame of `rom`, after `warm_up` frames
+    fn frame_length(rom: &[u8], warm_up: u32) -> (u32, Option<Idle>) {
+        let mut chip8 = Chip8::new();
+        chip8.load_bytes(rom).unwrap();
+        let mut scheduler = Scheduler::new(10);
+        for _ in 0..warm_up {
+            scheduler.run_frame(&mut chip8);
+        }
+
+        let mut steps = 0;
+        scheduler.run_frame_with(&mut chip8, |_, _| steps += 1);
+        (steps, chip8.idle())
+    }
+
+    #[test]
+    fn halted_programs_skip_the_rest_of_the_frame() {
+        assert_eq!(frame_length(&[0x12, 0x00], 1), (0, Some(Idle::Halted)));
+    }
+
+    #[test]
+    fn key_waits_skip_the_rest_of_the_frame() {
+        assert_eq!(
+            frame_length(&[0xF0, 0x0A], 1),
+            (0, Some(Idle::WaitingForKey))
+        );
+    }
+
+    #[test]
+    fn delay_loops_run_to_the_end_of_the_frame() {
+        // DT = 60, then FX07, 3X00, jump back to the FX07
+        let rom = [0x60, 0x3C, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04];
+        assert_eq!(frame_length(&rom, 1), (10, Some(Idle::WaitingForTimer)));
+    }
+}
@@ -0,0 +1,511 @@
+use crate::chip::Chip8;
+use crate::scheduler::Scheduler;
+use crate::ui::ERROR_COLOR;
+use anyhow::{anyhow, Context, Error};
+use imgui::*;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, Scope, AST};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+///Lines of script output the panel keeps
+const OUTPUT_LINES: usize = 200;
+
+///Text a script put over the display, at CHIP-8 pixel coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayText {
+    pub x: i64,
+    pub y: i64,
+    pub text: String,
+}
+
+///What the functions a script calls work on
+struct State {
+    ///The machine, swapped in from the frontend while the script runs
+    chip8: Chip8,
+    scheduler: Scheduler,
+
+    ///Callbacks for when PC reaches an address, before the instruction there runs
+    pc_hooks: BTreeMap<u16, Vec<FnPtr>>,
+
+    ///Callbacks for the end of every frame
+    frame_hooks: Vec<FnPtr>,
+
+    ///Frames finished since the script was loaded
+    frames: u64,
+
+    ///Keys the script holds down
+    keys: [bool; 16],
+
+    overlay: Vec<OverlayText>,
+
+    ///Printed lines not yet collected by `Script::take_output`
+    output: Vec<String>,
+
+    ///Set by `stop()`, a windowless run ends after the current frame
+    stopped: bool,
+}
+
+///A Rhai script driving the emulator
+///
+///Scripts get these functions, numbers are plain integers:
+///
+///- `peek(addr)`, `poke(addr, value)` read and write memory
+///- `v(x)`, `set_v(x, value)`, `pc()`, `set_pc(addr)`, `i()`, `set_i(addr)`, `delay_timer()`,
+///  `set_delay_timer(value)`, `sound_timer()` and `set_sound_timer(value)` for the registers
+///- `press(key)`, `release(key)`, `is_key_down(key)` for the keypad
+///- `step()` runs one instruction, `frame()` runs the rest of the frame, `frame_count()`
+///- `on_pc(addr, callback)` and `on_frame(callback)` register callbacks, which take no arguments
+///- `text(x, y, string)` and `clear_text()` for text over the display
+///- `stop()` ends a windowless run
+pub struct Script {
+    pub path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Rc<RefCell<State>>,
+}
+
+impl Script {
+    /// Compiles the script at `path` and runs its top level, which usually registers callbacks
+    pub fn load(path: &Path, chip8: &mut Chip8, scheduler: &mut Scheduler) -> Result<Self, Error> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let state = Rc::new(RefCell::new(State {
+            chip8: Chip8::new(),
+            scheduler: Scheduler::default(),
+            pc_hooks: BTreeMap::new(),
+            frame_hooks: Vec::new(),
+            frames: 0,
+            keys: [false; 16],
+            overlay: Vec::new(),
+            output: Vec::new(),
+            stopped: false,
+        }));
+
+        let mut engine = Engine::new();
+        register_api(&mut engine, &state);
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+        let mut script = Script {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+            scope: Scope::new(),
+            state,
+        };
+        script
+            .lend(chip8, scheduler, |script| {
+                script
+                    .engine
+                    .run_ast_with_scope(&mut script.scope, &script.ast)
+            })
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+        Ok(script)
+    }
+
+    /// Runs the rest of the current frame like `Scheduler::run_frame_with`, calling the script's callbacks
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        scheduler: &mut Scheduler,
+        on_step: impl FnMut(u16, &Chip8),
+    ) -> Result<(), Error> {
+        let result = self.lend(chip8, scheduler, |script| {
+            let (engine, ast) = (&script.engine, &script.ast);
+            run(&script.state, false, on_step, |hook| {
+                hook.call::<Dynamic>(engine, ast, ()).map(drop)
+            })
+        });
+        result.map_err(|e| anyhow!("{}: {}", self.path.display(), e))
+    }
+
+    /// Swaps the frontend's machine in while `f` runs
+    fn lend<T>(
+        &mut self,
+        chip8: &mut Chip8,
+        scheduler: &mut Scheduler,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let swap = |script: &mut Self, chip8: &mut Chip8, scheduler: &mut Scheduler| {
+            let mut state = script.state.borrow_mut();
+            std::mem::swap(&mut state.chip8, chip8);
+            std::mem::swap(&mut state.scheduler, scheduler);
+        };
+
+        swap(self, chip8, scheduler);
+        let result = f(self);
+        swap(self, chip8, scheduler);
+        result
+    }
+
+    /// Keys the script holds down, on top of the keyboard
+    pub fn keys(&self) -> [bool; 16] {
+        self.state.borrow().keys
+    }
+
+    pub fn overlay(&self) -> Vec<OverlayText> {
+        self.state.borrow().overlay.clone()
+    }
+
+    /// Lines printed since last time
+    pub fn take_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.state.borrow_mut().output)
+    }
+
+    /// Whether the script asked for a windowless run to end
+    pub fn stopped(&self) -> bool {
+        self.state.borrow().stopped
+    }
+}
+
+/// Runs instructions until the frame ends, or just one, calling the callbacks they reach
+///
+/// Stops early and pauses at breakpoints, like `Scheduler::run_frame_with`
+fn run(
+    state: &Rc<RefCell<State>>,
+    one_instruction: bool,
+    mut on_step: impl FnMut(u16, &Chip8),
+    mut call: impl FnMut(&FnPtr) -> ScriptResult<()>,
+) -> ScriptResult<()> {
+    loop {
+        // Nothing stays borrowed while callbacks run, they use the state too
+        let (hooks, at_breakpoint, frame_done) = {
+            let mut state = state.borrow_mut();
+            let State {
+                chip8,
+                scheduler,
+                pc_hooks,
+                ..
+            } = &mut *state;
+
+            scheduler.step_instruction_with(chip8, &mut on_step);
+            let at_breakpoint = scheduler.breakpoints.contains(&chip8.pc);
+            if at_breakpoint {
+                scheduler.paused = true;
+            }
+            let hooks = pc_hooks.get(&chip8.pc).cloned().unwrap_or_default();
+            (hooks, at_breakpoint, scheduler.at_frame_start())
+        };
+
+        for hook in &hooks {
+            call(hook)?;
+        }
+
+        if frame_done {
+            let hooks = {
+                let mut state = state.borrow_mut();
+                state.frames += 1;
+                state.frame_hooks.clone()
+            };
+            for hook in &hooks {
+                call(hook)?;
+            }
+            return Ok(());
+        }
+
+        if at_breakpoint || one_instruction {
+            return Ok(());
+        }
+    }
+}
+
+fn address(value: i64) -> ScriptResult<u16> {
+    match u16::try_from(value) {
+        Ok(addr) if addr <= 0xFFF => Ok(addr),
+        _ => Err(format!("address {} is outside memory", value).into()),
+    }
+}
+
+fn byte(value: i64) -> ScriptResult<u8> {
+    u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", value).into())
+}
+
+/// Checks a V register or key number
+fn nibble(value: i64) -> ScriptResult<usize> {
+    match usize::try_from(value) {
+        Ok(n) if n < 16 => Ok(n),
+        _ => Err(format!("{} isn't 0 to 15", value).into()),
+    }
+}
+
+fn register_api(engine: &mut Engine, state: &Rc<RefCell<State>>) {
+    let s = state.clone();
+    engine.on_print(move |text| s.borrow_mut().output.push(text.to_string()));
+
+    let s = state.clone();
+    engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+        Ok(s.borrow().chip8.memory[address(addr)? as usize] as i64)
+    });
+    let s = state.clone();
+    engine.register_fn("poke", move |addr: i64, value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.memory[address(addr)? as usize] = byte(value)?;
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("v", move |x: i64| -> ScriptResult<i64> {
+        Ok(s.borrow().chip8.v[nibble(x)?] as i64)
+    });
+    let s = state.clone();
+    engine.register_fn("set_v", move |x: i64, value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.v[nibble(x)?] = byte(value)?;
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("pc", move || s.borrow().chip8.pc as i64);
+    let s = state.clone();
+    engine.register_fn("set_pc", move |addr: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.pc = address(addr)?;
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("i", move || s.borrow().chip8.i as i64);
+    let s = state.clone();
+    engine.register_fn("set_i", move |addr: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.i = address(addr)?;
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("delay_timer", move || s.borrow().chip8.delay_timer as i64);
+    let s = state.clone();
+    engine.register_fn("set_delay_timer", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.delay_timer = byte(value)?.into();
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("sound_timer", move || s.borrow().chip8.sound_timer as i64);
+    let s = state.clone();
+    engine.register_fn("set_sound_timer", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.sound_timer = byte(value)?.into();
+        Ok(())
+    });
+
+    for (name, pressed) in [("press", true), ("release", false)] {
+        let s = state.clone();
+        engine.register_fn(name, move |key: i64| -> ScriptResult<()> {
+            let key = nibble(key)?;
+            let mut state = s.borrow_mut();
+            state.keys[key] = pressed;
+            state.chip8.set_key(key, pressed);
+            Ok(())
+        });
+    }
+    let s = state.clone();
+    engine.register_fn("is_key_down", move |key: i64| -> ScriptResult<bool> {
+        Ok(s.borrow().chip8.is_key_down(nibble(key)?))
+    });
+
+    for (name, one_instruction) in [("step", true), ("frame", false)] {
+        let s = state.clone();
+        engine.register_fn(
+            name,
+            move |context: NativeCallContext| -> ScriptResult<()> {
+                run(
+                    &s,
+                    one_instruction,
+                    |_, _| {},
+                    |hook| hook.call_within_context::<Dynamic>(&context, ()).map(drop),
+                )
+            },
+        );
+    }
+    let s = state.clone();
+    engine.register_fn("frame_count", move || s.borrow().frames as i64);
+
+    let s = state.clone();
+    engine.register_fn(
+        "on_pc",
+        move |addr: i64, callback: FnPtr| -> ScriptResult<()> {
+            let addr = address(addr)?;
+            s.borrow_mut()
+                .pc_hooks
+                .entry(addr)
+                .or_default()
+                .push(callback);
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        s.borrow_mut().frame_hooks.push(callback);
+    });
+
+    let s = state.clone();
+    engine.register_fn("text", move |x: i64, y: i64, text: &str| {
+        s.borrow_mut().overlay.push(OverlayText {
+            x,
+            y,
+            text: text.to_string(),
+        });
+    });
+    let s = state.clone();
+    engine.register_fn("clear_text", move || s.borrow_mut().overlay.clear());
+
+    let s = state.clone();
+    engine.register_fn("stop", move || s.borrow_mut().stopped = true);
+}
+
+///Window for loading a script into the GUI and reading what it prints
+pub struct ScriptPanel {
+    pub open: bool,
+    pub script: Option<Script>,
+    path_input: String,
+    output: Vec<String>,
+    error: Option<String>,
+}
+
+impl ScriptPanel {
+    pub fn new() -> Self {
+        ScriptPanel {
+            open: false,
+            script: None,
+            path_input: String::new(),
+            output: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Loads a script, replacing the one running
+    pub fn load(&mut self, path: &Path, chip8: &mut Chip8, scheduler: &mut Scheduler) {
+        self.script = None;
+        self.output.clear();
+        self.path_input = path.display().to_string();
+
+        match Script::load(path, chip8, scheduler) {
+            Ok(script) => {
+                self.script = Some(script);
+                self.error = None;
+                self.collect_output();
+            }
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    fn collect_output(&mut self) {
+        if let Some(script) = &mut self.script {
+            self.output.extend(script.take_output());
+            let excess = self.output.len().saturating_sub(OUTPUT_LINES);
+            self.output.drain(..excess);
+        }
+    }
+
+    /// Same as `Scheduler::run_frame_with`, through the script if one is loaded
+    ///
+    /// A script that fails is unloaded and the emulator paused
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        scheduler: &mut Scheduler,
+        on_step: impl FnMut(u16, &Chip8),
+    ) {
+        let Some(script) = &mut self.script else {
+            scheduler.run_frame_with(chip8, on_step);
+            return;
+        };
+
+        let result = script.run_frame(chip8, scheduler, on_step);
+        self.collect_output();
+        if let Err(e) = result {
+            scheduler.paused = true;
+            self.error = Some(format!("{:#}", e));
+            self.script = None;
+        }
+    }
+
+    /// Keys the script holds down
+    pub fn keys(&self) -> [bool; 16] {
+        self.script.as_ref().map_or([false; 16], Script::keys)
+    }
+
+    /// Draws the script's text over the display, which was drawn as the last item
+    pub fn draw_overlay(&self, ui: &Ui, width: usize) {
+        let Some(script) = &self.script else {
+            return;
+        };
+
+        let origin = ui.item_rect_min();
+        let scale = ui.item_rect_size()[0] / width as f32;
+        let draw_list = ui.get_window_draw_list();
+        for text in script.overlay() {
+            let pos = [
+                origin[0] + text.x as f32 * scale,
+                origin[1] + text.y as f32 * scale,
+            ];
+            draw_list.add_text(pos, [1.0, 1.0, 0.0, 1.0], &text.text);
+        }
+    }
+
+    /// Draws the panel if it is open
+    pub fn draw(&mut self, ui: &Ui, chip8: &mut Chip8, scheduler: &mut Scheduler) {
+        if !self.open {
+            return;
+        }
+
+        let mut open = self.open;
+        ui.window("Script")
+            .opened(&mut open)
+            .size([420.0, 300.0], Condition::FirstUseEver)
+            .position([620.0, 100.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.set_next_item_width(240.0);
+                let entered = ui
+                    .input_text("##path", &mut self.path_input)
+                    .hint("Path to a .rhai script")
+                    .enter_returns_true(true)
+                    .build();
+                ui.same_line();
+                let label = if self.script.is_some() {
+                    "Reload"
+                } else {
+                    "Load"
+                };
+                if ui.button(label) || entered {
+                    let path = PathBuf::from(self.path_input.trim());
+                    self.load(&path, chip8, scheduler);
+                }
+                if self.script.is_some() {
+                    ui.same_line();
+                    if ui.button("Unload") {
+                        self.script = None;
+                    }
+                }
+
+                if let Some(error) = &self.error {
+                    ui.text_colored(ERROR_COLOR, error);
+                }
+                match &self.script {
+                    Some(script) => ui.text_disabled(format!("Running {}", script.path.display())),
+                    None => ui.text_disabled("No script loaded"),
+                }
+
+                ui.separator();
+                ui.child_window("##output").build(|| {
+                    for line in &self.output {
+                        ui.text(line);
+                    }
+                    if ui.scroll_y() >= ui.scroll_max_y() {
+                        ui.set_scroll_here_y_with_ratio(1.0);
+                    }
+                });
+            });
+        self.open = open;
+    }
+}
+
+impl Default for ScriptPanel {
+    fn default() -> Self {
+        ScriptPanel::new()
+    }
+}
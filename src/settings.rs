@@ -0,0 +1,172 @@
+use crate::keymap::DEFAULT_KEYMAP;
+use crate::palette::Palette;
+use crate::romdb::Overrides;
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+///Directory inside the user's config directory
+const APP_DIR: &str = "chip8-rust";
+
+const FILE_NAME: &str = "settings.toml";
+
+///Size and placement of the main window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    ///Inner size in logical pixels
+    pub width: f64,
+    pub height: f64,
+
+    ///Position of the top left corner, left to the window manager if unset
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+
+    pub fullscreen: bool,
+    pub vsync: bool,
+
+    ///UI font size in logical pixels
+    pub font_size: f32,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: 1024.0,
+            height: 768.0,
+            x: None,
+            y: None,
+            fullscreen: false,
+            vsync: true,
+            font_size: 13.0,
+        }
+    }
+}
+
+///Everything the frontend remembers between runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    ///Palette for ROMs that don't ask for their own colours
+    pub palette: Palette,
+
+    ///Host key for each CHIP-8 key, see `Keymap::parse`
+    pub keymap: String,
+
+    ///Emulation speed, 1.0 is full speed
+    pub speed: f32,
+
+    ///Beeper volume from 0.0 to 1.0
+    pub volume: f32,
+
+    ///Directory the ROM browser opens in
+    pub rom_dir: PathBuf,
+
+    ///Most recently loaded ROM first
+    pub recent: Vec<PathBuf>,
+
+    pub window: WindowSettings,
+
+    ///imgui window layout, in imgui's .ini format
+    pub layout: String,
+
+    ///Settings changed in the ROM menu, by SHA-1
+    pub roms: BTreeMap<String, Overrides>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            palette: Palette::default(),
+            keymap: DEFAULT_KEYMAP.to_string(),
+            speed: 1.0,
+            volume: 0.5,
+            rom_dir: PathBuf::from("roms"),
+            recent: Vec::new(),
+            window: WindowSettings::default(),
+            layout: String::new(),
+            roms: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// `settings.toml` in the user's config directory, `$XDG_CONFIG_HOME/chip8-rust` on Linux
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_DIR).join(FILE_NAME))
+    }
+
+    /// Reads settings, the defaults if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Writes settings, creating the directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+
+        let text = toml::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+    use crate::romdb::QuirkSet;
+
+    fn round_trip(settings: &Settings) -> Settings {
+        toml::from_str(&toml::to_string_pretty(settings).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn defaults_survive_a_round_trip() {
+        assert_eq!(round_trip(&Settings::default()), Settings::default());
+    }
+
+    #[test]
+    fn rom_overrides_survive_a_round_trip() {
+        let mut settings = Settings::default();
+        settings.window.x = Some(-20);
+        settings.recent.push(PathBuf::from("roms/pong.rom"));
+        settings
+            .roms
+            .insert("0123abcd".to_string(), Overrides::default());
+        settings.roms.insert(
+            "4567ef01".to_string(),
+            Overrides {
+                platform: Some(Platform::SuperChip),
+                cycles_per_frame: Some(30),
+                quirk_changes: QuirkSet {
+                    shift: Some(false),
+                    ..QuirkSet::default()
+                },
+                ..Overrides::default()
+            },
+        );
+
+        assert_eq!(round_trip(&settings), settings);
+    }
+
+    #[test]
+    fn missing_fields_are_defaults() {
+        let settings: Settings = toml::from_str("speed = 2.0\n[window]\nvsync = false").unwrap();
+        assert_eq!(settings.speed, 2.0);
+        assert!(!settings.window.vsync);
+        assert_eq!(settings.window.width, WindowSettings::default().width);
+        assert_eq!(settings.keymap, Settings::default().keymap);
+    }
+}
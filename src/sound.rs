@@ -1,3 +1,4 @@
+use anyhow::{anyhow, Error};
 use rodio::source::{SineWave, Source};
 use rodio::{Decoder, OutputStream, Sink};
 use std::time::Duration;
@@ -28,3 +29,41 @@ pub fn beep() {
     };
     */
 }
+
+///Frequency of the tone played while the sound timer runs
+const BEEP_FREQUENCY: u32 = 440;
+
+///Tone played while the sound timer is running
+pub struct Beeper {
+    _stream: OutputStream,
+    sink: Sink,
+}
+
+impl Beeper {
+    /// Opens the default audio device, silent until `update` says otherwise
+    pub fn new() -> Result<Self, Error> {
+        let (stream, handle) = OutputStream::try_default().map_err(|e| anyhow!("{}", e))?;
+        let sink = Sink::try_new(&handle).map_err(|e| anyhow!("{}", e))?;
+        sink.append(SineWave::new(BEEP_FREQUENCY).amplify(0.20));
+        sink.pause();
+
+        Ok(Beeper {
+            _stream: stream,
+            sink,
+        })
+    }
+
+    /// Volume from 0.0 to 1.0
+    pub fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume);
+    }
+
+    /// Plays the tone while `on`, pauses it otherwise
+    pub fn update(&self, on: bool) {
+        if on {
+            self.sink.play();
+        } else {
+            self.sink.pause();
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use crate::chip::{Chip8, BIG_FONT, BIG_FONT_ADDR, FONT, FONT_ADDR, START};
+use crate::palette::Palette;
+use crate::ui::ERROR_COLOR;
+use anyhow::{bail, Context, Error};
+use imgui::*;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+///Bytes in each small font glyph
+const GLYPH_BYTES: usize = 5;
+
+///Bytes in each big font glyph
+const BIG_GLYPH_BYTES: usize = 10;
+
+///Screen pixels per sprite pixel in the previews
+const PREVIEW_PIXEL: f32 = 12.0;
+const FONT_PIXEL: f32 = 4.0;
+const RIPPER_PIXEL: f32 = 3.0;
+
+const SELECTED_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+///Shape of the sprites DXYN draws
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteShape {
+    ///Rows, each one byte or two for 16 pixel wide sprites
+    pub height: usize,
+
+    ///16x16 SUPER-CHIP sprite, DXY0
+    pub wide: bool,
+}
+
+impl SpriteShape {
+    pub fn bytes(&self) -> usize {
+        if self.wide {
+            self.height * 2
+        } else {
+            self.height
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.wide {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Whether pixel `x`, `y` of a sprite stored in `bytes` is lit
+    fn lit(&self, bytes: &[u8], x: usize, y: usize) -> bool {
+        let row = if self.wide { y * 2 + x / 8 } else { y };
+        bytes
+            .get(row)
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+}
+
+///Window that shows memory as sprites, for the sprite at I, the fonts and anywhere else
+#[derive(Debug)]
+pub struct SpriteViewer {
+    pub open: bool,
+
+    ///Shape of the sprite previewed at I
+    preview: SpriteShape,
+
+    ///Take the preview's shape from the DXYN that just ran
+    follow_draw: bool,
+
+    rip_start_input: String,
+    rip_start: usize,
+    rip_shape: SpriteShape,
+    rip_count: i32,
+
+    ///Start addresses of the ripped sprites picked for export
+    selected: BTreeSet<usize>,
+
+    export_path: String,
+
+    ///Image pixels per sprite pixel in exported PNGs
+    export_scale: u32,
+
+    status: Option<Result<String, String>>,
+}
+
+impl SpriteViewer {
+    pub fn new() -> Self {
+        SpriteViewer {
+            open: false,
+            preview: SpriteShape {
+                height: 15,
+                wide: false,
+            },
+            follow_draw: true,
+            rip_start_input: "200".to_string(),
+            rip_start: START,
+            rip_shape: SpriteShape {
+                height: 8,
+                wide: false,
+            },
+            rip_count: 64,
+            selected: BTreeSet::new(),
+            export_path: "sprites".to_string(),
+            export_scale: 8,
+            status: None,
+        }
+    }
+
+    /// Draws the viewer if it is open
+    pub fn draw(&mut self, ui: &Ui, chip8: &Chip8, palette: Palette) {
+        if !self.open {
+            return;
+        }
+
+        if self.follow_draw && chip8.opcode & 0xF000 == 0xD000 {
+            let n = (chip8.opcode & 0x000F) as usize;
+            self.preview = SpriteShape {
+                height: if n == 0 { 16 } else { n },
+                wide: n == 0,
+            };
+        }
+
+        let mut open = self.open;
+        ui.window("Sprites")
+            .opened(&mut open)
+            .size([420.0, 420.0], Condition::FirstUseEver)
+            .position([200.0, 460.0], Condition::FirstUseEver)
+            .build(|| {
+                let Some(_tabs) = ui.tab_bar("##sprite tabs") else {
+                    return;
+                };
+                if let Some(_tab) = ui.tab_item("At I") {
+                    self.preview_tab(ui, chip8, palette);
+                }
+                if let Some(_tab) = ui.tab_item("Fonts") {
+                    font_tab(ui, chip8, palette);
+                }
+                if let Some(_tab) = ui.tab_item("Ripper") {
+                    self.ripper_tab(ui, chip8, palette);
+                }
+            });
+        self.open = open;
+    }
+
+    fn preview_tab(&mut self, ui: &Ui, chip8: &Chip8, palette: Palette) {
+        ui.checkbox("Follow DXYN", &mut self.follow_draw);
+        ui.disabled(self.follow_draw, || {
+            shape_controls(ui, &mut self.preview);
+        });
+
+        let start = chip8.i as usize;
+        ui.text(format!(
+            "{}x{} at I = {:#05X}",
+            self.preview.width(),
+            self.preview.height,
+            start
+        ));
+
+        let bytes = read_wrapping(&chip8.memory, start, self.preview.bytes());
+        sprite(
+            ui,
+            "##preview",
+            &bytes,
+            self.preview,
+            PREVIEW_PIXEL,
+            palette,
+            false,
+        );
+
+        ui.same_line();
+        ui.group(|| {
+            for row in bytes.chunks(if self.preview.wide { 2 } else { 1 }) {
+                let hex: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+                let bits: Vec<String> = row.iter().map(|b| format!("{:08b}", b)).collect();
+                ui.text(format!("{}  {}", hex.join(""), bits.join("")));
+            }
+        });
+    }
+
+    fn ripper_tab(&mut self, ui: &Ui, chip8: &Chip8, palette: Palette) {
+        ui.set_next_item_width(60.0);
+        if ui
+            .input_text("Start", &mut self.rip_start_input)
+            .chars_hexadecimal(true)
+            .build()
+        {
+            if let Ok(addr) = usize::from_str_radix(self.rip_start_input.trim(), 16) {
+                self.rip_start = addr % chip8.memory.len();
+            }
+        }
+        ui.same_line();
+        if ui.button("I") {
+            self.rip_start = chip8.i as usize;
+            self.rip_start_input = format!("{:03X}", self.rip_start);
+        }
+        ui.same_line();
+        ui.set_next_item_width(100.0);
+        ui.slider("Count", 1, 512, &mut self.rip_count);
+        let shape = self.rip_shape;
+        shape_controls(ui, &mut self.rip_shape);
+        // Picked addresses were laid out for the old shape
+        if self.rip_shape != shape {
+            self.selected.clear();
+        }
+        ui.text_disabled("Click sprites to pick them for export");
+
+        let stride = self.rip_shape.bytes();
+        let addrs: Vec<usize> = (0..self.rip_count as usize)
+            .map(|n| self.rip_start + n * stride)
+            .take_while(|addr| addr + stride <= chip8.memory.len())
+            .collect();
+
+        ui.child_window("##ripped")
+            .size([0.0, -ui.frame_height_with_spacing() * 3.0])
+            .border(true)
+            .build(|| {
+                let sprite_width =
+                    self.rip_shape.width() as f32 * RIPPER_PIXEL + ui.clone_style().item_spacing[0];
+                let per_row = ((ui.content_region_avail()[0] / sprite_width) as usize).max(1);
+
+                for (n, &addr) in addrs.iter().enumerate() {
+                    if n % per_row != 0 {
+                        ui.same_line();
+                    }
+                    let bytes = &chip8.memory[addr..addr + stride];
+                    let picked = self.selected.contains(&addr);
+                    let id = format!("##rip{}", addr);
+                    let clicked = sprite(
+                        ui,
+                        &id,
+                        bytes,
+                        self.rip_shape,
+                        RIPPER_PIXEL,
+                        palette,
+                        picked,
+                    );
+                    // Clicking a picked sprite unpicks it
+                    if clicked && !self.selected.remove(&addr) {
+                        self.selected.insert(addr);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(format!("{:#05X}", addr));
+                    }
+                }
+            });
+
+        ui.text(format!("{} picked", self.selected.len()));
+        ui.same_line();
+        if ui.button("All") {
+            self.selected.extend(&addrs);
+        }
+        ui.same_line();
+        if ui.button("None") {
+            self.selected.clear();
+        }
+
+        ui.set_next_item_width(160.0);
+        ui.input_text("##export path", &mut self.export_path)
+            .hint("File name")
+            .build();
+        ui.same_line();
+        ui.set_next_item_width(60.0);
+        ui.slider("Scale", 1, 16, &mut self.export_scale);
+
+        ui.disabled(self.selected.is_empty(), || {
+            if ui.button("Export Octo") {
+                let path = Path::new(&self.export_path).with_extension("8o");
+                let source = octo_sprites(&chip8.memory, &self.selected, self.rip_shape);
+                self.status = Some(
+                    std::fs::write(&path, source)
+                        .map(|_| format!("Wrote {}", path.display()))
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e)),
+                );
+            }
+            ui.same_line();
+            if ui.button("Export PNG") {
+                let path = Path::new(&self.export_path).with_extension("png");
+                let sprites: Vec<Vec<u8>> = self
+                    .selected
+                    .iter()
+                    .map(|&addr| read_wrapping(&chip8.memory, addr, stride))
+                    .collect();
+                let sprites: Vec<&[u8]> = sprites.iter().map(Vec::as_slice).collect();
+                self.status = Some(
+                    write_png(&path, &sprites, self.rip_shape, self.export_scale, palette)
+                        .map(|_| format!("Wrote {}", path.display()))
+                        .map_err(|e| format!("{:#}", e)),
+                );
+            }
+        });
+
+        match &self.status {
+            Some(Ok(message)) => ui.text_disabled(message),
+            Some(Err(error)) => ui.text_colored(ERROR_COLOR, error),
+            None => {}
+        }
+    }
+}
+
+fn font_tab(ui: &Ui, chip8: &Chip8, palette: Palette) {
+    ui.text(format!("Font at {:#05X}", FONT_ADDR));
+    let small = SpriteShape {
+        height: GLYPH_BYTES,
+        wide: false,
+    };
+    glyphs(
+        ui,
+        &chip8.memory[FONT_ADDR..FONT_ADDR + FONT.len()],
+        small,
+        palette,
+    );
+
+    ui.separator();
+    ui.text(format!("SUPER-CHIP big font at {:#05X}", BIG_FONT_ADDR));
+    let big = SpriteShape {
+        height: BIG_GLYPH_BYTES,
+        wide: false,
+    };
+    glyphs(
+        ui,
+        &chip8.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len()],
+        big,
+        palette,
+    );
+}
+
+/// Draws a font in a row of glyphs labelled with their digit
+fn glyphs(ui: &Ui, font: &[u8], shape: SpriteShape, palette: Palette) {
+    for (digit, glyph) in font.chunks(shape.bytes()).enumerate() {
+        if digit > 0 {
+            ui.same_line();
+        }
+        ui.group(|| {
+            ui.text(format!("{:X}", digit));
+            sprite(
+                ui,
+                &format!("##glyph{}", digit),
+                glyph,
+                shape,
+                FONT_PIXEL,
+                palette,
+                false,
+            );
+        });
+    }
+}
+
+fn shape_controls(ui: &Ui, shape: &mut SpriteShape) {
+    let mut height = shape.height as i32;
+    ui.set_next_item_width(100.0);
+    if ui.slider("Height", 1, 16, &mut height) {
+        shape.height = height as usize;
+    }
+    ui.same_line();
+    ui.checkbox("16 wide", &mut shape.wide);
+}
+
+/// `len` bytes from `start`, wrapping around the end of memory like DXYN does
+fn read_wrapping(memory: &[u8], start: usize, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|n| memory[(start + n) % memory.len()])
+        .collect()
+}
+
+/// Draws a sprite as a clickable image, returning whether it was clicked
+fn sprite(
+    ui: &Ui,
+    id: &str,
+    bytes: &[u8],
+    shape: SpriteShape,
+    pixel: f32,
+    palette: Palette,
+    selected: bool,
+) -> bool {
+    let on = rgba(palette.on);
+    let off = rgba(palette.off);
+    let pos = ui.cursor_screen_pos();
+    let size = [shape.width() as f32 * pixel, shape.height as f32 * pixel];
+    let clicked = ui.invisible_button(id, size);
+
+    let draw_list = ui.get_window_draw_list();
+    draw_list
+        .add_rect(pos, [pos[0] + size[0], pos[1] + size[1]], off)
+        .filled(true)
+        .build();
+    for y in 0..shape.height {
+        for x in 0..shape.width() {
+            if shape.lit(bytes, x, y) {
+                let min = [pos[0] + x as f32 * pixel, pos[1] + y as f32 * pixel];
+                draw_list
+                    .add_rect(min, [min[0] + pixel, min[1] + pixel], on)
+                    .filled(true)
+                    .build();
+            }
+        }
+    }
+    if selected {
+        draw_list
+            .add_rect(pos, [pos[0] + size[0], pos[1] + size[1]], SELECTED_COLOR)
+            .thickness(2.0)
+            .build();
+    }
+
+    clicked
+}
+
+fn rgba(color: [u8; 3]) -> [f32; 4] {
+    [
+        color[0] as f32 / 255.0,
+        color[1] as f32 / 255.0,
+        color[2] as f32 / 255.0,
+        1.0,
+    ]
+}
+
+/// Octo source for the sprites starting at each address, one `: sprite_XXX` label per sprite
+pub fn octo_sprites(memory: &[u8], addrs: &BTreeSet<usize>, shape: SpriteShape) -> String {
+    let mut source = String::new();
+
+    for &addr in addrs {
+        let bytes = read_wrapping(memory, addr, shape.bytes());
+        let bytes: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+        let _ = writeln!(source, ": sprite_{:03X}", addr);
+        let _ = writeln!(source, "\t{}", bytes.join(" "));
+    }
+
+    source
+}
+
+/// Writes sprites side by side into a PNG, one sprite pixel apart, in the palette's colours
+pub fn write_png(
+    path: &Path,
+    sprites: &[&[u8]],
+    shape: SpriteShape,
+    scale: u32,
+    palette: Palette,
+) -> Result<(), Error> {
+    if sprites.is_empty() {
+        bail!("no sprites to export");
+    }
+
+    let gap = 1;
+    let columns = sprites.len() * (shape.width() + gap) - gap;
+    let width = columns as u32 * scale;
+    let height = shape.height as u32 * scale;
+
+    let mut data = Vec::with_capacity((width * height * 3) as usize);
+    for py in 0..height {
+        for px in 0..width {
+            let column = (px / scale) as usize;
+            let (index, x) = (
+                column / (shape.width() + gap),
+                column % (shape.width() + gap),
+            );
+            let lit = x < shape.width() && shape.lit(sprites[index], x, (py / scale) as usize);
+            data.extend(if lit { palette.on } else { palette.off });
+        }
+    }
+
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
@@ -1,12 +1,14 @@
+use crate::settings::WindowSettings;
 use glium::glutin;
+use glium::glutin::dpi::{LogicalSize, PhysicalPosition};
 use glium::glutin::event::{Event, WindowEvent};
 use glium::glutin::event_loop::{ControlFlow, EventLoop};
-use glium::glutin::window::WindowBuilder;
+use glium::glutin::window::{Fullscreen, WindowBuilder};
 use glium::{Display, Surface};
-use imgui::{Context, Ui};
+use imgui::{Context, FontConfig, FontSource, Ui};
 use imgui_glium_renderer::Renderer;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::time::Instant;
 
@@ -19,21 +21,48 @@ pub struct System {
     pub font_size: f32,
 }
 
-pub fn init(title: &str) -> System {
+///Everything besides the Ui that a frame may need to draw with
+pub struct Frame<'a> {
+    pub display: &'a Display,
+    pub renderer: &'a mut Renderer,
+
+    ///Files dropped onto the window since the last frame
+    pub dropped_files: Vec<PathBuf>,
+
+    ///imgui's window layout when it changed since the last frame
+    pub layout: Option<String>,
+
+    ///Inner size of the window in logical pixels
+    pub window_size: [f64; 2],
+
+    ///Position of the window on the desktop, if the platform knows
+    pub window_position: Option<[i32; 2]>,
+
+    ///The window is closing, this is the last frame
+    pub closing: bool,
+}
+
+pub fn init(title: &str, settings: &WindowSettings, layout: &str) -> System {
     let title = match Path::new(&title).file_name() {
         Some(file_name) => file_name.to_str().unwrap(),
         None => title,
     };
     let event_loop = EventLoop::new();
-    let context = glutin::ContextBuilder::new().with_vsync(true);
-    let builder = WindowBuilder::new()
+    let context = glutin::ContextBuilder::new().with_vsync(settings.vsync);
+    let mut builder = WindowBuilder::new()
         .with_title(title.to_owned())
-        .with_inner_size(glutin::dpi::LogicalSize::new(1024f64, 768f64));
+        .with_inner_size(LogicalSize::new(settings.width, settings.height))
+        .with_fullscreen(settings.fullscreen.then_some(Fullscreen::Borderless(None)));
+    if let (Some(x), Some(y)) = (settings.x, settings.y) {
+        builder = builder.with_position(PhysicalPosition::new(x, y));
+    }
     let display =
         Display::new(builder, context, &event_loop).expect("Failed to initialize display");
 
     let mut imgui = Context::create();
+    // Layout is kept in the settings file instead of imgui.ini
     imgui.set_ini_filename(None);
+    imgui.load_ini_settings(layout);
 
     let mut platform = WinitPlatform::init(&mut imgui);
     {
@@ -58,7 +87,13 @@ pub fn init(title: &str) -> System {
     // scaling factor. Meaning, 13.0 pixels should look the same size
     // on two different screens, and thus we do not need to scale this
     // value (as the scaling is handled by winit)
-    let font_size = 13.0;
+    let font_size = settings.font_size;
+    imgui.fonts().add_font(&[FontSource::DefaultFontData {
+        config: Some(FontConfig {
+            size_pixels: font_size,
+            ..FontConfig::default()
+        }),
+    }]);
     let renderer = Renderer::init(&mut imgui, &display).expect("Failed to initialize renderer");
 
     System {
@@ -72,7 +107,7 @@ pub fn init(title: &str) -> System {
 }
 
 impl System {
-    pub fn main_loop<F: FnMut(&mut bool, &mut Ui) + 'static>(self, mut run_ui: F) {
+    pub fn main_loop<F: FnMut(&mut bool, &mut Ui, &mut Frame) + 'static>(self, mut run_ui: F) {
         let System {
             event_loop,
             display,
@@ -82,6 +117,8 @@ impl System {
             ..
         } = self;
         let mut last_frame = Instant::now();
+        let mut dropped_files = Vec::new();
+        let mut closing = false;
 
         event_loop.run(move |event, _, control_flow| match event {
             Event::NewEvents(_) => {
@@ -97,11 +134,34 @@ impl System {
                 gl_window.window().request_redraw();
             }
             Event::RedrawRequested(_) => {
+                let layout = (closing || imgui.io().want_save_ini_settings).then(|| {
+                    let mut layout = String::new();
+                    imgui.save_ini_settings(&mut layout);
+                    layout
+                });
+                let (window_size, window_position) = {
+                    let gl_window = display.gl_window();
+                    let window = gl_window.window();
+                    let size: LogicalSize<f64> =
+                        window.inner_size().to_logical(window.scale_factor());
+                    let position = window.outer_position().ok().map(|p| [p.x, p.y]);
+                    ([size.width, size.height], position)
+                };
+
                 let ui = imgui.frame();
 
                 let mut run = true;
-                run_ui(&mut run, ui);
-                if !run {
+                let mut frame = Frame {
+                    display: &display,
+                    renderer: &mut renderer,
+                    dropped_files: std::mem::take(&mut dropped_files),
+                    layout,
+                    window_size,
+                    window_position,
+                    closing,
+                };
+                run_ui(&mut run, ui, &mut frame);
+                if !run || closing {
                     *control_flow = ControlFlow::Exit;
                 }
 
@@ -116,10 +176,15 @@ impl System {
                 target.finish().expect("Failed to swap buffers");
                 std::thread::sleep(Duration::from_millis(8));
             }
+            // Run one more frame so the app can save before exiting
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
-            } => *control_flow = ControlFlow::Exit,
+            } => closing = true,
+            Event::WindowEvent {
+                event: WindowEvent::DroppedFile(path),
+                ..
+            } => dropped_files.push(path),
             event => {
                 let gl_window = display.gl_window();
                 platform.handle_event(imgui.io_mut(), gl_window.window(), &event);
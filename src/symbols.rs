@@ -0,0 +1,228 @@
+use crate::asm::parse_number;
+use anyhow::{anyhow, bail, Context, Error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+///Extensions of symbol files looked for next to a ROM
+const SYMBOL_EXTENSIONS: &[&str] = &["sym", "map"];
+
+///Extensions of source files looked for next to a ROM
+const SOURCE_EXTENSIONS: &[&str] = &["8o", "asm", "s", "src"];
+
+///How far past a label an address is still described relative to it
+const MAX_OFFSET: u16 = 0x100;
+
+///Names and source lines for the addresses of a ROM
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    ///Label at each address
+    pub labels: BTreeMap<u16, String>,
+
+    ///Source line each address was assembled from, starting at 1
+    pub lines: BTreeMap<u16, usize>,
+
+    ///Addresses the program asks to stop at, Octo's `:breakpoint`
+    pub breakpoints: BTreeMap<u16, String>,
+
+    ///Lines of the source file, if it was found
+    pub source: Vec<String>,
+}
+
+impl Symbols {
+    /// Parses a symbol file, either the assembler's map or an Octo style symbol list
+    ///
+    /// Records are `label ADDR NAME`, `line ADDR NUMBER` and `breakpoint ADDR NAME`.
+    /// Bare `NAME ADDR`, `ADDR NAME` and `NAME = ADDR` lines are labels.
+    /// Comments start with `#` or `;`
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut symbols = Symbols::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let tokens: Vec<&str> = line.split_whitespace().filter(|t| *t != "=").collect();
+
+            let addr = |token: &str| match parse_number(token) {
+                Some(addr) if addr <= 0xFFFF => Ok(addr as u16),
+                _ => Err(anyhow!("line {}: invalid address {:?}", number, token)),
+            };
+
+            match tokens.as_slice() {
+                [] => {}
+                ["label", at, name] => {
+                    symbols.labels.insert(addr(at)?, name.to_string());
+                }
+                ["breakpoint", at, name] => {
+                    symbols.breakpoints.insert(addr(at)?, name.to_string());
+                }
+                ["line", at, line] => match line.parse() {
+                    Ok(line) => {
+                        symbols.lines.insert(addr(at)?, line);
+                    }
+                    Err(_) => bail!("line {}: invalid line number {:?}", number, line),
+                },
+                [first, second] => match (parse_number(first), parse_number(second)) {
+                    (Some(_), None) => {
+                        symbols.labels.insert(addr(first)?, second.to_string());
+                    }
+                    (None, Some(_)) => {
+                        symbols.labels.insert(addr(second)?, first.to_string());
+                    }
+                    _ => bail!("line {}: expected a name and an address", number),
+                },
+                _ => bail!("line {}: unrecognised record {:?}", number, line.trim()),
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// Symbols for a ROM from `name.sym` or `name.map` beside it, nothing if neither exists
+    ///
+    /// The source file, `name.8o` or `name.asm` and so on, is read too if there is one
+    pub fn for_rom(rom: &Path) -> Result<Self, Error> {
+        let Some(path) = sibling(rom, SYMBOL_EXTENSIONS) else {
+            return Ok(Symbols::default());
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut symbols =
+            Symbols::parse(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+
+        if let Some(source) = sibling(rom, SOURCE_EXTENSIONS) {
+            let text = fs::read_to_string(&source)
+                .with_context(|| format!("failed to read {}", source.display()))?;
+            symbols.source = text.lines().map(str::to_string).collect();
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.lines.is_empty() && self.breakpoints.is_empty()
+    }
+
+    /// Label at exactly `addr`
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    /// Address of a label, names are case sensitive
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(addr, _)| *addr)
+    }
+
+    /// `addr` relative to the closest label at or before it, such as `draw_ball+4`
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        let (&start, name) = self.labels.range(..=addr).next_back()?;
+        match addr - start {
+            0 => Some(name.clone()),
+            offset if offset < MAX_OFFSET => Some(format!("{}+{}", name, offset)),
+            _ => None,
+        }
+    }
+
+    /// Number and text of the source line the instruction at `addr` came from
+    pub fn source_line(&self, addr: u16) -> Option<(usize, &str)> {
+        let number = *self.lines.get(&addr)?;
+        let text = self
+            .source
+            .get(number.checked_sub(1)?)
+            .map_or("", String::as_str);
+        Some((number, text))
+    }
+}
+
+/// File next to `rom` with the same name and one of `extensions`, also trying `rom.ch8.sym` style names
+fn sibling(rom: &Path, extensions: &[&str]) -> Option<PathBuf> {
+    extensions
+        .iter()
+        .flat_map(|ext| {
+            let mut appended = rom.as_os_str().to_owned();
+            appended.push(".");
+            appended.push(ext);
+            [rom.with_extension(ext), PathBuf::from(appended)]
+        })
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn octo_symbol_lists() {
+        let symbols = Symbols::parse(
+            "# exported by Octo\n\
+             main 0x200\n\
+             0x20A draw_ball\n\
+             score = 0x300 ; data\n\
+             breakpoint 0x204 hit\n",
+        )
+        .unwrap();
+
+        assert_eq!(symbols.label(0x200), Some("main"));
+        assert_eq!(symbols.label(0x20A), Some("draw_ball"));
+        assert_eq!(symbols.address("score"), Some(0x300));
+        assert_eq!(symbols.breakpoints[&0x204], "hit");
+        assert!(symbols.lines.is_empty());
+    }
+
+    #[test]
+    fn assembler_maps() {
+        let assembly = asm::assemble("start: CLS\n\nloop: JP loop").unwrap();
+        let symbols = Symbols::parse(&assembly.map()).unwrap();
+
+        assert_eq!(symbols.address("start"), Some(0x200));
+        assert_eq!(symbols.address("loop"), Some(0x202));
+        assert_eq!(symbols.lines, BTreeMap::from([(0x200, 1), (0x202, 3)]));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        for (text, error) in [
+            (
+                "main 0x200\nlabel zz main",
+                "line 2: invalid address \"zz\"",
+            ),
+            ("line 0x200 first", "line 1: invalid line number \"first\""),
+            ("main loop", "line 1: expected a name and an address"),
+            ("0x200 0x202 0x204", "line 1: unrecognised record"),
+            ("main 0x10000", "line 1: invalid address"),
+        ] {
+            let e = Symbols::parse(text).unwrap_err().to_string();
+            assert!(e.starts_with(error), "{}", e);
+        }
+    }
+
+    #[test]
+    fn addresses_are_described_from_the_closest_label() {
+        let symbols = Symbols::parse("main 0x200\nsprites 0x300").unwrap();
+        assert_eq!(symbols.describe(0x200).as_deref(), Some("main"));
+        assert_eq!(symbols.describe(0x204).as_deref(), Some("main+4"));
+        assert_eq!(symbols.describe(0x1FE), None);
+        assert_eq!(symbols.describe(0x400), None);
+    }
+
+    #[test]
+    fn roms_find_symbols_and_source_beside_them() {
+        let dir = std::env::temp_dir().join(format!("chip8-symbols-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        assert_eq!(Symbols::for_rom(&rom).unwrap(), Symbols::default());
+
+        fs::write(dir.join("game.ch8.sym"), "line 0x200 2\n").unwrap();
+        fs::write(dir.join("game.8o"), ": main\n\tclear\n").unwrap();
+        let symbols = Symbols::for_rom(&rom).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(symbols.source_line(0x200), Some((2, "\tclear")));
+        assert_eq!(symbols.source_line(0x202), None);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::chip::{Chip8, HEIGHT, WIDTH};
+use crate::keymap::Keymap;
+use crate::scheduler::Scheduler;
+use anyhow::Error;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style::Print, terminal};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+///How long a key stays pressed after the terminal reports it
+///
+///Terminals only send key-down (and auto-repeat) events, so a key is treated as
+///released once this much time has passed without hearing from it again.
+pub const DEFAULT_KEY_HOLD: Duration = Duration::from_millis(150);
+
+///Characters used to draw the display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glyphs {
+    /// One cell per 1x2 pixels using half blocks, 64x16 cells
+    HalfBlock,
+
+    /// One cell per 2x4 pixels using braille patterns, 32x8 cells
+    Braille,
+}
+
+///Terminal frontend, for machines without a display
+pub struct Tui {
+    pub keymap: Keymap,
+    pub key_hold: Duration,
+    pub glyphs: Glyphs,
+
+    ///When each key should be released, if it is held
+    held: [Option<Instant>; 16],
+}
+
+///Puts the terminal in raw mode for as long as it lives
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self, Error> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Tui {
+    pub fn new(keymap: Keymap, key_hold: Duration, glyphs: Glyphs) -> Self {
+        Tui {
+            keymap,
+            key_hold,
+            glyphs,
+            held: [None; 16],
+        }
+    }
+
+    /// Runs the emulator in the terminal until Esc or Ctrl+C is pressed
+    pub fn run(&mut self, chip8: &mut Chip8, scheduler: &mut Scheduler) -> Result<(), Error> {
+        let _guard = TerminalGuard::enter()?;
+        let mut out = io::stdout();
+
+        execute!(out, terminal::Clear(terminal::ClearType::All))?;
+
+        loop {
+            if !self.poll_input(chip8)? {
+                return Ok(());
+            }
+
+            scheduler.run_frame(chip8);
+            self.draw(&mut out, chip8)?;
+            scheduler.wait_for_next_frame();
+        }
+    }
+
+    /// Drains pending terminal events into the keypad
+    ///
+    /// Returns false when the user asked to quit
+    fn poll_input(&mut self, chip8: &mut Chip8) -> Result<bool, Error> {
+        let now = Instant::now();
+
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                let quit = key.code == KeyCode::Esc
+                    || (key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL));
+                if quit {
+                    return Ok(false);
+                }
+
+                if let KeyCode::Char(c) = key.code {
+                    if let Some(k) = self.keymap.key_for(c) {
+                        // Some terminals do report releases, use them when we get them
+                        self.held[k] = match key.kind {
+                            KeyEventKind::Release => None,
+                            _ => Some(now + self.key_hold),
+                        };
+                    }
+                }
+            }
+        }
+
+        for (k, until) in self.held.iter_mut().enumerate() {
+            if until.is_some_and(|t| t <= now) {
+                *until = None;
+            }
+            chip8.set_key(k, until.is_some());
+        }
+
+        Ok(true)
+    }
+
+    fn draw(&self, out: &mut Stdout, chip8: &Chip8) -> Result<(), Error> {
+        let screen = match self.glyphs {
+            Glyphs::HalfBlock => half_blocks(&chip8.display),
+            Glyphs::Braille => braille(&chip8.display),
+        };
+        let registers = registers(chip8);
+
+        let width = screen[0].chars().count();
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            Print(format!("┌{}┐", "─".repeat(width)))
+        )?;
+
+        for (row, line) in screen.iter().enumerate() {
+            let regs = registers.get(row).map(String::as_str).unwrap_or("");
+            queue!(
+                out,
+                cursor::MoveTo(0, row as u16 + 1),
+                Print(format!("│{}│ {}", line, regs)),
+                terminal::Clear(terminal::ClearType::UntilNewLine)
+            )?;
+        }
+
+        let bottom = screen.len() as u16 + 1;
+        queue!(
+            out,
+            cursor::MoveTo(0, bottom),
+            Print(format!("└{}┘", "─".repeat(width))),
+            cursor::MoveTo(0, bottom + 1),
+            Print("Esc: quit")
+        )?;
+
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn pixel(display: &[u8], x: usize, y: usize) -> bool {
+    display[(y * WIDTH) + x] != 0
+}
+
+/// Two pixels stacked vertically per character
+fn half_blocks(display: &[u8]) -> Vec<String> {
+    (0..HEIGHT / 2)
+        .map(|row| {
+            (0..WIDTH)
+                .map(
+                    |x| match (pixel(display, x, row * 2), pixel(display, x, row * 2 + 1)) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+/// 2x4 pixels per character
+fn braille(display: &[u8]) -> Vec<String> {
+    // Bit for each dot of a braille cell, indexed by [y][x]
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    (0..HEIGHT / 4)
+        .map(|row| {
+            (0..WIDTH / 2)
+                .map(|col| {
+                    let mut bits = 0;
+                    for (dy, line) in DOTS.iter().enumerate() {
+                        for (dx, dot) in line.iter().enumerate() {
+                            if pixel(display, col * 2 + dx, row * 4 + dy) {
+                                bits |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + bits).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn registers(chip8: &Chip8) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC: {:#05X}  I: {:#05X}", chip8.pc, chip8.i),
+        format!(
+            "SP: {:X}  DT: {:<3} ST: {:<3}",
+            chip8.sp, chip8.delay_timer, chip8.sound_timer
+        ),
+    ];
+
+    for (row, values) in chip8.v.chunks(4).enumerate() {
+        let line: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X}: {:02X}", row * 4 + i, v))
+            .collect();
+        lines.push(line.join("  "));
+    }
+
+    if let Some(fault) = chip8.fault {
+        lines.push(fault.to_string());
+    } else if let Some(idle) = chip8.idle() {
+        lines.push(idle.to_string());
+    }
+
+    lines
+}
@@ -0,0 +1,2 @@
+///Text colour for error messages in every window
+pub const ERROR_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
@@ -0,0 +1,80 @@
+use crate::chip::Chip8;
+use raylib::prelude::*;
+
+///Keyboard and drawing for a raylib window, the frontend before the imgui one
+impl Chip8 {
+    pub fn check_keys(&mut self, rl: &mut RaylibDrawHandle) {
+        //If '1' key is pressed or down
+        self.key[0x1] =
+            (rl.is_key_pressed(KeyboardKey::KEY_ONE) || rl.is_key_down(KeyboardKey::KEY_ONE)) as u8;
+
+        //If '2' key is pressed or down
+        self.key[0x2] =
+            (rl.is_key_pressed(KeyboardKey::KEY_TWO) || rl.is_key_down(KeyboardKey::KEY_TWO)) as u8;
+
+        //If '3' key  is pressed or down
+        self.key[0x3] = (rl.is_key_pressed(KeyboardKey::KEY_THREE)
+            || rl.is_key_down(KeyboardKey::KEY_THREE)) as u8;
+
+        //If '4' key is pressed or down
+        self.key[0xC] = (rl.is_key_pressed(KeyboardKey::KEY_FOUR)
+            || rl.is_key_down(KeyboardKey::KEY_FOUR)) as u8;
+
+        //If 'Q' key is pressed or down
+        self.key[0x4] =
+            (rl.is_key_pressed(KeyboardKey::KEY_Q) || rl.is_key_down(KeyboardKey::KEY_Q)) as u8;
+
+        //If 'W' key is pressed or down
+        self.key[0x5] =
+            (rl.is_key_pressed(KeyboardKey::KEY_W) || rl.is_key_down(KeyboardKey::KEY_W)) as u8;
+
+        //If 'E' key is pressed or down
+        self.key[0x6] =
+            (rl.is_key_pressed(KeyboardKey::KEY_E) || rl.is_key_down(KeyboardKey::KEY_E)) as u8;
+
+        //If 'R' key is pressed or down
+        self.key[0xD] =
+            (rl.is_key_pressed(KeyboardKey::KEY_R) || rl.is_key_down(KeyboardKey::KEY_R)) as u8;
+
+        //If 'A' key is pressed or down
+        self.key[0x7] =
+            (rl.is_key_pressed(KeyboardKey::KEY_A) || rl.is_key_down(KeyboardKey::KEY_A)) as u8;
+
+        //If 'S' key is pressed or down
+        self.key[0x8] =
+            (rl.is_key_pressed(KeyboardKey::KEY_S) || rl.is_key_down(KeyboardKey::KEY_S)) as u8;
+
+        //If 'D' key is pressed or down
+        self.key[0x9] =
+            (rl.is_key_pressed(KeyboardKey::KEY_D) || rl.is_key_down(KeyboardKey::KEY_D)) as u8;
+
+        //If 'F' Key is pressed or down
+        self.key[0xE] =
+            (rl.is_key_pressed(KeyboardKey::KEY_F) || rl.is_key_down(KeyboardKey::KEY_F)) as u8;
+
+        self.key[0xA] =
+            (rl.is_key_pressed(KeyboardKey::KEY_Z) || rl.is_key_down(KeyboardKey::KEY_Z)) as u8;
+
+        self.key[0x0] =
+            (rl.is_key_pressed(KeyboardKey::KEY_X) || rl.is_key_down(KeyboardKey::KEY_X)) as u8;
+
+        self.key[0xB] =
+            (rl.is_key_pressed(KeyboardKey::KEY_C) || rl.is_key_down(KeyboardKey::KEY_C)) as u8;
+
+        self.key[0xF] =
+            (rl.is_key_pressed(KeyboardKey::KEY_V) || rl.is_key_down(KeyboardKey::KEY_V)) as u8;
+    }
+
+    /// Draws graphics to raylib window
+    pub fn draw_graphics(&self, display: &mut RaylibDrawHandle) {
+        for y in 0..32 {
+            for x in 0..64 {
+                if self.display[(y * 64) + x] != 0 {
+                    display.draw_rectangle((x * 10) as i32, (y * 10) as i32, 10, 10, Color::WHITE);
+                } else {
+                    display.draw_rectangle((x * 10) as i32, (y * 10) as i32, 10, 10, Color::BLACK);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Writes `rom` to a temporary file named after the test
+fn rom_file(name: &str, rom: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chip8-{}-{}.ch8", name, std::process::id()));
+    fs::write(&path, rom).unwrap();
+    path
+}
+
+fn headless(name: &str, rom: &[u8], args: &[&str]) -> Output {
+    let path = rom_file(name, rom);
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-rust"))
+        .arg("headless")
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn until_idle_stops_when_the_program_halts() {
+    let output = headless("halt", &[0x60, 0x05, 0x12, 0x02], &["--until-idle"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("frames: 1\n"), "{}", stdout);
+    assert!(stdout.contains("idle: program finished"), "{}", stdout);
+}
+
+#[test]
+fn until_idle_stops_when_the_program_waits_for_a_key() {
+    let output = headless("key", &[0xF0, 0x0A], &["--until-idle"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.contains("idle: waiting for a key"), "{}", stdout);
+}
+
+#[test]
+fn until_idle_fails_if_frames_run_out() {
+    // A delay loop is idle but not finished, DT = 255 outlasts 10 frames
+    let rom = [0x60, 0xFF, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04];
+    let output = headless("timer", &rom, &["--until-idle", "--frames", "10"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("frames: 10\n"), "{}", stdout);
+    assert!(
+        stdout.contains("idle: waiting for the delay timer"),
+        "{}",
+        stdout
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("still running after 10 frames"));
+}
+
+#[test]
+fn roms_too_large_are_usage_errors() {
+    let output = headless("big", &[0; 4000], &[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("only 3584 fit in memory"));
+}
+
+#[test]
+fn test_reports_roms_too_large_and_carries_on() {
+    let big = rom_file("test-big", &[0; 4000]);
+    let good = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("roms/test_opcode.ch8");
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-rust"))
+        .arg("test")
+        .arg(&big)
+        .arg(&good)
+        .output()
+        .unwrap();
+    fs::remove_file(big).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("only 3584 fit in memory"), "{}", stdout);
+    assert!(stdout.contains("ok   "), "{}", stdout);
+    assert!(stdout.contains("1 passed, 1 failed"), "{}", stdout);
+}
+
+#[test]
+fn headless_ignores_broken_symbols() {
+    let path = rom_file("sym", &[0x12, 0x00]);
+    let sym = path.with_extension("sym");
+    fs::write(&sym, "not a symbol record\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-rust"))
+        .args(["headless", "--frames", "1"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(path).unwrap();
+    fs::remove_file(sym).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ignoring symbols"));
+}
+
+#[test]
+fn floods_report_how_many_messages_were_dropped() {
+    // An unknown opcode in a loop, warned about every time it runs
+    let output = headless("flood", &[0x00, 0x01, 0x12, 0x00], &["--frames", "3"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("unknown opcode").count(), 6, "{}", stderr);
+    assert!(
+        stderr
+            .lines()
+            .last()
+            .unwrap()
+            .ends_with("similar messages dropped)"),
+        "{}",
+        stderr
+    );
+}